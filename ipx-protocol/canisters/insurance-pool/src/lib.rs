@@ -0,0 +1,1208 @@
+use ic_cdk::api::{canister_self, msg_caller, time};
+use ic_cdk::call::{Call, CallResult};
+use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{CandidType, Nat, Principal};
+use serde::{Deserialize, Serialize};
+
+const BPS_DENOMINATOR: u64 = 10_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum VerificationLevel {
+    Unverified,
+    Verified,    // Valid YouTube identity on the NFT registry
+    Established, // Verified with a large audience
+}
+
+// Risk inputs the vault reports when applying for cover
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RiskProfile {
+    pub creator: Principal,
+    pub campaign_id: u64,
+    pub funding_goal: u64,
+    pub verified_revenue_reports: u64,
+    pub verified_revenue_total: u64,
+}
+
+// All surcharges are in basis points of the insured amount
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PricingConfig {
+    pub base_premium_bps: u64,
+    pub unverified_surcharge_bps: u64,
+    pub verified_surcharge_bps: u64,
+    pub established_subscriber_threshold: u64,
+    pub no_revenue_history_surcharge_bps: u64,
+    pub short_revenue_history_surcharge_bps: u64,
+    pub established_revenue_reports: u64,
+    pub large_campaign_threshold: u64,
+    pub large_campaign_surcharge_bps: u64,
+    pub min_premium_bps: u64,
+    pub max_premium_bps: u64,
+    pub coverage_ratio: u8,          // Share of each backer's investment that is insured
+    pub max_coverage_percentage: u8, // Policy limit as a share of the funding goal
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PremiumQuote {
+    pub verification_level: VerificationLevel,
+    pub base_bps: u64,
+    pub verification_bps: u64,
+    pub revenue_history_bps: u64,
+    pub campaign_size_bps: u64,
+    pub premium_bps: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PolicyStatus {
+    Pending, // Opened, no premium received yet
+    Active,
+    Cancelled,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Policy {
+    pub policy_id: u64,
+    pub vault: Principal,
+    pub risk_profile: RiskProfile,
+    pub quote: PremiumQuote,
+    pub coverage_limit: u64,
+    pub coverage_ratio: u8,
+    pub premiums_paid: u64,
+    pub claims_reserved: u64,
+    pub claims_paid: u64,
+    pub status: PolicyStatus,
+    pub created_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ClaimStatus {
+    Pending,
+    Approved, // Funds reserved in the pool
+    Rejected,
+    Paid,
+    Paying, // Payout sent with an unknown outcome, retried with the same created_at_time
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Claim {
+    pub claim_id: u64,
+    pub policy_id: u64,
+    pub claimant: Principal,
+    pub trigger_id: u64,
+    pub amount: u64,
+    pub reason: String,
+    pub evidence: Vec<String>,
+    pub status: ClaimStatus,
+    pub filed_at: u64,
+    pub adjudicated_by: Option<Principal>,
+    pub adjudicated_at: Option<u64>,
+    pub notes: Option<String>,
+    pub payout_block_index: Option<u64>,
+    pub payout_created_at: Option<u64>, // Fixed on the first payout attempt so the ledger deduplicates retries
+    pub payout_error: Option<String>,
+}
+
+enum TransferOutcome {
+    Completed(u64),   // Block index of the transfer
+    Rejected(String), // The ledger refused it, nothing moved
+    Unknown(String),  // The transfer may or may not have happened
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, Default)]
+pub struct PoolStats {
+    pub reserves: u64,          // Tokens held for claims, including reserved amounts
+    pub reserved_for_claims: u64,
+    pub total_premiums: u64,
+    pub total_claims_paid: u64,
+    pub active_policies: u64,
+    pub outstanding_coverage: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+struct InsuranceConfig {
+    admin: Principal,
+    dao_canister: Option<Principal>,
+    ledger_canister: Option<Principal>,
+    nft_registry_canister: Option<Principal>,
+    adjudicators: Vec<Principal>,
+    pricing: PricingConfig,
+}
+
+// Mirrors the NFT registry's YouTubeIdentity
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct YouTubeIdentity {
+    pub channel_id: String,
+    pub channel_name: String,
+    pub verification_timestamp: u64,
+    pub valid_until: u64,
+    pub subscriber_count: u64,
+    pub view_count: u64,
+    pub video_count: u64,
+    pub creation_date: String,
+}
+
+// Subset of the vault's BackerInfo needed to size a claim
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BackerInfo {
+    pub amount_invested: u64,
+    pub refunded_amount: u64,
+}
+
+// Subset of the vault's InsuranceTriggerEvent
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InsuranceTriggerEvent {
+    pub trigger_id: u64,
+    pub recorded_at: u64,
+}
+
+// ICRC-1 ledger interface
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// ICRC-2 ledger interface
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+thread_local! {
+    static CONFIG: RefCell<Option<InsuranceConfig>> = const { RefCell::new(None) };
+    static REGISTERED_VAULTS: RefCell<Vec<Principal>> = const { RefCell::new(Vec::new()) };
+    static POLICIES: RefCell<HashMap<u64, Policy>> = RefCell::new(HashMap::new());
+    static CLAIMS: RefCell<HashMap<u64, Claim>> = RefCell::new(HashMap::new());
+    static POOL: RefCell<PoolStats> = RefCell::new(PoolStats::default());
+    static POLICY_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static CLAIM_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static PAYING_CLAIMS: RefCell<HashSet<u64>> = RefCell::new(HashSet::new());
+}
+
+#[init]
+fn init() {
+    let config = InsuranceConfig {
+        admin: msg_caller(),
+        dao_canister: None,
+        ledger_canister: None,
+        nft_registry_canister: None,
+        adjudicators: Vec::new(),
+        pricing: default_pricing(),
+    };
+    
+    CONFIG.with(|c| *c.borrow_mut() = Some(config));
+}
+
+fn default_pricing() -> PricingConfig {
+    PricingConfig {
+        base_premium_bps: 200,
+        unverified_surcharge_bps: 300,
+        verified_surcharge_bps: 100,
+        established_subscriber_threshold: 10_000,
+        no_revenue_history_surcharge_bps: 200,
+        short_revenue_history_surcharge_bps: 100,
+        established_revenue_reports: 6,
+        large_campaign_threshold: 10_000_000,
+        large_campaign_surcharge_bps: 100,
+        min_premium_bps: 100,
+        max_premium_bps: 1_000,
+        coverage_ratio: 80,
+        max_coverage_percentage: 80,
+    }
+}
+
+fn get_config() -> Result<InsuranceConfig, String> {
+    CONFIG.with(|c| c.borrow().clone())
+        .ok_or_else(|| "Insurance pool not initialized".to_string())
+}
+
+fn is_governance(config: &InsuranceConfig, caller: Principal) -> bool {
+    caller == config.admin || config.dao_canister == Some(caller)
+}
+
+fn is_adjudicator(config: &InsuranceConfig, caller: Principal) -> bool {
+    is_governance(config, caller) || config.adjudicators.contains(&caller)
+}
+
+// Inter-canister calls with the arguments and results as tuples, on ic-cdk's unbounded-wait Call API
+async fn call<A: ArgumentEncoder, R: for<'de> ArgumentDecoder<'de>>(
+    canister: Principal,
+    method: &str,
+    args: A,
+) -> CallResult<R> {
+    Ok(Call::unbounded_wait(canister, method).with_args(&args).await?.candid_tuple()?)
+}
+
+// Configuration
+
+#[update]
+fn set_canister_refs(
+    dao_canister: Option<Principal>,
+    ledger_canister: Option<Principal>,
+    nft_registry_canister: Option<Principal>,
+) -> Result<(), String> {
+    let caller = msg_caller();
+    CONFIG.with(|c| {
+        let mut config_opt = c.borrow_mut();
+        let config = config_opt.as_mut().ok_or_else(|| "Insurance pool not initialized".to_string())?;
+        if !is_governance(config, caller) {
+            return Err("Only governance can configure the insurance pool".to_string());
+        }
+        
+        config.dao_canister = dao_canister;
+        config.ledger_canister = ledger_canister;
+        config.nft_registry_canister = nft_registry_canister;
+        Ok(())
+    })
+}
+
+#[update]
+fn set_adjudicators(adjudicators: Vec<Principal>) -> Result<(), String> {
+    let caller = msg_caller();
+    CONFIG.with(|c| {
+        let mut config_opt = c.borrow_mut();
+        let config = config_opt.as_mut().ok_or_else(|| "Insurance pool not initialized".to_string())?;
+        if !is_governance(config, caller) {
+            return Err("Only governance can appoint adjudicators".to_string());
+        }
+        
+        config.adjudicators = adjudicators;
+        Ok(())
+    })
+}
+
+#[update]
+fn set_pricing_config(pricing: PricingConfig) -> Result<(), String> {
+    let caller = msg_caller();
+    if pricing.min_premium_bps > pricing.max_premium_bps || pricing.max_premium_bps > BPS_DENOMINATOR {
+        return Err("Premium bounds must satisfy min <= max <= 10000 bps".to_string());
+    }
+    if pricing.coverage_ratio == 0 || pricing.coverage_ratio > 100 || pricing.max_coverage_percentage > 100 {
+        return Err("Coverage percentages must be between 1 and 100".to_string());
+    }
+    
+    CONFIG.with(|c| {
+        let mut config_opt = c.borrow_mut();
+        let config = config_opt.as_mut().ok_or_else(|| "Insurance pool not initialized".to_string())?;
+        if !is_governance(config, caller) {
+            return Err("Only governance can change pricing".to_string());
+        }
+        
+        // Existing policies keep the premium they were quoted
+        config.pricing = pricing;
+        Ok(())
+    })
+}
+
+// Only vaults deployed by the protocol may buy cover, since claims are sized from their books
+#[update]
+fn register_vault(vault: Principal) -> Result<(), String> {
+    let config = get_config()?;
+    if !is_governance(&config, msg_caller()) {
+        return Err("Only governance can register vaults".to_string());
+    }
+    
+    REGISTERED_VAULTS.with(|v| {
+        let mut vaults = v.borrow_mut();
+        if !vaults.contains(&vault) {
+            vaults.push(vault);
+        }
+    });
+    Ok(())
+}
+
+// Pricing
+
+async fn lookup_verification_level(config: &InsuranceConfig, creator: Principal) -> VerificationLevel {
+    let registry = match config.nft_registry_canister {
+        Some(registry) => registry,
+        None => return VerificationLevel::Unverified,
+    };
+    
+    let result: CallResult<(Option<YouTubeIdentity>,)> = call(
+        registry,
+        "get_youtube_identity",
+        (creator,),
+    ).await;
+    
+    match result {
+        Ok((Some(identity),)) if identity.valid_until > time() => {
+            if identity.subscriber_count >= config.pricing.established_subscriber_threshold {
+                VerificationLevel::Established
+            } else {
+                VerificationLevel::Verified
+            }
+        }
+        // An expired identity or a failed lookup is priced as unverified
+        _ => VerificationLevel::Unverified,
+    }
+}
+
+fn price_premium(pricing: &PricingConfig, profile: &RiskProfile, verification_level: VerificationLevel) -> PremiumQuote {
+    let verification_bps = match verification_level {
+        VerificationLevel::Unverified => pricing.unverified_surcharge_bps,
+        VerificationLevel::Verified => pricing.verified_surcharge_bps,
+        VerificationLevel::Established => 0,
+    };
+    
+    let revenue_history_bps = if profile.verified_revenue_reports == 0 {
+        pricing.no_revenue_history_surcharge_bps
+    } else if profile.verified_revenue_reports < pricing.established_revenue_reports {
+        pricing.short_revenue_history_surcharge_bps
+    } else {
+        0
+    };
+    
+    let campaign_size_bps = if profile.funding_goal >= pricing.large_campaign_threshold {
+        pricing.large_campaign_surcharge_bps
+    } else {
+        0
+    };
+    
+    let premium_bps = (pricing.base_premium_bps + verification_bps + revenue_history_bps + campaign_size_bps)
+        .clamp(pricing.min_premium_bps, pricing.max_premium_bps);
+    
+    PremiumQuote {
+        verification_level,
+        base_bps: pricing.base_premium_bps,
+        verification_bps,
+        revenue_history_bps,
+        campaign_size_bps,
+        premium_bps,
+    }
+}
+
+#[update]
+async fn quote_premium(profile: RiskProfile) -> Result<PremiumQuote, String> {
+    let config = get_config()?;
+    let level = lookup_verification_level(&config, profile.creator).await;
+    Ok(price_premium(&config.pricing, &profile, level))
+}
+
+// Cover the pool actually owes on a policy: capped by the limit and by the premiums received
+fn policy_cover(policy: &Policy) -> u64 {
+    if policy.quote.premium_bps == 0 {
+        return 0;
+    }
+    let insured_principal = (policy.premiums_paid as u128 * BPS_DENOMINATOR as u128
+        / policy.quote.premium_bps as u128) as u64;
+    let earned_cover = insured_principal * policy.coverage_ratio as u64 / 100;
+    earned_cover.min(policy.coverage_limit)
+}
+
+// Policies
+
+#[update]
+async fn open_policy(profile: RiskProfile, requested_coverage: u64) -> Result<Policy, String> {
+    let vault = msg_caller();
+    let config = get_config()?;
+    
+    if !REGISTERED_VAULTS.with(|v| v.borrow().contains(&vault)) {
+        return Err("Only registered vaults can open policies".to_string());
+    }
+    if profile.funding_goal == 0 || requested_coverage == 0 {
+        return Err("Funding goal and requested coverage must be positive".to_string());
+    }
+    
+    let level = lookup_verification_level(&config, profile.creator).await;
+    let quote = price_premium(&config.pricing, &profile, level);
+    let max_coverage = profile.funding_goal * config.pricing.max_coverage_percentage as u64 / 100;
+    
+    POLICIES.with(|p| {
+        let mut policies = p.borrow_mut();
+        // Checked after the registry call so two concurrent applications cannot both succeed
+        if policies.values().any(|policy| policy.vault == vault && policy.status != PolicyStatus::Cancelled) {
+            return Err("Vault already holds a policy".to_string());
+        }
+        
+        let policy_id = POLICY_COUNTER.with(|c| {
+            let mut counter = c.borrow_mut();
+            *counter += 1;
+            *counter
+        });
+        
+        let policy = Policy {
+            policy_id,
+            vault,
+            risk_profile: profile,
+            quote,
+            coverage_limit: requested_coverage.min(max_coverage),
+            coverage_ratio: config.pricing.coverage_ratio,
+            premiums_paid: 0,
+            claims_reserved: 0,
+            claims_paid: 0,
+            status: PolicyStatus::Pending,
+            created_at: time(),
+        };
+        
+        policies.insert(policy_id, policy.clone());
+        Ok(policy)
+    })
+}
+
+// Pulls an approved premium from the policy's vault into the shared reserves
+#[update]
+async fn pay_premium(policy_id: u64, amount: u64) -> Result<u64, String> {
+    let caller = msg_caller();
+    let config = get_config()?;
+    let ledger = config.ledger_canister.ok_or_else(|| "Ledger canister not configured".to_string())?;
+    
+    if amount == 0 {
+        return Err("Premium must be positive".to_string());
+    }
+    
+    let policy = get_policy(policy_id).ok_or_else(|| "Policy not found".to_string())?;
+    if policy.vault != caller {
+        return Err("Only the insured vault can pay premiums".to_string());
+    }
+    if policy.status == PolicyStatus::Cancelled {
+        return Err("Policy is cancelled".to_string());
+    }
+    
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account { owner: caller, subaccount: None },
+        to: Account { owner: canister_self(), subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(policy_id.to_be_bytes().to_vec()),
+        created_at_time: Some(time()),
+    };
+    
+    let result: CallResult<(Result<Nat, TransferFromError>,)> = call(
+        ledger,
+        "icrc2_transfer_from",
+        (args,),
+    ).await;
+    
+    let block_index = match result {
+        Ok((Ok(block_index),)) => u64::try_from(block_index.0)
+            .map_err(|_| "Ledger block index does not fit in u64".to_string())?,
+        Ok((Err(e),)) => return Err(format!("Premium transfer failed: {:?}", e)),
+        Err(e) => return Err(format!("Failed to call ledger: {:?}", e)),
+    };
+    
+    // The tokens have arrived, so the premium is credited even if the policy changed meanwhile
+    POLICIES.with(|p| {
+        if let Some(policy) = p.borrow_mut().get_mut(&policy_id) {
+            policy.premiums_paid += amount;
+            if policy.status == PolicyStatus::Pending {
+                policy.status = PolicyStatus::Active;
+            }
+        }
+    });
+    POOL.with(|pool| {
+        let mut pool = pool.borrow_mut();
+        pool.reserves += amount;
+        pool.total_premiums += amount;
+    });
+    
+    Ok(block_index)
+}
+
+#[update]
+fn cancel_policy(policy_id: u64) -> Result<(), String> {
+    let caller = msg_caller();
+    let config = get_config()?;
+    
+    POLICIES.with(|p| {
+        let mut policies = p.borrow_mut();
+        let policy = policies.get_mut(&policy_id).ok_or_else(|| "Policy not found".to_string())?;
+        if policy.vault != caller && !is_governance(&config, caller) {
+            return Err("Only the insured vault or governance can cancel a policy".to_string());
+        }
+        if policy.claims_reserved > 0 {
+            return Err("Policy has approved claims awaiting payment".to_string());
+        }
+        
+        // Premiums are not refunded; they stay in the shared reserves
+        policy.status = PolicyStatus::Cancelled;
+        Ok(())
+    })
+}
+
+// Claims
+
+#[update]
+async fn file_claim(
+    policy_id: u64,
+    trigger_id: u64,
+    amount: u64,
+    reason: String,
+    evidence: Vec<String>,
+) -> Result<u64, String> {
+    let claimant = msg_caller();
+    
+    if amount == 0 {
+        return Err("Claim amount must be positive".to_string());
+    }
+    
+    let policy = get_policy(policy_id).ok_or_else(|| "Policy not found".to_string())?;
+    if policy.status != PolicyStatus::Active {
+        return Err("Policy is not active".to_string());
+    }
+    
+    // The vault's books are the source of truth for who backed the campaign and what went wrong
+    let triggers: CallResult<(Vec<InsuranceTriggerEvent>,)> = call(
+        policy.vault,
+        "get_insurance_triggers",
+        (),
+    ).await;
+    let triggers = triggers.map_err(|e| format!("Failed to query vault triggers: {:?}", e))?.0;
+    if !triggers.iter().any(|t| t.trigger_id == trigger_id) {
+        return Err("Trigger event not found on the insured vault".to_string());
+    }
+    
+    let backer: CallResult<(Option<BackerInfo>,)> = call(
+        policy.vault,
+        "get_backer_info",
+        (claimant,),
+    ).await;
+    let backer = backer.map_err(|e| format!("Failed to query vault backer: {:?}", e))?.0
+        .ok_or_else(|| "Caller is not a backer of the insured vault".to_string())?;
+    
+    let insured = backer.amount_invested.saturating_sub(backer.refunded_amount)
+        * policy.coverage_ratio as u64 / 100;
+    
+    CLAIMS.with(|c| {
+        let mut claims = c.borrow_mut();
+        
+        // Re-checked after the awaits so concurrent filings cannot exceed the backer's cover
+        let already_claimed: u64 = claims.values()
+            .filter(|claim| claim.policy_id == policy_id && claim.claimant == claimant)
+            .filter(|claim| claim.status != ClaimStatus::Rejected)
+            .map(|claim| claim.amount)
+            .sum();
+        if already_claimed + amount > insured {
+            return Err(format!(
+                "Claim exceeds insured amount: {} remaining of {}",
+                insured.saturating_sub(already_claimed), insured
+            ));
+        }
+        if claims.values().any(|claim| {
+            claim.policy_id == policy_id
+                && claim.claimant == claimant
+                && claim.trigger_id == trigger_id
+                && claim.status == ClaimStatus::Pending
+        }) {
+            return Err("A claim for this trigger is already pending".to_string());
+        }
+        
+        let claim_id = CLAIM_COUNTER.with(|counter| {
+            let mut counter = counter.borrow_mut();
+            *counter += 1;
+            *counter
+        });
+        
+        claims.insert(claim_id, Claim {
+            claim_id,
+            policy_id,
+            claimant,
+            trigger_id,
+            amount,
+            reason,
+            evidence,
+            status: ClaimStatus::Pending,
+            filed_at: time(),
+            adjudicated_by: None,
+            adjudicated_at: None,
+            notes: None,
+            payout_block_index: None,
+            payout_created_at: None,
+            payout_error: None,
+        });
+        
+        Ok(claim_id)
+    })
+}
+
+#[update]
+fn adjudicate_claim(claim_id: u64, approve: bool, notes: Option<String>) -> Result<ClaimStatus, String> {
+    let caller = msg_caller();
+    let config = get_config()?;
+    if !is_adjudicator(&config, caller) {
+        return Err("Only adjudicators can decide claims".to_string());
+    }
+    
+    decide_claim(claim_id, approve, notes, caller, time())
+}
+
+// Approving reserves the claim against the policy's cover and the pool's free reserves
+fn decide_claim(claim_id: u64, approve: bool, notes: Option<String>, caller: Principal, now: u64) -> Result<ClaimStatus, String> {
+    CLAIMS.with(|c| {
+        let mut claims = c.borrow_mut();
+        let claim = claims.get_mut(&claim_id).ok_or_else(|| "Claim not found".to_string())?;
+        if claim.status != ClaimStatus::Pending {
+            return Err("Claim has already been decided".to_string());
+        }
+        
+        if approve {
+            POLICIES.with(|p| {
+                let mut policies = p.borrow_mut();
+                let policy = policies.get_mut(&claim.policy_id).ok_or_else(|| "Policy not found".to_string())?;
+                
+                let cover = policy_cover(policy);
+                let committed = policy.claims_reserved + policy.claims_paid;
+                if committed + claim.amount > cover {
+                    return Err(format!(
+                        "Claim exceeds the policy's remaining cover of {}",
+                        cover.saturating_sub(committed)
+                    ));
+                }
+                
+                POOL.with(|pool| {
+                    let mut pool = pool.borrow_mut();
+                    let available = pool.reserves - pool.reserved_for_claims;
+                    if claim.amount > available {
+                        return Err(format!("Insufficient pool reserves: {} available", available));
+                    }
+                    pool.reserved_for_claims += claim.amount;
+                    Ok(())
+                })?;
+                
+                policy.claims_reserved += claim.amount;
+                Ok(())
+            })?;
+            claim.status = ClaimStatus::Approved;
+        } else {
+            claim.status = ClaimStatus::Rejected;
+        }
+        
+        claim.adjudicated_by = Some(caller);
+        claim.adjudicated_at = Some(now);
+        claim.notes = notes;
+        Ok(claim.status.clone())
+    })
+}
+
+// Pays an approved claim out of the pooled reserves. A payout with an unknown outcome stays Paying
+// and is retried by calling this again, with the same memo and created_at_time.
+#[update]
+async fn pay_claim(claim_id: u64) -> Result<u64, String> {
+    let caller = msg_caller();
+    let config = get_config()?;
+    let ledger = config.ledger_canister.ok_or_else(|| "Ledger canister not configured".to_string())?;
+    let _guard = PayoutGuard::acquire(claim_id)?;
+    
+    let (claimant, amount, created_at_time) = start_payout(&config, claim_id, caller, time())?;
+    
+    let args = TransferArg {
+        from_subaccount: None,
+        to: Account { owner: claimant, subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(claim_id.to_be_bytes().to_vec()),
+        created_at_time: Some(created_at_time),
+    };
+    
+    let result: CallResult<(Result<Nat, TransferError>,)> = call(
+        ledger,
+        "icrc1_transfer",
+        (args,),
+    ).await;
+    
+    let outcome = match result {
+        Ok((Ok(block_index),)) | Ok((Err(TransferError::Duplicate { duplicate_of: block_index }),)) => {
+            match u64::try_from(block_index.0) {
+                Ok(block_index) => TransferOutcome::Completed(block_index),
+                Err(_) => TransferOutcome::Unknown("Ledger block index does not fit in u64".to_string()),
+            }
+        },
+        // Past the ledger's deduplication window a retry can't tell whether the first attempt landed
+        Ok((Err(TransferError::TooOld),)) => TransferOutcome::Unknown(
+            "Payout is too old for the ledger to deduplicate, governance has to resolve it".to_string()
+        ),
+        Ok((Err(e),)) => TransferOutcome::Rejected(format!("Claim payout failed: {:?}", e)),
+        Err(e) => TransferOutcome::Unknown(format!("Failed to call ledger: {:?}", e)),
+    };
+    
+    apply_payout_outcome(claim_id, outcome)
+}
+
+// Marks the claim as paying before the transfer so it cannot be paid twice. A retry reuses the
+// created_at_time of the first attempt.
+fn start_payout(config: &InsuranceConfig, claim_id: u64, caller: Principal, now: u64) -> Result<(Principal, u64, u64), String> {
+    CLAIMS.with(|c| {
+        let mut claims = c.borrow_mut();
+        let claim = claims.get_mut(&claim_id).ok_or_else(|| "Claim not found".to_string())?;
+        if claim.claimant != caller && !is_adjudicator(config, caller) {
+            return Err("Only the claimant or an adjudicator can pay a claim".to_string());
+        }
+        if claim.status != ClaimStatus::Approved && claim.status != ClaimStatus::Paying {
+            return Err("Claim is not approved".to_string());
+        }
+        claim.status = ClaimStatus::Paying;
+        let created_at_time = *claim.payout_created_at.get_or_insert(now);
+        Ok((claim.claimant, claim.amount, created_at_time))
+    })
+}
+
+fn apply_payout_outcome(claim_id: u64, outcome: TransferOutcome) -> Result<u64, String> {
+    match outcome {
+        TransferOutcome::Completed(block_index) => {
+            commit_payout(claim_id, block_index);
+            Ok(block_index)
+        },
+        TransferOutcome::Rejected(error) => {
+            revert_payout(claim_id);
+            Err(error)
+        },
+        TransferOutcome::Unknown(error) => {
+            CLAIMS.with(|c| {
+                if let Some(claim) = c.borrow_mut().get_mut(&claim_id) {
+                    claim.payout_error = Some(error.clone());
+                }
+            });
+            Err(format!("Payout of claim {} is pending, call pay_claim again to settle it: {}", claim_id, error))
+        },
+    }
+}
+
+// Settles a payout the ledger can no longer deduplicate: commit it with the block that paid it, or revert it
+#[update]
+fn resolve_claim_payout(claim_id: u64, block_index: Option<u64>) -> Result<(), String> {
+    let config = get_config()?;
+    if !is_governance(&config, msg_caller()) {
+        return Err("Only governance can resolve a claim payout".to_string());
+    }
+    let _guard = PayoutGuard::acquire(claim_id)?;
+    
+    let paying = CLAIMS.with(|c| c.borrow().get(&claim_id).map(|claim| claim.status == ClaimStatus::Paying));
+    if paying != Some(true) {
+        return Err("Claim has no pending payout".to_string());
+    }
+    
+    match block_index {
+        Some(block_index) => commit_payout(claim_id, block_index),
+        None => revert_payout(claim_id),
+    }
+    Ok(())
+}
+
+// The transfer went through: move the reserved amount into paid
+fn commit_payout(claim_id: u64, block_index: u64) {
+    let claim = CLAIMS.with(|c| {
+        let mut claims = c.borrow_mut();
+        let claim = claims.get_mut(&claim_id)?;
+        claim.status = ClaimStatus::Paid;
+        claim.payout_block_index = Some(block_index);
+        claim.payout_error = None;
+        Some(claim.clone())
+    });
+    
+    if let Some(claim) = claim {
+        POLICIES.with(|p| {
+            if let Some(policy) = p.borrow_mut().get_mut(&claim.policy_id) {
+                policy.claims_reserved -= claim.amount;
+                policy.claims_paid += claim.amount;
+            }
+        });
+        POOL.with(|pool| {
+            let mut pool = pool.borrow_mut();
+            pool.reserves -= claim.amount;
+            pool.reserved_for_claims -= claim.amount;
+            pool.total_claims_paid += claim.amount;
+        });
+    }
+}
+
+// Nothing moved: the claim is approved again and its funds stay reserved for a new attempt
+fn revert_payout(claim_id: u64) {
+    CLAIMS.with(|c| {
+        if let Some(claim) = c.borrow_mut().get_mut(&claim_id) {
+            claim.status = ClaimStatus::Approved;
+            claim.payout_created_at = None;
+            claim.payout_error = None;
+        }
+    });
+}
+
+// Marks a claim payout as in flight; dropped when the call ends, including when its callback traps
+struct PayoutGuard {
+    claim_id: u64,
+}
+
+impl PayoutGuard {
+    fn acquire(claim_id: u64) -> Result<Self, String> {
+        PAYING_CLAIMS.with(|paying| {
+            if paying.borrow_mut().insert(claim_id) {
+                Ok(PayoutGuard { claim_id })
+            } else {
+                Err(format!("Payout of claim {} is already in progress", claim_id))
+            }
+        })
+    }
+}
+
+impl Drop for PayoutGuard {
+    fn drop(&mut self) {
+        PAYING_CLAIMS.with(|paying| {
+            paying.borrow_mut().remove(&self.claim_id);
+        });
+    }
+}
+
+// Upgrades
+
+// Config, registered vaults, policies, claims, pool and the policy and claim counters
+type PoolState = (
+    Option<InsuranceConfig>,
+    Vec<Principal>,
+    HashMap<u64, Policy>,
+    HashMap<u64, Claim>,
+    PoolStats,
+    u64,
+    u64,
+);
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    ic_cdk::storage::stable_save(pool_state()).expect("Failed to save insurance pool state");
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let state: PoolState = ic_cdk::storage::stable_restore().expect("Failed to restore insurance pool state");
+    restore_pool_state(state);
+}
+
+fn pool_state() -> PoolState {
+    (
+        CONFIG.with(|c| c.borrow().clone()),
+        REGISTERED_VAULTS.with(|v| v.borrow().clone()),
+        POLICIES.with(|p| p.borrow().clone()),
+        CLAIMS.with(|c| c.borrow().clone()),
+        POOL.with(|p| p.borrow().clone()),
+        POLICY_COUNTER.with(|c| *c.borrow()),
+        CLAIM_COUNTER.with(|c| *c.borrow()),
+    )
+}
+
+fn restore_pool_state(state: PoolState) {
+    let (config, registered_vaults, policies, claims, pool, policy_counter, claim_counter) = state;
+    
+    CONFIG.with(|c| *c.borrow_mut() = config);
+    REGISTERED_VAULTS.with(|v| *v.borrow_mut() = registered_vaults);
+    POLICIES.with(|p| *p.borrow_mut() = policies);
+    CLAIMS.with(|c| *c.borrow_mut() = claims);
+    POOL.with(|p| *p.borrow_mut() = pool);
+    POLICY_COUNTER.with(|c| *c.borrow_mut() = policy_counter);
+    CLAIM_COUNTER.with(|c| *c.borrow_mut() = claim_counter);
+}
+
+// Queries
+
+#[query]
+fn get_policy(policy_id: u64) -> Option<Policy> {
+    POLICIES.with(|p| p.borrow().get(&policy_id).cloned())
+}
+
+#[query]
+fn get_policies_for_vault(vault: Principal) -> Vec<Policy> {
+    POLICIES.with(|p| {
+        p.borrow().values()
+            .filter(|policy| policy.vault == vault)
+            .cloned()
+            .collect()
+    })
+}
+
+#[query]
+fn get_policy_cover(policy_id: u64) -> Option<u64> {
+    POLICIES.with(|p| p.borrow().get(&policy_id).map(policy_cover))
+}
+
+#[query]
+fn get_claim(claim_id: u64) -> Option<Claim> {
+    CLAIMS.with(|c| c.borrow().get(&claim_id).cloned())
+}
+
+#[query]
+fn get_claims(policy_id: Option<u64>) -> Vec<Claim> {
+    CLAIMS.with(|c| {
+        let mut claims: Vec<Claim> = c.borrow().values()
+            .filter(|claim| policy_id.is_none_or(|id| claim.policy_id == id))
+            .cloned()
+            .collect();
+        claims.sort_by_key(|claim| claim.claim_id);
+        claims
+    })
+}
+
+#[query]
+fn get_pool_stats() -> PoolStats {
+    let mut stats = POOL.with(|pool| pool.borrow().clone());
+    POLICIES.with(|p| {
+        for policy in p.borrow().values().filter(|policy| policy.status == PolicyStatus::Active) {
+            stats.active_policies += 1;
+            stats.outstanding_coverage += policy_cover(policy).saturating_sub(policy.claims_paid);
+        }
+    });
+    stats
+}
+
+#[query]
+fn get_pricing_config() -> Option<PricingConfig> {
+    CONFIG.with(|c| c.borrow().as_ref().map(|config| config.pricing.clone()))
+}
+
+#[query]
+fn get_registered_vaults() -> Vec<Principal> {
+    REGISTERED_VAULTS.with(|v| v.borrow().clone())
+}
+
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+    
+    fn config() -> InsuranceConfig {
+        InsuranceConfig {
+            admin: principal(7),
+            dao_canister: None,
+            ledger_canister: Some(principal(8)),
+            nft_registry_canister: None,
+            adjudicators: vec![principal(6)],
+            pricing: default_pricing(),
+        }
+    }
+    
+    fn profile(funding_goal: u64, verified_revenue_reports: u64) -> RiskProfile {
+        RiskProfile {
+            creator: principal(5),
+            campaign_id: 1,
+            funding_goal,
+            verified_revenue_reports,
+            verified_revenue_total: 0,
+        }
+    }
+    
+    // An active policy at 500 bps: every 500 of premium insures 10_000, 80% of which is covered
+    fn insert_policy(policy_id: u64, premiums_paid: u64) {
+        let pricing = default_pricing();
+        let mut quote = price_premium(&pricing, &profile(1_000_000, 0), VerificationLevel::Verified);
+        quote.premium_bps = 500;
+        POLICIES.with(|p| p.borrow_mut().insert(policy_id, Policy {
+            policy_id,
+            vault: principal(9),
+            risk_profile: profile(1_000_000, 0),
+            quote,
+            coverage_limit: 800_000,
+            coverage_ratio: 80,
+            premiums_paid,
+            claims_reserved: 0,
+            claims_paid: 0,
+            status: PolicyStatus::Active,
+            created_at: 0,
+        }));
+    }
+    
+    fn insert_claim(claim_id: u64, policy_id: u64, amount: u64) {
+        CLAIMS.with(|c| c.borrow_mut().insert(claim_id, Claim {
+            claim_id,
+            policy_id,
+            claimant: principal(1),
+            trigger_id: 0,
+            amount,
+            reason: "Campaign failed".to_string(),
+            evidence: Vec::new(),
+            status: ClaimStatus::Pending,
+            filed_at: 0,
+            adjudicated_by: None,
+            adjudicated_at: None,
+            notes: None,
+            payout_block_index: None,
+            payout_created_at: None,
+            payout_error: None,
+        }));
+    }
+    
+    fn claim(claim_id: u64) -> Claim {
+        CLAIMS.with(|c| c.borrow()[&claim_id].clone())
+    }
+    
+    fn fund_pool(reserves: u64) {
+        POOL.with(|pool| pool.borrow_mut().reserves = reserves);
+    }
+    
+    #[test]
+    fn premium_rises_with_the_risk_profile() {
+        let pricing = default_pricing();
+        
+        // Established creator with a revenue history and a small campaign pays the base rate
+        let low = price_premium(&pricing, &profile(1_000, 12), VerificationLevel::Established);
+        assert_eq!((low.verification_bps, low.revenue_history_bps, low.campaign_size_bps), (0, 0, 0));
+        assert_eq!(low.premium_bps, 200);
+        
+        let medium = price_premium(&pricing, &profile(1_000, 2), VerificationLevel::Verified);
+        assert_eq!(medium.premium_bps, 200 + 100 + 100);
+        
+        // Unverified, no revenue reports and a large campaign carry every surcharge
+        let high = price_premium(&pricing, &profile(10_000_000, 0), VerificationLevel::Unverified);
+        assert_eq!((high.verification_bps, high.revenue_history_bps, high.campaign_size_bps), (300, 200, 100));
+        assert_eq!(high.premium_bps, 800);
+    }
+    
+    #[test]
+    fn premium_is_clamped_to_the_configured_range() {
+        let pricing = PricingConfig { base_premium_bps: 50, max_premium_bps: 500, ..default_pricing() };
+        
+        assert_eq!(price_premium(&pricing, &profile(1_000, 12), VerificationLevel::Established).premium_bps, 100);
+        assert_eq!(price_premium(&pricing, &profile(10_000_000, 0), VerificationLevel::Unverified).premium_bps, 500);
+    }
+    
+    #[test]
+    fn approval_is_bounded_by_the_earned_cover_and_free_reserves() {
+        insert_policy(1, 500);
+        fund_pool(100_000);
+        assert_eq!(policy_cover(&get_policy(1).unwrap()), 8_000);
+        
+        insert_claim(1, 1, 9_000);
+        assert!(decide_claim(1, true, None, principal(6), 10).is_err());
+        assert_eq!(claim(1).status, ClaimStatus::Pending);
+        
+        insert_claim(2, 1, 8_000);
+        assert_eq!(decide_claim(2, true, Some("Trigger confirmed".to_string()), principal(6), 10), Ok(ClaimStatus::Approved));
+        assert_eq!(get_policy(1).unwrap().claims_reserved, 8_000);
+        assert_eq!(POOL.with(|pool| pool.borrow().reserved_for_claims), 8_000);
+        assert_eq!(claim(2).notes.as_deref(), Some("Trigger confirmed"));
+        assert!(decide_claim(2, false, None, principal(6), 20).is_err());
+        
+        // Rejecting reserves nothing
+        assert_eq!(decide_claim(1, false, None, principal(6), 10), Ok(ClaimStatus::Rejected));
+        assert_eq!(get_policy(1).unwrap().claims_reserved, 8_000);
+    }
+    
+    #[test]
+    fn approval_needs_free_pool_reserves() {
+        insert_policy(1, 5_000);
+        fund_pool(5_000);
+        insert_claim(1, 1, 6_000);
+        
+        assert!(decide_claim(1, true, None, principal(6), 10).is_err());
+        assert_eq!(get_policy(1).unwrap().claims_reserved, 0);
+    }
+    
+    #[test]
+    fn payout_retries_with_the_same_created_at_and_settles_once() {
+        let config = config();
+        insert_policy(1, 500);
+        fund_pool(100_000);
+        insert_claim(1, 1, 1_000);
+        decide_claim(1, true, None, principal(6), 10).unwrap();
+        
+        assert!(start_payout(&config, 1, principal(2), 20).is_err());
+        assert_eq!(start_payout(&config, 1, principal(1), 20), Ok((principal(1), 1_000, 20)));
+        assert!(apply_payout_outcome(1, TransferOutcome::Unknown("Timeout".to_string())).is_err());
+        assert_eq!(claim(1).status, ClaimStatus::Paying);
+        assert!(claim(1).payout_error.is_some());
+        
+        // The retry re-sends the same transfer, the ledger answers with the original block
+        assert_eq!(start_payout(&config, 1, principal(1), 30), Ok((principal(1), 1_000, 20)));
+        assert_eq!(apply_payout_outcome(1, TransferOutcome::Completed(42)), Ok(42));
+        
+        let paid = claim(1);
+        assert_eq!((paid.status, paid.payout_block_index, paid.payout_error), (ClaimStatus::Paid, Some(42), None));
+        let pool = POOL.with(|pool| pool.borrow().clone());
+        assert_eq!((pool.reserves, pool.reserved_for_claims, pool.total_claims_paid), (99_000, 0, 1_000));
+        let policy = get_policy(1).unwrap();
+        assert_eq!((policy.claims_reserved, policy.claims_paid), (0, 1_000));
+        
+        // A paid claim cannot be paid again
+        assert!(start_payout(&config, 1, principal(1), 40).is_err());
+    }
+    
+    #[test]
+    fn rejected_payout_keeps_the_claim_approved_and_reserved() {
+        let config = config();
+        insert_policy(1, 500);
+        fund_pool(100_000);
+        insert_claim(1, 1, 1_000);
+        decide_claim(1, true, None, principal(6), 10).unwrap();
+        
+        start_payout(&config, 1, principal(6), 20).unwrap();
+        assert!(apply_payout_outcome(1, TransferOutcome::Rejected("InsufficientFunds".to_string())).is_err());
+        
+        let claim = claim(1);
+        assert_eq!((claim.status, claim.payout_created_at), (ClaimStatus::Approved, None));
+        assert_eq!(POOL.with(|pool| pool.borrow().reserved_for_claims), 1_000);
+        assert_eq!(start_payout(&config, 1, principal(1), 30), Ok((principal(1), 1_000, 30)));
+    }
+    
+    #[test]
+    fn pool_state_survives_an_upgrade() {
+        CONFIG.with(|c| *c.borrow_mut() = Some(config()));
+        REGISTERED_VAULTS.with(|v| v.borrow_mut().push(principal(9)));
+        insert_policy(1, 500);
+        fund_pool(100_000);
+        insert_claim(1, 1, 1_000);
+        decide_claim(1, true, None, principal(6), 10).unwrap();
+        start_payout(&config(), 1, principal(1), 20).unwrap();
+        POLICY_COUNTER.with(|c| *c.borrow_mut() = 1);
+        CLAIM_COUNTER.with(|c| *c.borrow_mut() = 1);
+        
+        // stable_save and stable_restore encode the state as Candid arguments
+        let bytes = candid::encode_args(pool_state()).unwrap();
+        restore_pool_state(Default::default());
+        assert!(get_policy(1).is_none());
+        restore_pool_state(candid::decode_args::<PoolState>(&bytes).unwrap());
+        
+        assert_eq!(get_registered_vaults(), vec![principal(9)]);
+        assert_eq!(get_pricing_config().unwrap().max_premium_bps, 1_000);
+        assert_eq!(get_policy(1).unwrap().claims_reserved, 1_000);
+        let claim = claim(1);
+        assert_eq!((claim.status, claim.payout_created_at), (ClaimStatus::Paying, Some(20)));
+        assert_eq!(POOL.with(|pool| pool.borrow().reserved_for_claims), 1_000);
+        assert_eq!((POLICY_COUNTER.with(|c| *c.borrow()), CLAIM_COUNTER.with(|c| *c.borrow())), (1, 1));
+    }
+}
@@ -1,8 +1,9 @@
 use ic_cdk::api::{msg_caller, time};
+use ic_cdk::call::{Call, CallResult};
 use ic_cdk_macros::{init, query, update};
 use std::cell::RefCell;
 use std::collections::HashMap;
-use candid::{CandidType, Principal};
+use candid::{utils::ArgumentEncoder, CandidType, Principal};
 use serde::{Deserialize, Serialize};
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -10,6 +11,19 @@ pub enum ProposalType {
     ParameterChange,
     CodeUpgrade,
     Treasury,
+    VaultSlashing { vault: Principal, slash_proposal_id: u64, approve: bool },
+    VaultGuardian { vault: Principal, guardian: Option<Principal> },
+    VaultUnpause { vault: Principal, operations: Option<Vec<VaultOperation>> }, // None lifts every pause
+    StreamFunder { stream_canister: Principal, vault: Principal, campaign_id: Option<u64> }, // None revokes the vault
+}
+
+// Mirrors the vault's VaultOperation
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum VaultOperation {
+    Invest,
+    UpdateRevenue,
+    DistributePayouts,
+    StreamTransfers,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -44,11 +58,11 @@ pub struct GovernanceStats {
 // Storage
 thread_local! {
     static PROPOSALS: RefCell<HashMap<u64, Proposal>> = RefCell::new(HashMap::new());
-    static PROPOSAL_COUNTER: RefCell<u64> = RefCell::new(0);
+    static PROPOSAL_COUNTER: RefCell<u64> = const { RefCell::new(0) };
     static MEMBER_VOTES: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new()); // Voting power
-    static TREASURY_BALANCE: RefCell<u64> = RefCell::new(1000000); // Initial treasury
-    static READY_FOR_UPGRADE: RefCell<bool> = RefCell::new(false); // Flag for code upgrade
-    static DEFAULT_VOTING_PERIOD: RefCell<u64> = RefCell::new(7 * 24 * 60 * 60 * 1_000_000_000); // 7 days in nanoseconds
+    static TREASURY_BALANCE: RefCell<u64> = const { RefCell::new(1000000) }; // Initial treasury
+    static READY_FOR_UPGRADE: RefCell<bool> = const { RefCell::new(false) }; // Flag for code upgrade
+    static DEFAULT_VOTING_PERIOD: RefCell<u64> = const { RefCell::new(7 * 24 * 60 * 60 * 1_000_000_000) }; // 7 days in nanoseconds
 }
 
 #[init]
@@ -145,10 +159,10 @@ fn vote(proposal_id: u64, support: bool) -> Result<String, String> {
 }
 
 #[update]
-fn execute_proposal(proposal_id: u64) -> Result<String, String> {
+async fn execute_proposal(proposal_id: u64) -> Result<String, String> {
     let current_time = time();
     
-    PROPOSALS.with(|proposals| {
+    let passed_proposal = PROPOSALS.with(|proposals| {
         let mut proposals = proposals.borrow_mut();
         
         if let Some(proposal) = proposals.get_mut(&proposal_id) {
@@ -165,45 +179,92 @@ fn execute_proposal(proposal_id: u64) -> Result<String, String> {
             // Check if proposal passed (simple majority)
             if proposal.votes_for > proposal.votes_against {
                 proposal.executed = true;
-                
-                // Execute based on proposal type
-                match proposal.data.proposal_type {
-                    ProposalType::Treasury => {
-                        // Distribute treasury funds
-                        TREASURY_BALANCE.with(|balance| {
-                            let mut balance = balance.borrow_mut();
-                            if *balance >= 1000 {
-                                *balance -= 1000; // Distribute 1000 tokens
-                            }
-                        });
-                    },
-                    ProposalType::ParameterChange => {
-                      
-                        // We'll store a global DEFAULT_VOTING_PERIOD
-                        DEFAULT_VOTING_PERIOD.with(|period| {
-                            let mut period = period.borrow_mut();
-                            *period = proposal.data.voting_period;
-                        });
-                        ic_cdk::println!("Default voting period updated to {}", proposal.data.voting_period);
-                    },
-                    ProposalType::CodeUpgrade => {
-                        // Mark proposal as ready for upgrade and log
-                        READY_FOR_UPGRADE.with(|flag| {
-                            *flag.borrow_mut() = true;
-                        });
-                        ic_cdk::println!("Code upgrade proposal executed. Upgrade flag set.");
-                       
-                    },
-                }
-                
-                Ok("Proposal executed successfully".to_string())
+                Ok(Some(proposal.clone()))
             } else {
-                Ok("Proposal failed to pass".to_string())
+                Ok(None)
             }
         } else {
             Err("Proposal not found".to_string())
         }
-    })
+    })?;
+    
+    let proposal = match passed_proposal {
+        Some(proposal) => proposal,
+        None => return Ok("Proposal failed to pass".to_string()),
+    };
+    
+    // Execute based on proposal type
+    match proposal.data.proposal_type {
+        ProposalType::Treasury => {
+            // Distribute treasury funds
+            TREASURY_BALANCE.with(|balance| {
+                let mut balance = balance.borrow_mut();
+                if *balance >= 1000 {
+                    *balance -= 1000; // Distribute 1000 tokens
+                }
+            });
+        },
+        ProposalType::ParameterChange => {
+          
+            // We'll store a global DEFAULT_VOTING_PERIOD
+            DEFAULT_VOTING_PERIOD.with(|period| {
+                let mut period = period.borrow_mut();
+                *period = proposal.data.voting_period;
+            });
+            ic_cdk::println!("Default voting period updated to {}", proposal.data.voting_period);
+        },
+        ProposalType::CodeUpgrade => {
+            // Mark proposal as ready for upgrade and log
+            READY_FOR_UPGRADE.with(|flag| {
+                *flag.borrow_mut() = true;
+            });
+            ic_cdk::println!("Code upgrade proposal executed. Upgrade flag set.");
+           
+        },
+        ProposalType::VaultSlashing { vault, slash_proposal_id, approve } => {
+            // Record the DAO decision on the vault; the vault executes it after the challenge period
+            call_canister(proposal_id, vault, "resolve_slashing_by_dao", (slash_proposal_id, approve)).await?;
+        },
+        ProposalType::VaultGuardian { vault, guardian } => {
+            call_canister(proposal_id, vault, "set_guardian", (guardian,)).await?;
+        },
+        ProposalType::VaultUnpause { vault, operations } => {
+            call_canister(proposal_id, vault, "unpause_vault", (operations,)).await?;
+        },
+        ProposalType::StreamFunder { stream_canister, vault, campaign_id } => {
+            match campaign_id {
+                Some(campaign_id) => call_canister(proposal_id, stream_canister, "register_funder", (vault, campaign_id)).await?,
+                None => call_canister(proposal_id, stream_canister, "revoke_funder", (vault,)).await?,
+            }
+        },
+    }
+    
+    Ok("Proposal executed successfully".to_string())
+}
+
+// Calls a method returning Result<(), String>, reopening the proposal if it fails
+async fn call_canister<T: ArgumentEncoder>(proposal_id: u64, canister: Principal, method: &str, args: T) -> Result<(), String> {
+    let result: CallResult<Result<(), String>> = async {
+        Ok(Call::unbounded_wait(canister, method).with_args(&args).await?.candid()?)
+    }.await;
+    
+    let outcome = match result {
+        Ok(Ok(())) => Ok(()),
+        Ok(Err(e)) => Err(e),
+        Err(e) => Err(format!("Failed to call {}: {:?}", canister.to_text(), e)),
+    };
+    
+    if let Err(e) = outcome {
+        // Allow the proposal to be executed again once the canister is reachable
+        PROPOSALS.with(|proposals| {
+            if let Some(p) = proposals.borrow_mut().get_mut(&proposal_id) {
+                p.executed = false;
+            }
+        });
+        return Err(format!("{} failed: {}", method, e));
+    }
+    
+    Ok(())
 }
 
 #[update]
@@ -1,10 +1,17 @@
-use ic_cdk::api::{msg_caller, time};
-use candid::{CandidType, Principal};
+use ic_cdk::api::{canister_self, msg_caller, time};
+use ic_cdk::call::{Call, CallResult};
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
 use ic_cdk_macros::*;
+use ic_stable_structures::{
+    memory_manager::{MemoryId, MemoryManager, VirtualMemory},
+    DefaultMemoryImpl, StableCell,
+};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::cell::RefCell;
 
+type Memory = VirtualMemory<DefaultMemoryImpl>;
 type StreamId = u64;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -16,19 +23,60 @@ pub struct Stream {
     pub start_time: u64,
     pub end_time: u64,
     pub claimed_amount: u64,
+    pub pending_amount: u64,        // Reserved by claims whose transfer hasn't settled yet
     pub campaign_id: u64,
     pub vault_canister: Principal,
     pub is_active: bool,
     pub stream_type: StreamType,
+    pub ledger: Principal,
+    pub funder: Principal,          // Claims are paid from this funder's escrow subaccount
+    pub funding_block_index: u64,
+    pub paused_duration: u64,       // Nanoseconds spent in completed pauses, end_time has been pushed back by as much
+    pub pauses: Vec<PauseInterval>,
+    pub returned_to_funder: u64,    // Remainder too small to cover the ledger fee, left in the escrow for the funder
+}
+
+// Vesting is frozen from `paused_at` until `resumed_at`
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PauseInterval {
+    pub paused_at: u64,
+    pub paused_by: Principal,
+    pub pause_reason: String,
+    pub resumed_at: Option<u64>,
+    pub resumed_by: Option<Principal>,
+    pub resume_reason: Option<String>,
+}
+
+impl Stream {
+    fn current_pause(&self) -> Option<&PauseInterval> {
+        self.pauses.last().filter(|pause| pause.resumed_at.is_none())
+    }
+    
+    // What the stream still owes its recipient, pending claims included
+    fn outstanding(&self) -> u64 {
+        self.total_amount - self.claimed_amount - self.returned_to_funder
+    }
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub enum StreamType {
-    Linear,      // Uniform distribution over time
-    Cliff,       // All at once after cliff period
-    Exponential, // Decreasing rate over time
+    Linear,                                   // Uniform distribution over time
+    Cliff,                                    // All at once at the end
+    CliffLinear { cliff_seconds: u64 },       // Nothing until the cliff, which unlocks what has accrued linearly since the start
+    Steps { interval_seconds: u64 },          // Equal tranches unlocked at the end of every interval
+    Custom { breakpoints: Vec<VestingPoint> }, // Linear between breakpoints, from nothing at the start to everything at the end
 }
 
+// Share of the total vested `offset_seconds` into the schedule
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct VestingPoint {
+    pub offset_seconds: u64,
+    pub vested_bps: u32,
+}
+
+const BPS_DENOMINATOR: u32 = 10_000;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct ClaimResult {
     pub stream_id: StreamId,
@@ -37,6 +85,119 @@ pub struct ClaimResult {
     pub next_claim_time: u64,
 }
 
+// What a funder's escrow owes its streams against what it holds on the ledger
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EscrowCoverage {
+    pub funder: Principal,
+    pub ledger: Principal,
+    pub escrow: Account,
+    pub obligations: u64, // Unclaimed amounts of the funder's streams on this ledger
+    pub balance: u64,
+    pub shortfall: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EscrowObligation {
+    pub funder: Principal,
+    pub ledger: Principal,
+    pub streams: u64,
+    pub outstanding: u64,
+}
+
+// ICRC-1/ICRC-2 ledger types
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// A claim whose amount is reserved on the stream until the ledger transfer settles
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PendingClaim {
+    pub claim_id: u64,
+    pub stream_id: StreamId,
+    pub recipient: Principal,
+    pub funder: Principal,
+    pub ledger: Principal,
+    pub amount: u64,          // Reserved from the stream, the recipient receives it less the fee
+    pub fee: u64,
+    pub created_at_time: u64, // Reused on every attempt so the ledger deduplicates the transfer
+    pub attempts: u32,
+    pub last_error: Option<String>,
+}
+
+// A claim whose transfer landed, kept per funder so the funding vault can book the payout
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SettledClaim {
+    pub claim_id: u64,
+    pub stream_id: StreamId,
+    pub recipient: Principal,
+    pub ledger: Principal,
+    pub amount: u64, // Received by the recipient, the ledger fee is not included
+    pub block_index: u64,
+    pub settled_at: u64,
+}
+
+enum TransferOutcome {
+    Completed(u64),   // Block index of the transfer
+    Rejected(String), // The ledger refused it, nothing moved
+    Unknown(String),  // The transfer may or may not have happened
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StreamConfig {
+    pub admin: Principal,
+    pub campaign_factory: Option<Principal>, // May register the vaults it deploys as funders
+    pub dao_canister: Option<Principal>,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct StreamStats {
     pub total_streams: u64,
@@ -48,25 +209,252 @@ pub struct StreamStats {
 thread_local! {
     static STREAMS: RefCell<HashMap<StreamId, Stream>> = RefCell::new(HashMap::new());
     static USER_STREAMS: RefCell<HashMap<Principal, Vec<StreamId>>> = RefCell::new(HashMap::new());
-    static STREAM_COUNTER: RefCell<StreamId> = RefCell::new(0);
+    static STREAM_COUNTER: RefCell<StreamId> = const { RefCell::new(0) };
+    static PENDING_CLAIMS: RefCell<HashMap<u64, PendingClaim>> = RefCell::new(HashMap::new());
+    static SETTLING_CLAIMS: RefCell<HashSet<u64>> = RefCell::new(HashSet::new());
+    static CLAIM_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static SETTLED_CLAIMS: RefCell<HashMap<Principal, Vec<SettledClaim>>> = RefCell::new(HashMap::new()); // Funder -> claims in settlement order
+    static CONFIG: RefCell<Option<StreamConfig>> = const { RefCell::new(None) };
+    static AUTHORIZED_FUNDERS: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new()); // Vault -> campaign id
+    static FROZEN_FUNDERS: RefCell<HashMap<Principal, String>> = RefCell::new(HashMap::new()); // Vault -> freeze reason
+    static WITHDRAWING_ESCROWS: RefCell<HashSet<(Principal, Principal)>> = RefCell::new(HashSet::new()); // (funder, ledger)
+    
+    static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> = MemoryManager::init(DefaultMemoryImpl::default());
+    
+    // The stream state is kept here across upgrades
+    static UPGRADE_STATE: RefCell<StableCell<Vec<u8>, Memory>> = RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|m| m.get(MemoryId::new(0))), Vec::new())
+            .expect("Failed to initialize the upgrade state cell")
+    );
+}
+
+// Streams and the escrow they are owed from. Claims being settled and escrow withdrawals
+// in flight are not kept, an upgrade waits for their calls to end.
+#[derive(CandidType, Deserialize)]
+struct StreamState {
+    streams: HashMap<StreamId, Stream>,
+    user_streams: HashMap<Principal, Vec<StreamId>>,
+    stream_counter: StreamId,
+    pending_claims: HashMap<u64, PendingClaim>, // Unsettled claims, reconciled with the same claim id after the upgrade
+    claim_counter: u64,
+    settled_claims: HashMap<Principal, Vec<SettledClaim>>,
+    config: Option<StreamConfig>,
+    authorized_funders: HashMap<Principal, u64>,
+    frozen_funders: HashMap<Principal, String>,
 }
 
 #[init]
 fn init() {
+    let config = StreamConfig {
+        admin: msg_caller(),
+        campaign_factory: None,
+        dao_canister: None,
+    };
+    
+    CONFIG.with(|c| *c.borrow_mut() = Some(config));
     ic_cdk::println!("BeamFi Stream canister initialized");
 }
 
+#[pre_upgrade]
+fn pre_upgrade() {
+    let state = StreamState {
+        streams: STREAMS.with(|streams| streams.borrow().clone()),
+        user_streams: USER_STREAMS.with(|user_streams| user_streams.borrow().clone()),
+        stream_counter: STREAM_COUNTER.with(|counter| *counter.borrow()),
+        pending_claims: PENDING_CLAIMS.with(|claims| claims.borrow().clone()),
+        claim_counter: CLAIM_COUNTER.with(|counter| *counter.borrow()),
+        settled_claims: SETTLED_CLAIMS.with(|settled| settled.borrow().clone()),
+        config: CONFIG.with(|c| c.borrow().clone()),
+        authorized_funders: AUTHORIZED_FUNDERS.with(|funders| funders.borrow().clone()),
+        frozen_funders: FROZEN_FUNDERS.with(|frozen_funders| frozen_funders.borrow().clone()),
+    };
+    
+    let bytes = Encode!(&state).expect("Failed to encode stream state");
+    UPGRADE_STATE.with(|cell| cell.borrow_mut().set(bytes))
+        .expect("Failed to save stream state");
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let bytes = UPGRADE_STATE.with(|cell| cell.borrow().get().clone());
+    if bytes.is_empty() {
+        return;
+    }
+    
+    let state = Decode!(&bytes, StreamState).expect("Failed to decode stream state");
+    STREAMS.with(|streams| *streams.borrow_mut() = state.streams);
+    USER_STREAMS.with(|user_streams| *user_streams.borrow_mut() = state.user_streams);
+    STREAM_COUNTER.with(|counter| *counter.borrow_mut() = state.stream_counter);
+    PENDING_CLAIMS.with(|claims| *claims.borrow_mut() = state.pending_claims);
+    CLAIM_COUNTER.with(|counter| *counter.borrow_mut() = state.claim_counter);
+    SETTLED_CLAIMS.with(|settled| *settled.borrow_mut() = state.settled_claims);
+    CONFIG.with(|c| *c.borrow_mut() = state.config);
+    AUTHORIZED_FUNDERS.with(|funders| *funders.borrow_mut() = state.authorized_funders);
+    FROZEN_FUNDERS.with(|frozen_funders| *frozen_funders.borrow_mut() = state.frozen_funders);
+}
+
+fn is_admin(caller: Principal) -> bool {
+    CONFIG.with(|c| c.borrow().as_ref().map(|config| config.admin == caller).unwrap_or(false))
+}
+
+fn is_governance(caller: Principal) -> bool {
+    CONFIG.with(|c| {
+        c.borrow().as_ref()
+            .map(|config| config.admin == caller || config.dao_canister == Some(caller))
+            .unwrap_or(false)
+    })
+}
+
+// Configuration
+
+#[update]
+fn set_canister_refs(campaign_factory: Option<Principal>, dao_canister: Option<Principal>) -> Result<(), String> {
+    let caller = msg_caller();
+    if !is_governance(caller) {
+        return Err("Only governance can configure the stream canister".to_string());
+    }
+    
+    CONFIG.with(|c| {
+        let mut config_opt = c.borrow_mut();
+        let config = config_opt.as_mut().ok_or_else(|| "Stream canister not initialized".to_string())?;
+        config.campaign_factory = campaign_factory;
+        config.dao_canister = dao_canister;
+        Ok(())
+    })
+}
+
+// Only registered vaults may fund streams, and every stream they fund carries their campaign id
+#[update]
+fn register_funder(funder: Principal, campaign_id: u64) -> Result<(), String> {
+    authorize_funder(msg_caller(), funder, campaign_id)
+}
+
+fn authorize_funder(caller: Principal, funder: Principal, campaign_id: u64) -> Result<(), String> {
+    let from_factory = CONFIG.with(|c| c.borrow().as_ref().and_then(|config| config.campaign_factory) == Some(caller));
+    if !from_factory && !is_governance(caller) {
+        return Err("Only the campaign factory or governance can register funders".to_string());
+    }
+    
+    AUTHORIZED_FUNDERS.with(|funders| {
+        funders.borrow_mut().insert(funder, campaign_id);
+    });
+    Ok(())
+}
+
+// Stops the funder opening new streams, the streams it already funded stay claimable from its escrow
+#[update]
+fn revoke_funder(funder: Principal) -> Result<(), String> {
+    deauthorize_funder(msg_caller(), funder)
+}
+
+fn deauthorize_funder(caller: Principal, funder: Principal) -> Result<(), String> {
+    if !is_governance(caller) {
+        return Err("Only governance can revoke funders".to_string());
+    }
+    
+    AUTHORIZED_FUNDERS.with(|funders| funders.borrow_mut().remove(&funder))
+        .map(|_| ())
+        .ok_or_else(|| "Funder is not registered".to_string())
+}
+
+#[query]
+fn get_authorized_funders() -> Vec<(Principal, u64)> {
+    AUTHORIZED_FUNDERS.with(|funders| funders.borrow().iter().map(|(funder, campaign_id)| (*funder, *campaign_id)).collect())
+}
+
+// Circuit breaker for claims against a funder's escrow. A vault trips it for its own streams when its
+// guardian or governance pauses stream transfers; governance can trip it for any funder.
 #[update]
-fn create_stream(
+fn set_claims_frozen(funder: Principal, frozen: bool, reason: String) -> Result<(), String> {
+    freeze_claims(msg_caller(), funder, frozen, reason)
+}
+
+fn freeze_claims(caller: Principal, funder: Principal, frozen: bool, reason: String) -> Result<(), String> {
+    if caller != funder && !is_governance(caller) {
+        return Err("Only the funder or governance can freeze its stream claims".to_string());
+    }
+    
+    FROZEN_FUNDERS.with(|frozen_funders| {
+        let mut frozen_funders = frozen_funders.borrow_mut();
+        if frozen {
+            if reason.is_empty() {
+                return Err("A reason is required to freeze stream claims".to_string());
+            }
+            frozen_funders.insert(funder, reason);
+        } else {
+            frozen_funders.remove(&funder);
+        }
+        Ok(())
+    })
+}
+
+#[query]
+fn get_frozen_funders() -> Vec<(Principal, String)> {
+    FROZEN_FUNDERS.with(|frozen_funders| frozen_funders.borrow().iter().map(|(funder, reason)| (*funder, reason.clone())).collect())
+}
+
+fn ensure_claims_open(funder: Principal) -> Result<(), String> {
+    match FROZEN_FUNDERS.with(|frozen_funders| frozen_funders.borrow().get(&funder).cloned()) {
+        Some(reason) => Err(format!("Claims on this stream are frozen: {}", reason)),
+        None => Ok(()),
+    }
+}
+
+fn funder_campaign(funder: Principal) -> Result<u64, String> {
+    AUTHORIZED_FUNDERS.with(|funders| funders.borrow().get(&funder).copied())
+        .ok_or_else(|| format!("{} is not an authorized stream funder", funder.to_text()))
+}
+
+// Funds the stream from the calling vault, which must have approved this canister for `total_amount` plus the ledger fee
+#[update]
+async fn create_stream(
     recipient: Principal,
     total_amount: u64,
     duration_seconds: u64,
-    campaign_id: u64,
-    vault_canister: Principal,
     stream_type: StreamType,
+    ledger: Principal,
 ) -> Result<StreamId, String> {
-    let _caller = msg_caller();
+    let funder = msg_caller();
+    let campaign_id = funder_campaign(funder)?;
+    validate_stream(total_amount, duration_seconds, &stream_type)?;
     
+    let funding_block_index = fund_escrow(ledger, funder, total_amount).await?;
+    Ok(open_stream(recipient, total_amount, duration_seconds, campaign_id, funder, stream_type, ledger, funding_block_index, time()))
+}
+
+// Creates one stream per payout, funded from the caller in a single transfer so the batch is all or nothing
+#[update]
+async fn create_streams(ledger: Principal, payouts: Vec<(Principal, u64)>) -> Result<Vec<StreamId>, String> {
+    let caller = msg_caller();
+    let campaign_id = funder_campaign(caller)?;
+    let duration_seconds = 2592000; // 30 days default duration
+    
+    for (_, amount) in &payouts {
+        validate_stream(*amount, duration_seconds, &StreamType::Linear)?;
+    }
+    
+    let total: u64 = payouts.iter().map(|(_, amount)| amount).sum();
+    let funding_block_index = fund_escrow(ledger, caller, total).await?;
+    let now = time();
+    
+    let stream_ids = payouts.into_iter()
+        .map(|(recipient, amount)| open_stream(
+            recipient,
+            amount,
+            duration_seconds,
+            campaign_id,
+            caller,
+            StreamType::Linear,
+            ledger,
+            funding_block_index,
+            now,
+        ))
+        .collect();
+    
+    Ok(stream_ids)
+}
+
+fn validate_stream(total_amount: u64, duration_seconds: u64, stream_type: &StreamType) -> Result<(), String> {
     if total_amount == 0 {
         return Err("Stream amount must be greater than 0".to_string());
     }
@@ -75,6 +463,60 @@ fn create_stream(
         return Err("Stream duration must be greater than 0".to_string());
     }
     
+    if duration_seconds.checked_mul(NANOS_PER_SECOND).is_none() {
+        return Err("Stream duration is too long".to_string());
+    }
+    
+    match stream_type {
+        StreamType::Linear | StreamType::Cliff => {},
+        StreamType::CliffLinear { cliff_seconds } => {
+            if *cliff_seconds > duration_seconds {
+                return Err("Cliff must not be longer than the stream".to_string());
+            }
+        },
+        StreamType::Steps { interval_seconds } => {
+            if *interval_seconds == 0 || *interval_seconds > duration_seconds {
+                return Err("Step interval must be between 1 second and the stream duration".to_string());
+            }
+        },
+        StreamType::Custom { breakpoints } => {
+            let mut previous: Option<&VestingPoint> = None;
+            for point in breakpoints {
+                if point.offset_seconds >= duration_seconds {
+                    return Err("Breakpoints must fall before the end of the stream".to_string());
+                }
+                if point.vested_bps > BPS_DENOMINATOR {
+                    return Err("Breakpoints cannot vest more than 10000 bps".to_string());
+                }
+                if let Some(previous) = previous {
+                    if point.offset_seconds <= previous.offset_seconds {
+                        return Err("Breakpoint offsets must be strictly increasing".to_string());
+                    }
+                    if point.vested_bps < previous.vested_bps {
+                        return Err("Breakpoints cannot unvest".to_string());
+                    }
+                }
+                previous = Some(point);
+            }
+        },
+    }
+    
+    Ok(())
+}
+
+// The funding vault owns the stream and its escrow pays the claims. The stream starts at `now`.
+#[allow(clippy::too_many_arguments)]
+fn open_stream(
+    recipient: Principal,
+    total_amount: u64,
+    duration_seconds: u64,
+    campaign_id: u64,
+    vault_canister: Principal,
+    stream_type: StreamType,
+    ledger: Principal,
+    funding_block_index: u64,
+    now: u64,
+) -> StreamId {
     let stream_id = STREAM_COUNTER.with(|counter| {
         let current = *counter.borrow();
         let next = current + 1;
@@ -82,13 +524,12 @@ fn create_stream(
         next
     });
     
-    let start_time = time();
-    let end_time = start_time + (duration_seconds * 1_000_000_000); // Convert to nanoseconds
+    let start_time = now;
+    let end_time = start_time + (duration_seconds * NANOS_PER_SECOND); // Convert to nanoseconds
     
     let amount_per_second = match stream_type {
-        StreamType::Linear => total_amount / duration_seconds,
         StreamType::Cliff => 0, // All at end
-        StreamType::Exponential => total_amount / duration_seconds, // Initial rate
+        _ => total_amount / duration_seconds, // Average rate
     };
     
     let stream = Stream {
@@ -99,10 +540,17 @@ fn create_stream(
         start_time,
         end_time,
         claimed_amount: 0,
+        pending_amount: 0,
         campaign_id,
         vault_canister,
         is_active: true,
         stream_type,
+        ledger,
+        funder: vault_canister,
+        funding_block_index,
+        paused_duration: 0,
+        pauses: Vec::new(),
+        returned_to_funder: 0,
     };
     
     // Store stream
@@ -119,149 +567,563 @@ fn create_stream(
     
     ic_cdk::println!("Stream {} created for {} (amount: {})", stream_id, recipient.to_text(), total_amount);
     
-    Ok(stream_id)
+    stream_id
 }
 
 #[update]
-fn create_streams(payouts: Vec<(Principal, u64)>) -> Result<Vec<StreamId>, String> {
+async fn claim_stream(stream_id: StreamId) -> Result<ClaimResult, String> {
     let caller = msg_caller();
-    let mut stream_ids = Vec::new();
     
-    for (recipient, amount) in payouts {
-        let stream_id = create_stream(
-            recipient,
-            amount,
-            2592000, // 30 days default duration
-            0, // TODO: Get campaign_id from caller context
-            caller, // Assume caller is vault canister
-            StreamType::Linear,
-        )?;
-        stream_ids.push(stream_id);
-    }
+    // The escrow pays the ledger fee out of the claim, so a claim has to be larger than it
+    let (ledger, funder) = STREAMS.with(|streams| streams.borrow().get(&stream_id).map(|s| (s.ledger, s.funder)))
+        .ok_or_else(|| "Stream not found".to_string())?;
+    ensure_claims_open(funder)?;
+    let fee = ledger_fee(ledger).await?;
     
-    Ok(stream_ids)
+    let Some(claim) = reserve_claim(stream_id, caller, fee, time())? else {
+        return Ok(ClaimResult {
+            stream_id,
+            claimed_amount: 0,
+            remaining_amount: 0,
+            next_claim_time: 0,
+        });
+    };
+    
+    settle_claim(claim.claim_id).await
 }
 
-#[update]
-async fn claim_stream(stream_id: StreamId) -> Result<ClaimResult, String> {
-    let caller = msg_caller();
-    let current_time = time();
-    
-    // First, get and validate the stream
-    let stream_data = STREAMS.with(|streams| {
+// Reserves what `caller` can claim from the stream at `now` and records it as a pending claim. Returns
+// None when the remainder was below the fee and the stream was closed instead.
+fn reserve_claim(stream_id: StreamId, caller: Principal, fee: u64, now: u64) -> Result<Option<PendingClaim>, String> {
+    // Reserve the claimable amount so the stream can't pay it twice while the transfer is in flight
+    let claim = STREAMS.with(|streams| {
         let mut borrowed = streams.borrow_mut();
-        if let Some(stream) = borrowed.get_mut(&stream_id) {
-            if stream.recipient != caller {
-                return Err("Only stream recipient can claim".to_string());
-            }
-            
-            if !stream.is_active {
-                return Err("Stream is not active".to_string());
+        let stream = borrowed.get_mut(&stream_id).ok_or_else(|| "Stream not found".to_string())?;
+        
+        if stream.recipient != caller {
+            return Err("Only stream recipient can claim".to_string());
+        }
+        
+        if !stream.is_active {
+            return Err("Stream is not active".to_string());
+        }
+        
+        if now < stream.start_time {
+            return Err("Stream has not started yet".to_string());
+        }
+        
+        let mut claimable = calculate_claimable_amount(stream, now);
+        
+        if claimable == 0 {
+            return Err("No claimable amount available".to_string());
+        }
+        
+        // A remainder no larger than the fee could never be claimed on its own, so this claim takes it early
+        let left_after = stream.outstanding() - stream.pending_amount - claimable;
+        if left_after <= fee {
+            claimable += left_after;
+        }
+        
+        if claimable <= fee {
+            if claimable + stream.pending_amount < stream.outstanding() {
+                return Err(format!("Claimable amount {} does not cover the ledger fee of {}", claimable, fee));
             }
-            
-            if current_time < stream.start_time {
-                return Err("Stream has not started yet".to_string());
+            if stream.pending_amount > 0 {
+                return Err("Stream has a pending claim, settle it first".to_string());
             }
             
-            let claimable = calculate_claimable_amount(&stream, current_time);
-            
-            if claimable == 0 {
-                return Err("No claimable amount available".to_string());
+            // The whole remainder is below the fee: close the stream and leave it in the escrow for the funder
+            stream.returned_to_funder += claimable;
+            stream.is_active = false;
+            ic_cdk::println!("Stream {} closed, {} below the ledger fee returned to the funder", stream_id, claimable);
+            return Ok(None);
+        }
+        
+        stream.pending_amount += claimable;
+        
+        let claim_id = CLAIM_COUNTER.with(|counter| {
+            let next = *counter.borrow() + 1;
+            *counter.borrow_mut() = next;
+            next
+        });
+        
+        Ok(Some(PendingClaim {
+            claim_id,
+            stream_id,
+            recipient: stream.recipient,
+            funder: stream.funder,
+            ledger: stream.ledger,
+            amount: claimable,
+            fee,
+            created_at_time: now,
+            attempts: 0,
+            last_error: None,
+        }))
+    })?;
+    
+    if let Some(ref claim) = claim {
+        PENDING_CLAIMS.with(|claims| {
+            claims.borrow_mut().insert(claim.claim_id, claim.clone());
+        });
+    }
+    Ok(claim)
+}
+
+// Re-sends the transfer of a claim left pending by an unknown outcome or a trapped callback
+#[update]
+async fn retry_claim(claim_id: u64) -> Result<ClaimResult, String> {
+    let caller = msg_caller();
+    
+    let (recipient, funder) = PENDING_CLAIMS.with(|claims| claims.borrow().get(&claim_id).map(|c| (c.recipient, c.funder)))
+        .ok_or_else(|| "Pending claim not found".to_string())?;
+    
+    if recipient != caller && !is_admin(caller) {
+        return Err("Only the claim recipient or the admin can retry a claim".to_string());
+    }
+    
+    ensure_claims_open(funder)?;
+    
+    settle_claim(claim_id).await
+}
+
+// Settles a claim the ledger can no longer deduplicate: commit it with the block that paid it, or revert it
+#[update]
+fn resolve_claim(claim_id: u64, block_index: Option<u64>) -> Result<(), String> {
+    if !is_admin(msg_caller()) {
+        return Err("Only the admin can resolve a claim".to_string());
+    }
+    
+    if SETTLING_CLAIMS.with(|settling| settling.borrow().contains(&claim_id)) {
+        return Err(format!("Claim {} is being settled", claim_id));
+    }
+    
+    match block_index {
+        Some(block_index) => commit_claim(claim_id, block_index, time()).map(|_| ()),
+        None => revert_claim(claim_id),
+    }
+}
+
+#[query]
+fn get_pending_claims() -> Vec<PendingClaim> {
+    PENDING_CLAIMS.with(|claims| claims.borrow().values().cloned().collect())
+}
+
+// The funder's settled claims from `offset` on, the vault pages through them to book its stream payouts
+#[query]
+fn get_settled_claims(funder: Principal, offset: u64, limit: u64) -> Vec<SettledClaim> {
+    SETTLED_CLAIMS.with(|settled| {
+        settled.borrow().get(&funder)
+            .map(|claims| claims.iter().skip(offset as usize).take(limit as usize).cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+// Marks a claim as being settled; dropped when the call ends, including when its callback traps
+struct SettlementGuard {
+    claim_id: u64,
+}
+
+impl SettlementGuard {
+    fn acquire(claim_id: u64) -> Result<Self, String> {
+        SETTLING_CLAIMS.with(|settling| {
+            if settling.borrow_mut().insert(claim_id) {
+                Ok(SettlementGuard { claim_id })
+            } else {
+                Err(format!("Claim {} is already being settled", claim_id))
             }
+        })
+    }
+}
+
+impl Drop for SettlementGuard {
+    fn drop(&mut self) {
+        SETTLING_CLAIMS.with(|settling| {
+            settling.borrow_mut().remove(&self.claim_id);
+        });
+    }
+}
+
+async fn settle_claim(claim_id: u64) -> Result<ClaimResult, String> {
+    let _guard = SettlementGuard::acquire(claim_id)?;
+    
+    let claim = PENDING_CLAIMS.with(|claims| {
+        let mut borrowed = claims.borrow_mut();
+        let claim = borrowed.get_mut(&claim_id).ok_or_else(|| "Pending claim not found".to_string())?;
+        claim.attempts += 1;
+        Ok::<_, String>(claim.clone())
+    })?;
+    
+    // Paid straight from the funder's escrow, the recipient receives the claim less the ledger fee
+    let outcome = escrow_transfer(
+        claim.ledger,
+        claim.funder,
+        claim.recipient,
+        claim.amount - claim.fee,
+        claim.fee,
+        claim.claim_id,
+        claim.created_at_time,
+    ).await;
+    
+    apply_claim_outcome(claim_id, outcome, time())
+}
+
+// Commits the claim when the transfer landed and releases it when nothing moved. An unknown outcome
+// keeps it pending, to be re-sent with the same memo and created_at_time.
+fn apply_claim_outcome(claim_id: u64, outcome: TransferOutcome, now: u64) -> Result<ClaimResult, String> {
+    match outcome {
+        TransferOutcome::Completed(block_index) => commit_claim(claim_id, block_index, now),
+        TransferOutcome::Rejected(error) => {
+            revert_claim(claim_id)?;
+            Err(error)
+        },
+        TransferOutcome::Unknown(error) => {
+            PENDING_CLAIMS.with(|claims| {
+                if let Some(claim) = claims.borrow_mut().get_mut(&claim_id) {
+                    claim.last_error = Some(error.clone());
+                }
+            });
+            Err(format!("Claim {} is pending, retry it to settle: {}", claim_id, error))
+        },
+    }
+}
+
+// The transfer went through: move the reserved amount into claimed
+fn commit_claim(claim_id: u64, block_index: u64, now: u64) -> Result<ClaimResult, String> {
+    let claim = PENDING_CLAIMS.with(|claims| claims.borrow_mut().remove(&claim_id))
+        .ok_or_else(|| "Pending claim not found".to_string())?;
+    
+    STREAMS.with(|streams| {
+        let mut borrowed = streams.borrow_mut();
+        let stream = borrowed.get_mut(&claim.stream_id).ok_or_else(|| "Stream not found".to_string())?;
+        
+        stream.pending_amount -= claim.amount;
+        stream.claimed_amount += claim.amount;
+        
+        // Check if stream is complete
+        if stream.outstanding() == 0 {
+            stream.is_active = false;
+        }
+        
+        let next_claim_time = if stream.is_active {
+            now + 1_000_000_000 // 1 second from now
+        } else {
+            0
+        };
+        
+        ic_cdk::println!("Claimed {} from stream {} for {} (block {})", claim.amount, claim.stream_id, claim.recipient.to_text(), block_index);
+        
+        SETTLED_CLAIMS.with(|settled| {
+            settled.borrow_mut().entry(claim.funder).or_default().push(SettledClaim {
+                claim_id,
+                stream_id: claim.stream_id,
+                recipient: claim.recipient,
+                ledger: claim.ledger,
+                amount: claim.amount - claim.fee,
+                block_index,
+                settled_at: now,
+            });
+        });
+        
+        Ok(ClaimResult {
+            stream_id: claim.stream_id,
+            claimed_amount: claim.amount,
+            remaining_amount: stream.outstanding(),
+            next_claim_time,
+        })
+    })
+}
+
+// The transfer did not happen: release the reservation so the amount can be claimed again
+fn revert_claim(claim_id: u64) -> Result<(), String> {
+    let claim = PENDING_CLAIMS.with(|claims| claims.borrow_mut().remove(&claim_id))
+        .ok_or_else(|| "Pending claim not found".to_string())?;
+    
+    STREAMS.with(|streams| {
+        if let Some(stream) = streams.borrow_mut().get_mut(&claim.stream_id) {
+            stream.pending_amount -= claim.amount;
             
-            // Update claimed amount
-            stream.claimed_amount += claimable;
-            
-            // Check if stream is complete
-            if stream.claimed_amount >= stream.total_amount || current_time >= stream.end_time {
-                stream.is_active = false;
+            // A resume while the claim was pending left the stream inactive if the claim covered the rest
+            if stream.current_pause().is_none() {
+                stream.is_active = stream.outstanding() > 0;
             }
-            
-            let remaining = stream.total_amount - stream.claimed_amount;
-            let next_claim_time = if stream.is_active {
-                current_time + 1_000_000_000 // 1 second from now
-            } else {
-                0
-            };
-            
-            ic_cdk::println!("Claimed {} from stream {} for {}", claimable, stream_id, caller.to_text());
-            
-            
-            // Can't use await within STREAMS.with, so we'll just prepare the data
-            return Ok((stream.vault_canister, stream.recipient.clone(), claimable, remaining, next_claim_time));
         }
-        
-        // Return an error if stream not found
-        Err("Stream not found".to_string())
     });
     
-    // Extract data from our stream
-    let (vault_canister, recipient, claimable_amount, remaining, next_claim_time) = stream_data?;
+    Ok(())
+}
+
+// Escrow
+
+// Each funder's streams on a ledger share one subaccount of this canister, derived from the funder
+fn escrow_subaccount(funder: Principal) -> Vec<u8> {
+    let bytes = funder.as_slice();
+    let mut subaccount = vec![0u8; 32];
+    subaccount[0] = bytes.len() as u8;
+    subaccount[1..1 + bytes.len()].copy_from_slice(bytes);
+    subaccount
+}
+
+fn escrow_account(funder: Principal) -> Account {
+    Account { owner: canister_self(), subaccount: Some(escrow_subaccount(funder)) }
+}
+
+// Pulls `amount` from the funder into their escrow subaccount and returns the ledger block index
+async fn fund_escrow(ledger: Principal, funder: Principal, amount: u64) -> Result<u64, String> {
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account { owner: funder, subaccount: None },
+        to: escrow_account(funder),
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: Some(time()),
+    };
     
-    // Now we can use await outside the closure - using the deprecated API but we can fix this later
-    let res: Result<(), _> = ic_cdk::api::call::call(
-        vault_canister, 
-        "transfer",
-        (recipient, claimable_amount)
+    let result: CallResult<(Result<Nat, TransferFromError>,)> = call(
+        ledger,
+        "icrc2_transfer_from",
+        (args,),
     ).await;
     
-    res.map_err(|e| format!("Transfer failed: {:?}", e))?;
+    match result {
+        Ok((Ok(block_index),)) => u64::try_from(block_index.0)
+            .map_err(|_| "Ledger block index does not fit in u64".to_string()),
+        Ok((Err(e),)) => Err(format!("Could not fund stream escrow: {:?}", e)),
+        Err(e) => Err(format!("Failed to call ledger: {:?}", e)),
+    }
+}
+
+// Sends `amount` from the funder's escrow. The memo and `created_at_time` are fixed per claim,
+// so re-sending a claim that already went through comes back as a duplicate of its block
+async fn escrow_transfer(
+    ledger: Principal,
+    funder: Principal,
+    to: Principal,
+    amount: u64,
+    fee: u64,
+    memo: u64,
+    created_at_time: u64,
+) -> TransferOutcome {
+    let args = TransferArg {
+        from_subaccount: Some(escrow_subaccount(funder)),
+        to: Account { owner: to, subaccount: None },
+        amount: Nat::from(amount),
+        fee: Some(Nat::from(fee)),
+        memo: Some(memo.to_be_bytes().to_vec()),
+        created_at_time: Some(created_at_time),
+    };
     
-    // Return the result
-    Ok(ClaimResult {
-        stream_id,
-        claimed_amount: claimable_amount,
-        remaining_amount: remaining,
-        next_claim_time,
+    let result: CallResult<(Result<Nat, TransferError>,)> = call(
+        ledger,
+        "icrc1_transfer",
+        (args,),
+    ).await;
+    
+    match result {
+        Ok((Ok(block_index),)) | Ok((Err(TransferError::Duplicate { duplicate_of: block_index }),)) => {
+            match u64::try_from(block_index.0) {
+                Ok(block_index) => TransferOutcome::Completed(block_index),
+                Err(_) => TransferOutcome::Unknown("Ledger block index does not fit in u64".to_string()),
+            }
+        },
+        // Past the ledger's deduplication window a retry can't tell whether the first attempt landed
+        Ok((Err(TransferError::TooOld),)) => TransferOutcome::Unknown(
+            "Claim is too old for the ledger to deduplicate, the admin has to resolve it".to_string()
+        ),
+        Ok((Err(e),)) => TransferOutcome::Rejected(format!("Escrow transfer failed: {:?}", e)),
+        Err(e) => TransferOutcome::Unknown(format!("Failed to call ledger: {:?}", e)),
+    }
+}
+
+// Inter-canister calls with the arguments and results as tuples, on ic-cdk's unbounded-wait Call API
+async fn call<A: ArgumentEncoder, R: for<'de> ArgumentDecoder<'de>>(
+    canister: Principal,
+    method: &str,
+    args: A,
+) -> CallResult<R> {
+    Ok(Call::unbounded_wait(canister, method).with_args(&args).await?.candid_tuple()?)
+}
+
+async fn ledger_fee(ledger: Principal) -> Result<u64, String> {
+    let result: CallResult<(Nat,)> = call(ledger, "icrc1_fee", ()).await;
+    let fee = result.map_err(|e| format!("Failed to query ledger fee: {:?}", e))?.0;
+    u64::try_from(fee.0).map_err(|_| "Ledger fee does not fit in u64".to_string())
+}
+
+// What the funder's streams on `ledger` still owe, across active and paused streams
+fn escrow_obligations(funder: Principal, ledger: Principal) -> u64 {
+    STREAMS.with(|streams| {
+        streams.borrow().values()
+            .filter(|s| s.funder == funder && s.ledger == ledger)
+            .map(|s| s.outstanding())
+            .sum()
+    })
+}
+
+// Compares a funder's escrow balance on the ledger with what their streams still owe
+#[update]
+async fn check_escrow_coverage(funder: Principal, ledger: Principal) -> Result<EscrowCoverage, String> {
+    let escrow = escrow_account(funder);
+    
+    let result: CallResult<(Nat,)> = call(ledger, "icrc1_balance_of", (escrow.clone(),)).await;
+    let balance = result.map_err(|e| format!("Failed to call ledger: {:?}", e))?.0;
+    let balance = u64::try_from(balance.0).map_err(|_| "Escrow balance does not fit in u64".to_string())?;
+    
+    // Read after the await so the obligations match the balance just fetched
+    let obligations = escrow_obligations(funder, ledger);
+    
+    Ok(EscrowCoverage {
+        funder,
+        ledger,
+        escrow,
+        obligations,
+        balance,
+        shortfall: obligations.saturating_sub(balance),
     })
 }
 
+// Sends the funder what their escrow on `ledger` holds beyond their streams' obligations, such as
+// remainders returned by streams closed below the ledger fee
+#[update]
+async fn withdraw_escrow_surplus(funder: Principal, ledger: Principal) -> Result<u64, String> {
+    let caller = msg_caller();
+    if caller != funder && !is_governance(caller) {
+        return Err("Only the funder or governance can withdraw an escrow surplus".to_string());
+    }
+    let _guard = EscrowWithdrawalGuard::acquire(funder, ledger)?;
+    
+    let fee = ledger_fee(ledger).await?;
+    let coverage = check_escrow_coverage(funder, ledger).await?;
+    let amount = surplus_after_fee(&coverage, fee)?;
+    
+    // The balance is read again on every call, so a withdrawal with an unknown outcome can't be paid twice
+    match escrow_transfer(ledger, funder, funder, amount, fee, 0, time()).await {
+        TransferOutcome::Completed(_) => Ok(amount),
+        TransferOutcome::Rejected(error) | TransferOutcome::Unknown(error) => Err(error),
+    }
+}
+
+// What the funder receives from their escrow surplus once the ledger fee is paid out of it
+fn surplus_after_fee(coverage: &EscrowCoverage, fee: u64) -> Result<u64, String> {
+    let surplus = coverage.balance.saturating_sub(coverage.obligations);
+    if surplus <= fee {
+        return Err(format!("Escrow surplus {} does not cover the ledger fee of {}", surplus, fee));
+    }
+    Ok(surplus - fee)
+}
+
+// Keeps one surplus withdrawal per escrow in flight, so two calls can't both spend the same balance
+struct EscrowWithdrawalGuard {
+    escrow: (Principal, Principal),
+}
+
+impl EscrowWithdrawalGuard {
+    fn acquire(funder: Principal, ledger: Principal) -> Result<Self, String> {
+        WITHDRAWING_ESCROWS.with(|withdrawing| {
+            if withdrawing.borrow_mut().insert((funder, ledger)) {
+                Ok(EscrowWithdrawalGuard { escrow: (funder, ledger) })
+            } else {
+                Err("A surplus withdrawal from this escrow is already in progress".to_string())
+            }
+        })
+    }
+}
+
+impl Drop for EscrowWithdrawalGuard {
+    fn drop(&mut self) {
+        WITHDRAWING_ESCROWS.with(|withdrawing| {
+            withdrawing.borrow_mut().remove(&self.escrow);
+        });
+    }
+}
+
+#[query]
+fn get_escrow_obligations() -> Vec<EscrowObligation> {
+    let mut obligations: Vec<EscrowObligation> = Vec::new();
+    
+    STREAMS.with(|streams| {
+        for stream in streams.borrow().values() {
+            let outstanding = stream.outstanding();
+            match obligations.iter_mut().find(|o| o.funder == stream.funder && o.ledger == stream.ledger) {
+                Some(obligation) => {
+                    obligation.streams += 1;
+                    obligation.outstanding += outstanding;
+                },
+                None => obligations.push(EscrowObligation {
+                    funder: stream.funder,
+                    ledger: stream.ledger,
+                    streams: 1,
+                    outstanding,
+                }),
+            }
+        }
+    });
+    
+    obligations
+}
+
 fn calculate_claimable_amount(stream: &Stream, current_time: u64) -> u64 {
     if current_time < stream.start_time {
         return 0;
     }
     
-    let elapsed_time = if current_time >= stream.end_time {
-        stream.end_time - stream.start_time
-    } else {
-        current_time - stream.start_time
-    };
+    // The vesting clock stops while the stream is paused and skips the pauses behind it
+    let clock = stream.current_pause().map(|pause| pause.paused_at).unwrap_or(current_time).min(stream.end_time);
+    let vesting_time = stream.end_time - stream.start_time - stream.paused_duration;
+    let elapsed_time = (clock - stream.start_time - stream.paused_duration).min(vesting_time);
     
-    let elapsed_seconds = elapsed_time / 1_000_000_000; // Convert from nanoseconds
+    let total_vested = vested_amount(&stream.stream_type, stream.total_amount, elapsed_time, vesting_time);
     
-    let total_vested = match stream.stream_type {
-        StreamType::Linear => {
-            let total_duration = (stream.end_time - stream.start_time) / 1_000_000_000;
-            if elapsed_seconds >= total_duration {
-                stream.total_amount
-            } else {
-                (stream.total_amount * elapsed_seconds) / total_duration
-            }
-        }
-        StreamType::Cliff => {
-            if current_time >= stream.end_time {
-                stream.total_amount
-            } else {
+    // Return claimable amount (total vested minus already claimed or reserved by a pending claim)
+    total_vested.saturating_sub(stream.claimed_amount + stream.pending_amount)
+}
+
+// Integer-only, so every schedule vests exactly `total_amount` once `elapsed` reaches `duration` (both in nanoseconds)
+fn vested_amount(stream_type: &StreamType, total_amount: u64, elapsed: u64, duration: u64) -> u64 {
+    if elapsed >= duration {
+        return total_amount;
+    }
+    
+    match stream_type {
+        StreamType::Linear => mul_div(total_amount, elapsed, duration),
+        StreamType::Cliff => 0,
+        StreamType::CliffLinear { cliff_seconds } => {
+            if elapsed < cliff_seconds * NANOS_PER_SECOND {
                 0
+            } else {
+                mul_div(total_amount, elapsed, duration)
             }
-        }
-        StreamType::Exponential => {
-            // Exponential decay formula
-            let total_duration = (stream.end_time - stream.start_time) / 1_000_000_000;
-            let progress = elapsed_seconds as f64 / total_duration as f64;
-            let vested_ratio = 1.0 - (-2.0 * progress).exp(); // Exponential curve
-            (stream.total_amount as f64 * vested_ratio) as u64
-        }
-    };
-    
-    // Return claimable amount (total vested minus already claimed)
-    if total_vested > stream.claimed_amount {
-        total_vested - stream.claimed_amount
-    } else {
-        0
+        },
+        StreamType::Steps { interval_seconds } => {
+            // The last tranche may cover a shorter interval, it unlocks at the end
+            let interval = interval_seconds * NANOS_PER_SECOND;
+            let steps = duration.div_ceil(interval);
+            mul_div(total_amount, elapsed / interval, steps)
+        },
+        StreamType::Custom { breakpoints } => {
+            // Implicit breakpoints at the start (nothing vested) and the end (everything vested)
+            let (from_offset, from_bps) = breakpoints.iter()
+                .rev()
+                .map(|point| (point.offset_seconds * NANOS_PER_SECOND, point.vested_bps as u64))
+                .find(|(offset, _)| *offset <= elapsed)
+                .unwrap_or((0, 0));
+            let (to_offset, to_bps) = breakpoints.iter()
+                .map(|point| (point.offset_seconds * NANOS_PER_SECOND, point.vested_bps as u64))
+                .find(|(offset, _)| *offset > elapsed)
+                .unwrap_or((duration, BPS_DENOMINATOR as u64));
+            
+            // Vested at the segment start plus the segment's share accrued so far, each product fits in u128
+            let at_from = mul_div(total_amount, from_bps, BPS_DENOMINATOR as u64);
+            let segment_amount = mul_div(total_amount, to_bps - from_bps, BPS_DENOMINATOR as u64);
+            at_from + mul_div(segment_amount, elapsed - from_offset, to_offset - from_offset)
+        },
     }
 }
 
+fn mul_div(amount: u64, numerator: u64, denominator: u64) -> u64 {
+    (amount as u128 * numerator as u128 / denominator as u128) as u64
+}
+
 #[query]
 fn get_stream(stream_id: StreamId) -> Option<Stream> {
     STREAMS.with(|streams| streams.borrow().get(&stream_id).cloned())
@@ -288,7 +1150,7 @@ fn get_claimable_amount(stream_id: StreamId) -> u64 {
     
     STREAMS.with(|streams| {
         if let Some(stream) = streams.borrow().get(&stream_id) {
-            calculate_claimable_amount(&stream, current_time)
+            calculate_claimable_amount(stream, current_time)
         } else {
             0
         }
@@ -298,7 +1160,7 @@ fn get_claimable_amount(stream_id: StreamId) -> u64 {
 #[query]
 fn get_stream_stats() -> StreamStats {
     STREAMS.with(|streams| {
-        let all_streams: Vec<Stream> = streams.borrow().iter().map(|(_, stream)| stream.clone()).collect();
+        let all_streams: Vec<Stream> = streams.borrow().values().cloned().collect();
         
         let total_streams = all_streams.len() as u64;
         let active_streams = all_streams.iter().filter(|s| s.is_active).count() as u64;
@@ -315,7 +1177,7 @@ fn get_stream_stats() -> StreamStats {
 }
 
 #[update]
-fn pause_stream(stream_id: StreamId) -> Result<(), String> {
+fn pause_stream(stream_id: StreamId, reason: String) -> Result<(), String> {
     let caller = msg_caller();
     
     STREAMS.with(|streams| {
@@ -326,7 +1188,23 @@ fn pause_stream(stream_id: StreamId) -> Result<(), String> {
                 return Err("Unauthorized to pause stream".to_string());
             }
             
+            if stream.current_pause().is_some() {
+                return Err("Stream is already paused".to_string());
+            }
+            
+            if !stream.is_active {
+                return Err("Stream is not active".to_string());
+            }
+            
             stream.is_active = false;
+            stream.pauses.push(PauseInterval {
+                paused_at: time(),
+                paused_by: caller,
+                pause_reason: reason,
+                resumed_at: None,
+                resumed_by: None,
+                resume_reason: None,
+            });
             Ok(())
         } else {
             Err("Stream not found".to_string())
@@ -335,8 +1213,9 @@ fn pause_stream(stream_id: StreamId) -> Result<(), String> {
 }
 
 #[update]
-fn resume_stream(stream_id: StreamId) -> Result<(), String> {
+fn resume_stream(stream_id: StreamId, reason: String) -> Result<(), String> {
     let caller = msg_caller();
+    let now = time();
     
     STREAMS.with(|streams| {
         let mut borrowed = streams.borrow_mut();
@@ -346,7 +1225,21 @@ fn resume_stream(stream_id: StreamId) -> Result<(), String> {
                 return Err("Only vault canister can resume stream".to_string());
             }
             
-            if stream.claimed_amount < stream.total_amount {
+            let pause = match stream.pauses.last_mut() {
+                Some(pause) if pause.resumed_at.is_none() => pause,
+                _ => return Err("Stream is not paused".to_string()),
+            };
+            
+            // Vesting resumes where it froze, a pause taken after end_time shifts nothing
+            let paused_for = if pause.paused_at < stream.end_time { now - pause.paused_at } else { 0 };
+            pause.resumed_at = Some(now);
+            pause.resumed_by = Some(caller);
+            pause.resume_reason = Some(reason);
+            
+            stream.paused_duration += paused_for;
+            stream.end_time += paused_for;
+            
+            if stream.pending_amount < stream.outstanding() {
                 stream.is_active = true;
             }
             Ok(())
@@ -355,4 +1248,508 @@ fn resume_stream(stream_id: StreamId) -> Result<(), String> {
         }
     })
 }
-ic_cdk::export_candid!();
\ No newline at end of file
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    const SECOND: u64 = NANOS_PER_SECOND;
+    const FEE: u64 = 10;
+    
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+    
+    // A 100 second stream to principal(1), funded by principal(9) on ledger principal(8)
+    fn insert_stream(total_amount: u64, stream_type: StreamType) -> StreamId {
+        let stream_id = STREAMS.with(|streams| streams.borrow().len() as u64 + 1);
+        let stream = Stream {
+            stream_id,
+            recipient: principal(1),
+            total_amount,
+            amount_per_second: total_amount / 100,
+            start_time: 0,
+            end_time: 100 * SECOND,
+            claimed_amount: 0,
+            pending_amount: 0,
+            campaign_id: 1,
+            vault_canister: principal(9),
+            is_active: true,
+            stream_type,
+            ledger: principal(8),
+            funder: principal(9),
+            funding_block_index: 0,
+            paused_duration: 0,
+            pauses: Vec::new(),
+            returned_to_funder: 0,
+        };
+        STREAMS.with(|streams| streams.borrow_mut().insert(stream_id, stream));
+        stream_id
+    }
+    
+    fn stream(stream_id: StreamId) -> Stream {
+        STREAMS.with(|streams| streams.borrow()[&stream_id].clone())
+    }
+    
+    fn pending_claim(claim_id: u64) -> Option<PendingClaim> {
+        PENDING_CLAIMS.with(|claims| claims.borrow().get(&claim_id).cloned())
+    }
+    
+    #[test]
+    fn completed_claim_moves_the_reservation_into_claimed() {
+        let stream_id = insert_stream(1_000, StreamType::Linear);
+        
+        let claim = reserve_claim(stream_id, principal(1), FEE, 50 * SECOND).unwrap().unwrap();
+        assert_eq!(claim.amount, 500);
+        assert_eq!(stream(stream_id).pending_amount, 500);
+        
+        let result = apply_claim_outcome(claim.claim_id, TransferOutcome::Completed(7), 50 * SECOND).unwrap();
+        assert_eq!(result.claimed_amount, 500);
+        assert_eq!(result.remaining_amount, 500);
+        
+        let stream = stream(stream_id);
+        assert_eq!((stream.claimed_amount, stream.pending_amount), (500, 0));
+        assert!(pending_claim(claim.claim_id).is_none());
+    }
+    
+    #[test]
+    fn rejected_claim_releases_the_reservation() {
+        let stream_id = insert_stream(1_000, StreamType::Linear);
+        
+        let claim = reserve_claim(stream_id, principal(1), FEE, 50 * SECOND).unwrap().unwrap();
+        let outcome = TransferOutcome::Rejected("InsufficientFunds".to_string());
+        assert!(apply_claim_outcome(claim.claim_id, outcome, 50 * SECOND).is_err());
+        
+        let stream = stream(stream_id);
+        assert_eq!((stream.claimed_amount, stream.pending_amount), (0, 0));
+        assert!(pending_claim(claim.claim_id).is_none());
+        
+        // Nothing moved, so the same amount can be claimed again
+        let again = reserve_claim(stream_id, principal(1), FEE, 50 * SECOND).unwrap().unwrap();
+        assert_eq!(again.amount, 500);
+    }
+    
+    #[test]
+    fn unknown_claim_stays_pending_until_a_retry_settles_it_once() {
+        let stream_id = insert_stream(1_000, StreamType::Linear);
+        
+        let claim = reserve_claim(stream_id, principal(1), FEE, 50 * SECOND).unwrap().unwrap();
+        let outcome = TransferOutcome::Unknown("Failed to call ledger".to_string());
+        assert!(apply_claim_outcome(claim.claim_id, outcome, 50 * SECOND).is_err());
+        
+        // The amount stays reserved and the retry re-sends the same transfer
+        let pending = pending_claim(claim.claim_id).unwrap();
+        assert_eq!(pending.created_at_time, claim.created_at_time);
+        assert_eq!(pending.amount, 500);
+        assert!(pending.last_error.is_some());
+        assert_eq!(stream(stream_id).pending_amount, 500);
+        assert!(reserve_claim(stream_id, principal(1), FEE, 50 * SECOND).is_err());
+        
+        // The ledger reports the first attempt as a duplicate, which settles the claim
+        apply_claim_outcome(claim.claim_id, TransferOutcome::Completed(7), 60 * SECOND).unwrap();
+        assert_eq!(stream(stream_id).claimed_amount, 500);
+        assert!(apply_claim_outcome(claim.claim_id, TransferOutcome::Completed(7), 60 * SECOND).is_err());
+        assert_eq!(stream(stream_id).claimed_amount, 500);
+    }
+    
+    #[test]
+    fn final_claim_sweeps_the_remainder_below_the_fee() {
+        let stream_id = insert_stream(1_000, StreamType::Linear);
+        
+        // 995 has vested, the 5 left could never be claimed on its own
+        let claim = reserve_claim(stream_id, principal(1), FEE, 99_500_000_000).unwrap().unwrap();
+        assert_eq!(claim.amount, 1_000);
+        
+        let result = apply_claim_outcome(claim.claim_id, TransferOutcome::Completed(7), 99_500_000_000).unwrap();
+        assert_eq!(result.remaining_amount, 0);
+        assert!(!stream(stream_id).is_active);
+    }
+    
+    #[test]
+    fn remainder_below_the_fee_closes_the_stream_and_returns_to_the_funder() {
+        let stream_id = insert_stream(1_000, StreamType::Linear);
+        STREAMS.with(|streams| streams.borrow_mut().get_mut(&stream_id).unwrap().claimed_amount = 995);
+        
+        assert!(reserve_claim(stream_id, principal(1), FEE, 100 * SECOND).unwrap().is_none());
+        
+        let stream = stream(stream_id);
+        assert!(!stream.is_active);
+        assert_eq!(stream.returned_to_funder, 5);
+        assert_eq!(stream.outstanding(), 0);
+        assert_eq!(escrow_obligations(principal(9), principal(8)), 0);
+    }
+    
+    fn pause(stream: &mut Stream, paused_at: u64) {
+        stream.is_active = false;
+        stream.pauses.push(PauseInterval {
+            paused_at,
+            paused_by: principal(9),
+            pause_reason: "test".to_string(),
+            resumed_at: None,
+            resumed_by: None,
+            resume_reason: None,
+        });
+    }
+    
+    // What resume_stream records for a pause that started before end_time
+    fn resume(stream: &mut Stream, resumed_at: u64) {
+        let pause = stream.pauses.last_mut().unwrap();
+        let paused_for = resumed_at - pause.paused_at;
+        pause.resumed_at = Some(resumed_at);
+        stream.paused_duration += paused_for;
+        stream.end_time += paused_for;
+        stream.is_active = true;
+    }
+    
+    #[test]
+    fn vesting_freezes_while_the_stream_is_paused() {
+        let mut stream = stream(insert_stream(1_000, StreamType::Linear));
+        pause(&mut stream, 30 * SECOND);
+        
+        assert_eq!(calculate_claimable_amount(&stream, 30 * SECOND), 300);
+        assert_eq!(calculate_claimable_amount(&stream, 80 * SECOND), 300);
+        // Still frozen past the original end
+        assert_eq!(calculate_claimable_amount(&stream, 500 * SECOND), 300);
+    }
+    
+    #[test]
+    fn vesting_continues_from_where_it_froze_after_resume() {
+        let mut stream = stream(insert_stream(1_000, StreamType::Linear));
+        pause(&mut stream, 30 * SECOND);
+        resume(&mut stream, 50 * SECOND);
+        
+        assert_eq!(stream.end_time, 120 * SECOND);
+        assert_eq!(calculate_claimable_amount(&stream, 50 * SECOND), 300);
+        assert_eq!(calculate_claimable_amount(&stream, 60 * SECOND), 400);
+        assert_eq!(calculate_claimable_amount(&stream, 119 * SECOND), 990);
+        assert_eq!(calculate_claimable_amount(&stream, 120 * SECOND), 1_000);
+    }
+    
+    #[test]
+    fn repeated_pauses_add_up() {
+        let mut stream = stream(insert_stream(1_000, StreamType::Linear));
+        pause(&mut stream, 10 * SECOND);
+        resume(&mut stream, 20 * SECOND);
+        pause(&mut stream, 40 * SECOND);
+        
+        // 30 seconds have vested: 10 before the first pause, 20 between the pauses
+        assert_eq!(calculate_claimable_amount(&stream, 90 * SECOND), 300);
+        
+        resume(&mut stream, 100 * SECOND);
+        assert_eq!(stream.paused_duration, 70 * SECOND);
+        assert_eq!(calculate_claimable_amount(&stream, 110 * SECOND), 400);
+        assert_eq!(calculate_claimable_amount(&stream, 170 * SECOND), 1_000);
+    }
+    
+    #[test]
+    fn a_cliff_does_not_unlock_while_paused() {
+        let mut stream = stream(insert_stream(1_000, StreamType::CliffLinear { cliff_seconds: 40 }));
+        pause(&mut stream, 30 * SECOND);
+        assert_eq!(calculate_claimable_amount(&stream, 60 * SECOND), 0);
+        
+        resume(&mut stream, 60 * SECOND);
+        // The cliff is measured in vesting time, reached 10 seconds after the resume
+        assert_eq!(calculate_claimable_amount(&stream, 69 * SECOND), 0);
+        assert_eq!(calculate_claimable_amount(&stream, 70 * SECOND), 400);
+    }
+    
+    fn vested(stream_type: &StreamType, total_amount: u64, elapsed_seconds: u64) -> u64 {
+        vested_amount(stream_type, total_amount, elapsed_seconds * SECOND, 100 * SECOND)
+    }
+    
+    #[test]
+    fn cliff_linear_unlocks_the_accrued_amount_at_the_cliff() {
+        let schedule = StreamType::CliffLinear { cliff_seconds: 25 };
+        
+        assert_eq!(vested_amount(&schedule, 1_000, 25 * SECOND - 1, 100 * SECOND), 0);
+        assert_eq!(vested(&schedule, 1_000, 25), 250);
+        assert_eq!(vested(&schedule, 1_000, 60), 600);
+        assert_eq!(vested(&schedule, 1_000, 100), 1_000);
+    }
+    
+    #[test]
+    fn steps_unlock_at_interval_boundaries_with_a_shorter_last_step() {
+        // 30 second steps over 100 seconds: three full steps and a 10 second one
+        let schedule = StreamType::Steps { interval_seconds: 30 };
+        
+        assert_eq!(vested_amount(&schedule, 1_000, 30 * SECOND - 1, 100 * SECOND), 0);
+        assert_eq!(vested(&schedule, 1_000, 30), 250);
+        assert_eq!(vested(&schedule, 1_000, 60), 500);
+        assert_eq!(vested(&schedule, 1_000, 90), 750);
+        assert_eq!(vested_amount(&schedule, 1_000, 100 * SECOND - 1, 100 * SECOND), 750);
+        assert_eq!(vested(&schedule, 1_000, 100), 1_000);
+    }
+    
+    #[test]
+    fn custom_breakpoints_interpolate_between_points() {
+        let schedule = StreamType::Custom {
+            breakpoints: vec![
+                VestingPoint { offset_seconds: 20, vested_bps: 5_000 },
+                VestingPoint { offset_seconds: 60, vested_bps: 6_000 },
+            ],
+        };
+        
+        assert_eq!(vested(&schedule, 1_000, 0), 0);
+        assert_eq!(vested(&schedule, 1_000, 10), 250);
+        assert_eq!(vested(&schedule, 1_000, 20), 500);
+        assert_eq!(vested(&schedule, 1_000, 40), 550);
+        assert_eq!(vested(&schedule, 1_000, 60), 600);
+        assert_eq!(vested(&schedule, 1_000, 80), 800);
+        assert_eq!(vested(&schedule, 1_000, 100), 1_000);
+    }
+    
+    #[test]
+    fn schedules_do_not_overflow_on_large_amounts_and_durations() {
+        // A u64::MAX stream over ten years
+        let duration = 10 * 365 * 24 * 60 * 60 * SECOND;
+        let schedules = [
+            StreamType::Linear,
+            StreamType::Cliff,
+            StreamType::CliffLinear { cliff_seconds: 365 * 24 * 60 * 60 },
+            StreamType::Steps { interval_seconds: 7 * 24 * 60 * 60 },
+            StreamType::Custom {
+                breakpoints: vec![
+                    VestingPoint { offset_seconds: 24 * 60 * 60, vested_bps: 1 },
+                    VestingPoint { offset_seconds: 9 * 365 * 24 * 60 * 60, vested_bps: 9_999 },
+                ],
+            },
+        ];
+        
+        for schedule in &schedules {
+            let mut previous = 0;
+            for elapsed in (0..10).map(|tenth| duration / 10 * tenth).chain([duration - 1]) {
+                let vested = vested_amount(schedule, u64::MAX, elapsed, duration);
+                assert!(vested >= previous, "{:?} unvested at {}", schedule, elapsed);
+                assert!(vested < u64::MAX, "{:?} vested everything early at {}", schedule, elapsed);
+                previous = vested;
+            }
+            assert_eq!(vested_amount(schedule, u64::MAX, duration, duration), u64::MAX);
+        }
+    }
+    
+    // Runs the upgrade hooks around a wipe of the heap, as an upgrade does
+    fn upgrade() {
+        pre_upgrade();
+        STREAMS.with(|streams| streams.borrow_mut().clear());
+        USER_STREAMS.with(|user_streams| user_streams.borrow_mut().clear());
+        STREAM_COUNTER.with(|counter| *counter.borrow_mut() = 0);
+        PENDING_CLAIMS.with(|claims| claims.borrow_mut().clear());
+        CLAIM_COUNTER.with(|counter| *counter.borrow_mut() = 0);
+        SETTLED_CLAIMS.with(|settled| settled.borrow_mut().clear());
+        CONFIG.with(|c| *c.borrow_mut() = None);
+        AUTHORIZED_FUNDERS.with(|funders| funders.borrow_mut().clear());
+        FROZEN_FUNDERS.with(|frozen_funders| frozen_funders.borrow_mut().clear());
+        post_upgrade();
+    }
+    
+    #[test]
+    fn upgrade_keeps_streams_and_config() {
+        let stream_id = insert_stream(1_000, StreamType::Steps { interval_seconds: 30 });
+        USER_STREAMS.with(|user_streams| user_streams.borrow_mut().insert(principal(1), vec![stream_id]));
+        STREAM_COUNTER.with(|counter| *counter.borrow_mut() = stream_id);
+        CONFIG.with(|c| *c.borrow_mut() = Some(StreamConfig {
+            admin: principal(7),
+            campaign_factory: Some(principal(6)),
+            dao_canister: None,
+        }));
+        let before = stream(stream_id);
+        
+        upgrade();
+        
+        let after = stream(stream_id);
+        assert_eq!((after.funder, after.ledger, after.total_amount), (before.funder, before.ledger, before.total_amount));
+        assert_eq!(after.stream_type, before.stream_type);
+        assert_eq!(USER_STREAMS.with(|user_streams| user_streams.borrow()[&principal(1)].clone()), vec![stream_id]);
+        assert_eq!(STREAM_COUNTER.with(|counter| *counter.borrow()), stream_id);
+        assert!(is_admin(principal(7)));
+        
+        // Escrow obligations are rebuilt from the restored streams
+        assert_eq!(escrow_obligations(principal(9), principal(8)), 1_000);
+    }
+    
+    #[test]
+    fn unknown_claim_survives_an_upgrade_and_claim_ids_are_not_reused() {
+        let stream_id = insert_stream(1_000, StreamType::Linear);
+        let claim = reserve_claim(stream_id, principal(1), FEE, 50 * SECOND).unwrap().unwrap();
+        let outcome = TransferOutcome::Unknown("Failed to call ledger".to_string());
+        assert!(apply_claim_outcome(claim.claim_id, outcome, 50 * SECOND).is_err());
+        
+        upgrade();
+        
+        // The retry finds the claim and re-sends it with the same dedup values
+        let pending = pending_claim(claim.claim_id).unwrap();
+        assert_eq!((pending.amount, pending.created_at_time), (claim.amount, claim.created_at_time));
+        assert_eq!(stream(stream_id).pending_amount, 500);
+        
+        let next = reserve_claim(stream_id, principal(1), FEE, 60 * SECOND).unwrap().unwrap();
+        assert!(next.claim_id > claim.claim_id);
+        
+        apply_claim_outcome(claim.claim_id, TransferOutcome::Completed(7), 60 * SECOND).unwrap();
+        assert_eq!(stream(stream_id).claimed_amount, 500);
+    }
+    
+    #[test]
+    fn upgrade_keeps_funder_authorizations_and_freezes() {
+        AUTHORIZED_FUNDERS.with(|funders| funders.borrow_mut().insert(principal(9), 4));
+        FROZEN_FUNDERS.with(|frozen_funders| frozen_funders.borrow_mut().insert(principal(9), "Guardian pause".to_string()));
+        assert!(funder_campaign(principal(5)).is_err());
+        
+        upgrade();
+        
+        assert_eq!(funder_campaign(principal(9)), Ok(4));
+        assert!(ensure_claims_open(principal(9)).is_err());
+        assert!(ensure_claims_open(principal(5)).is_ok());
+    }
+    
+    #[test]
+    fn only_settled_claims_are_kept_for_the_funder_to_book() {
+        let stream_id = insert_stream(1_000, StreamType::Linear);
+        
+        let rejected = reserve_claim(stream_id, principal(1), FEE, 20 * SECOND).unwrap().unwrap();
+        apply_claim_outcome(rejected.claim_id, TransferOutcome::Rejected("TooOld".to_string()), 20 * SECOND).unwrap_err();
+        let first = reserve_claim(stream_id, principal(1), FEE, 20 * SECOND).unwrap().unwrap();
+        apply_claim_outcome(first.claim_id, TransferOutcome::Unknown("Timeout".to_string()), 20 * SECOND).unwrap_err();
+        assert!(get_settled_claims(principal(9), 0, 10).is_empty());
+        
+        apply_claim_outcome(first.claim_id, TransferOutcome::Completed(7), 30 * SECOND).unwrap();
+        let second = reserve_claim(stream_id, principal(1), FEE, 40 * SECOND).unwrap().unwrap();
+        apply_claim_outcome(second.claim_id, TransferOutcome::Completed(8), 40 * SECOND).unwrap();
+        
+        upgrade();
+        
+        let settled = get_settled_claims(principal(9), 0, 10);
+        assert_eq!(settled.iter().map(|c| (c.claim_id, c.amount, c.block_index)).collect::<Vec<_>>(),
+            vec![(first.claim_id, 200 - FEE, 7), (second.claim_id, 200 - FEE, 8)]);
+        assert_eq!(settled[0].recipient, principal(1));
+        assert_eq!(get_settled_claims(principal(9), 1, 10).len(), 1);
+        assert!(get_settled_claims(principal(5), 0, 10).is_empty());
+    }
+    
+    fn open(recipient: u8, total_amount: u64, funder: u8, ledger: u8, funding_block_index: u64) -> StreamId {
+        open_stream(principal(recipient), total_amount, 100, 1, principal(funder), StreamType::Linear, principal(ledger), funding_block_index, 10 * SECOND)
+    }
+    
+    #[test]
+    fn funded_streams_add_to_their_funders_escrow_obligations() {
+        // A batch from one transfer, then streams of another ledger and another funder
+        let first = open(1, 1_000, 9, 8, 42);
+        let second = open(2, 500, 9, 8, 42);
+        open(1, 300, 9, 7, 43);
+        open(1, 200, 5, 8, 44);
+        
+        let stream = stream(first);
+        assert_eq!((stream.funder, stream.vault_canister, stream.funding_block_index), (principal(9), principal(9), 42));
+        assert_eq!((stream.start_time, stream.end_time), (10 * SECOND, 110 * SECOND));
+        assert_eq!(USER_STREAMS.with(|user_streams| user_streams.borrow()[&principal(2)].clone()), vec![second]);
+        
+        assert_eq!(escrow_obligations(principal(9), principal(8)), 1_500);
+        assert_eq!(escrow_obligations(principal(9), principal(7)), 300);
+        assert_eq!(escrow_obligations(principal(5), principal(8)), 200);
+        
+        let obligations = get_escrow_obligations();
+        let batch = obligations.iter().find(|o| o.funder == principal(9) && o.ledger == principal(8)).unwrap();
+        assert_eq!((batch.streams, batch.outstanding), (2, 1_500));
+        assert_eq!(obligations.len(), 3);
+    }
+    
+    #[test]
+    fn each_funder_has_its_own_escrow_subaccount() {
+        let escrow = escrow_subaccount(principal(9));
+        assert_eq!(escrow.len(), 32);
+        assert_eq!(&escrow[..2], &[1, 9]);
+        assert_ne!(escrow, escrow_subaccount(principal(5)));
+        assert_eq!(escrow, escrow_subaccount(principal(9)));
+    }
+    
+    #[test]
+    fn claim_is_paid_from_the_escrow_net_of_the_fee() {
+        let stream_id = open(1, 1_000, 9, 8, 42);
+        
+        let claim = reserve_claim(stream_id, principal(1), FEE, 60 * SECOND).unwrap().unwrap();
+        assert_eq!((claim.funder, claim.ledger, claim.amount, claim.fee), (principal(9), principal(8), 500, FEE));
+        // Reserved but still owed until the transfer lands
+        assert_eq!(escrow_obligations(principal(9), principal(8)), 1_000);
+        
+        apply_claim_outcome(claim.claim_id, TransferOutcome::Completed(7), 60 * SECOND).unwrap();
+        
+        // The escrow paid the recipient 490 and the ledger its fee
+        assert_eq!(escrow_obligations(principal(9), principal(8)), 500);
+        let settled = get_settled_claims(principal(9), 0, 10);
+        assert_eq!((settled[0].amount, settled[0].ledger, settled[0].settled_at), (500 - FEE, principal(8), 60 * SECOND));
+    }
+    
+    #[test]
+    fn only_the_surplus_beyond_the_obligations_can_be_withdrawn() {
+        let coverage = |balance| EscrowCoverage {
+            funder: principal(9),
+            ledger: principal(8),
+            escrow: Account { owner: principal(3), subaccount: Some(escrow_subaccount(principal(9))) },
+            obligations: 1_000,
+            balance,
+            shortfall: 1_000u64.saturating_sub(balance),
+        };
+        
+        assert_eq!(surplus_after_fee(&coverage(1_100), FEE), Ok(90));
+        assert!(surplus_after_fee(&coverage(1_000 + FEE), FEE).is_err());
+        assert!(surplus_after_fee(&coverage(900), FEE).is_err());
+    }
+    
+    // Admin principal(7), campaign factory principal(6), DAO principal(5)
+    fn configure() {
+        CONFIG.with(|c| *c.borrow_mut() = Some(StreamConfig {
+            admin: principal(7),
+            campaign_factory: Some(principal(6)),
+            dao_canister: Some(principal(5)),
+        }));
+    }
+    
+    #[test]
+    fn only_the_factory_or_governance_registers_funders() {
+        configure();
+        
+        assert!(authorize_funder(principal(9), principal(9), 1).is_err());
+        assert!(authorize_funder(principal(1), principal(9), 1).is_err());
+        assert!(funder_campaign(principal(9)).is_err());
+        
+        authorize_funder(principal(6), principal(9), 1).unwrap();
+        authorize_funder(principal(7), principal(3), 2).unwrap();
+        authorize_funder(principal(5), principal(4), 3).unwrap();
+        assert_eq!(funder_campaign(principal(9)), Ok(1));
+        assert_eq!(funder_campaign(principal(3)), Ok(2));
+        assert_eq!(get_authorized_funders().len(), 3);
+    }
+    
+    #[test]
+    fn revoked_funder_can_no_longer_fund_streams() {
+        configure();
+        authorize_funder(principal(6), principal(9), 1).unwrap();
+        
+        // The factory registers vaults but only governance revokes them
+        assert!(deauthorize_funder(principal(6), principal(9)).is_err());
+        assert!(deauthorize_funder(principal(9), principal(9)).is_err());
+        
+        deauthorize_funder(principal(5), principal(9)).unwrap();
+        assert!(funder_campaign(principal(9)).is_err());
+        assert!(deauthorize_funder(principal(7), principal(9)).is_err());
+    }
+    
+    #[test]
+    fn frozen_funder_blocks_claims_on_its_streams_until_unfrozen() {
+        configure();
+        
+        assert!(freeze_claims(principal(1), principal(9), true, "Guardian pause".to_string()).is_err());
+        assert!(freeze_claims(principal(9), principal(9), true, String::new()).is_err());
+        
+        // A vault freezes its own streams, governance any funder's
+        freeze_claims(principal(9), principal(9), true, "Guardian pause".to_string()).unwrap();
+        freeze_claims(principal(7), principal(4), true, "Under review".to_string()).unwrap();
+        assert_eq!(ensure_claims_open(principal(9)), Err("Claims on this stream are frozen: Guardian pause".to_string()));
+        assert!(ensure_claims_open(principal(4)).is_err());
+        assert!(ensure_claims_open(principal(3)).is_ok());
+        assert_eq!(get_frozen_funders().len(), 2);
+        
+        freeze_claims(principal(9), principal(9), false, String::new()).unwrap();
+        assert!(ensure_claims_open(principal(9)).is_ok());
+    }
+}
@@ -4,12 +4,11 @@ use ic_cdk_macros::*;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::cell::RefCell;
-use ark_bn254::{Bn254, Fr, G1Affine, G2Affine};
-use ark_ff::{FromBytes, PrimeField};
+use ark_bn254::{Bn254, Fr};
+use ark_ff::FromBytes;
 use ark_groth16::{prepare_verifying_key, verify_proof, Proof, VerifyingKey};
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_serialize::CanonicalDeserialize;
 use sha2::{Sha256, Digest};
-use hex;
 
 
 
@@ -25,6 +24,7 @@ pub struct TokenMetadata {
     pub share_percentage: f64,
     pub metadata_json: String,
     pub created_at: u64,
+    pub retired_at: Option<u64>, // Set when the campaign's vault settles; retired tokens stay readable but frozen
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -89,7 +89,7 @@ thread_local! {
     static TOKENS: RefCell<HashMap<TokenId, TokenMetadata>> = RefCell::new(HashMap::new());
     static TOKEN_APPROVALS: RefCell<HashMap<TokenId, Principal>> = RefCell::new(HashMap::new());
     static OPERATOR_APPROVALS: RefCell<HashMap<(Principal, Principal), bool>> = RefCell::new(HashMap::new());
-    static TOKEN_COUNTER: RefCell<TokenId> = RefCell::new(0);
+    static TOKEN_COUNTER: RefCell<TokenId> = const { RefCell::new(0) };
     static COLLECTION_METADATA: RefCell<CollectionMetadata> = RefCell::new(
         CollectionMetadata {
             name: "IPX Campaign NFTs".to_string(),
@@ -107,7 +107,7 @@ thread_local! {
     static VERIFIER_KEYS: RefCell<HashMap<ProofType, Vec<u8>>> = RefCell::new(HashMap::new());
     
     // Legacy verifier key for backward compatibility
-    static VERIFIER_KEY: RefCell<Option<Vec<u8>>> = RefCell::new(None);
+    static VERIFIER_KEY: RefCell<Option<Vec<u8>>> = const { RefCell::new(None) };
     
     // YouTube metrics storage
     static YOUTUBE_METRICS: RefCell<HashMap<String, YouTubeMetrics>> = RefCell::new(HashMap::new());
@@ -116,6 +116,9 @@ thread_local! {
     static ADMINS: RefCell<Vec<Principal>> = RefCell::new(vec![
         Principal::from_text("sgymv-uiaaa-aaaaa-aaaia-cai").unwrap(), 
     ]);
+    
+    // Campaign vaults allowed to mint and retire their backers' tokens
+    static REGISTERED_VAULTS: RefCell<Vec<Principal>> = const { RefCell::new(Vec::new()) };
 }
 
 #[init]
@@ -192,6 +195,10 @@ fn icrc7_transfer(args: TransferArgs) -> Result<TokenId, String> {
                 return Err("From address doesn't match token owner".to_string());
             }
             
+            if token_data.retired_at.is_some() {
+                return Err("Token is retired and can no longer be transferred".to_string());
+            }
+            
             // Update ownership
             token_data.owner = args.to;
             
@@ -216,7 +223,7 @@ fn icrc7_transfer(args: TransferArgs) -> Result<TokenId, String> {
 // Helper function to check if a caller is approved for a token
 fn is_approved(token_id: TokenId, caller: Principal) -> bool {
     TOKEN_APPROVALS.with(|approvals| {
-        approvals.borrow().get(&token_id).map_or(false, |approved| *approved == caller)
+        approvals.borrow().get(&token_id).is_some_and(|approved| *approved == caller)
     })
 }
 
@@ -232,6 +239,10 @@ fn icrc7_approve(args: ApprovalArgs) -> Result<TokenId, String> {
                 return Err("Only token owner can approve".to_string());
             }
             
+            if token_data.retired_at.is_some() {
+                return Err("Token is retired and can no longer be approved".to_string());
+            }
+            
             TOKEN_APPROVALS.with(|approvals| {
                 approvals.borrow_mut().insert(args.token_id, args.approved);
             });
@@ -275,9 +286,7 @@ fn mint(
     share_percentage: f64,
     metadata_json: String,
 ) -> Result<TokenId, String> {
-    let _caller = msg_caller(); // Prefix with underscore to acknowledge it's not used
-    
-
+    ensure_minting_vault(vault_canister)?;
     
     let token_id = TOKEN_COUNTER.with(|counter| {
         let current = *counter.borrow();
@@ -295,6 +304,7 @@ fn mint(
         share_percentage,
         metadata_json,
         created_at: time(),
+        retired_at: None,
     };
     
     TOKENS.with(|tokens| {
@@ -374,7 +384,7 @@ fn verify_zk_proof(
     let mut public_inputs_fr = Vec::with_capacity(public_inputs.len());
     for input in public_inputs {
         // Remove "0x" prefix if present
-        let input_str = if input.starts_with("0x") { &input[2..] } else { input };
+        let input_str = input.strip_prefix("0x").unwrap_or(input);
         
         // Convert hex string to bytes
         let bytes = hex::decode(input_str)
@@ -394,20 +404,14 @@ fn verify_zk_proof(
     if let Some(proof_type) = proof_type {
         // For subscriber count and view count proofs, we need to validate the public inputs format
         match proof_type {
-            ProofType::SubscriberCount => {
-                if public_inputs.len() < 2 {
-                    return Err("SubscriberCount proof requires at least 2 public inputs".to_string());
-                }
+            ProofType::SubscriberCount if public_inputs.len() < 2 => {
+                return Err("SubscriberCount proof requires at least 2 public inputs".to_string());
             },
-            ProofType::ViewCount => {
-                if public_inputs.len() < 2 {
-                    return Err("ViewCount proof requires at least 2 public inputs".to_string());
-                }
+            ProofType::ViewCount if public_inputs.len() < 2 => {
+                return Err("ViewCount proof requires at least 2 public inputs".to_string());
             },
-            ProofType::VideoEngagement => {
-                if public_inputs.len() < 3 {
-                    return Err("VideoEngagement proof requires at least 3 public inputs".to_string());
-                }
+            ProofType::VideoEngagement if public_inputs.len() < 3 => {
+                return Err("VideoEngagement proof requires at least 3 public inputs".to_string());
             },
             _ => {}
         }
@@ -430,6 +434,7 @@ fn verify_zk_proof(
     }
 }
 
+#[allow(clippy::too_many_arguments)] // Candid endpoint, its arguments are the interface
 #[update]
 fn store_youtube_zk_proof(
     proof_bytes: Vec<u8>,
@@ -462,7 +467,7 @@ fn store_youtube_zk_proof(
     };
     
     // Verify the ZK proof
-    let is_valid = verify_zk_proof(&proof_bytes, &public_inputs, &key, Some(proof_type.clone()))
+    let is_valid = verify_zk_proof(&proof_bytes, &public_inputs, &key, Some(proof_type))
         .map_err(|e| format!("Failed to verify ZK proof: {}", e))?;
     
     if !is_valid {
@@ -703,6 +708,7 @@ fn get_principal_by_youtube_channel(channel_id: String) -> Option<Principal> {
 }
 
 // Enhanced NFT minting with YouTube identity verification
+#[allow(clippy::too_many_arguments)] // Candid endpoint, its arguments are the interface
 #[update]
 fn mint_nft_with_youtube_verification(
     to: Principal,
@@ -715,6 +721,8 @@ fn mint_nft_with_youtube_verification(
     min_subscribers: Option<u64>,
     min_views: Option<u64>
 ) -> Result<TokenId, String> {
+    ensure_minting_vault(vault_canister)?;
+    
     // First check for channel verification if needed
     if let Some(channel_id) = youtube_channel_id.clone() {
         let is_verified = verify_youtube_ownership(to, channel_id);
@@ -743,14 +751,10 @@ fn mint_nft_with_youtube_verification(
     // Get YouTube metrics if available to include in metadata
     let youtube_metrics_json = if let Some(channel_id) = youtube_channel_id {
         YOUTUBE_METRICS.with(|m| {
-            if let Some(metrics) = m.borrow().get(&channel_id) {
-                Some(format!(
-                    r#", "youtube_metrics": {{"subscribers": {}, "views": {}, "videos": {}, "verified_at": {}}}"#,
-                    metrics.subscriber_count, metrics.view_count, metrics.video_count, metrics.verified_at
-                ))
-            } else {
-                None
-            }
+            m.borrow().get(&channel_id).map(|metrics| format!(
+                r#", "youtube_metrics": {{"subscribers": {}, "views": {}, "videos": {}, "verified_at": {}}}"#,
+                metrics.subscriber_count, metrics.view_count, metrics.video_count, metrics.verified_at
+            ))
         })
     } else {
         None
@@ -784,6 +788,7 @@ fn mint_nft_with_youtube_verification(
         share_percentage,
         metadata_json: enhanced_metadata,
         created_at: time(),
+        retired_at: None,
     };
     
     TOKENS.with(|tokens| {
@@ -824,7 +829,78 @@ fn mint_nft_with_youtube_verification_legacy(
     )
 }
 
+// Tokens are only minted by a registered vault, for its own campaign
+fn ensure_minting_vault(vault_canister: Principal) -> Result<(), String> {
+    let caller = msg_caller();
+    if caller != vault_canister {
+        return Err("Only the campaign vault can mint its tokens".to_string());
+    }
+    if !REGISTERED_VAULTS.with(|v| v.borrow().contains(&caller)) {
+        return Err("Vault is not registered with the NFT registry".to_string());
+    }
+    Ok(())
+}
+
+#[update]
+fn register_vault(vault: Principal) -> Result<(), String> {
+    let caller = msg_caller();
+    if !ADMINS.with(|admins| admins.borrow().contains(&caller)) {
+        return Err("Unauthorized: only admins can register vaults".to_string());
+    }
+    
+    REGISTERED_VAULTS.with(|v| {
+        let mut vaults = v.borrow_mut();
+        if !vaults.contains(&vault) {
+            vaults.push(vault);
+        }
+    });
+    Ok(())
+}
+
+#[query]
+fn get_registered_vaults() -> Vec<Principal> {
+    REGISTERED_VAULTS.with(|v| v.borrow().clone())
+}
+
+// Called by a campaign vault once its revenue share term is settled, retires the tokens it minted
+#[update]
+fn retire_campaign_tokens() -> Result<u64, String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    if !REGISTERED_VAULTS.with(|v| v.borrow().contains(&caller)) {
+        return Err("Only a registered campaign vault can retire its tokens".to_string());
+    }
+    
+    TOKENS.with(|tokens| {
+        let mut retired = 0;
+        for token in tokens.borrow_mut().values_mut() {
+            if token.vault_canister == caller && token.retired_at.is_none() {
+                token.retired_at = Some(now);
+                retired += 1;
+            }
+        }
+        
+        ic_cdk::println!("Retired {} tokens for vault {}", retired, caller.to_text());
+        
+        Ok(retired)
+    })
+}
+
 // Stable storage management
+type RegistryState = (
+    HashMap<TokenId, TokenMetadata>,
+    HashMap<TokenId, Principal>,
+    HashMap<(Principal, Principal), bool>,
+    TokenId,
+    CollectionMetadata,
+    HashMap<Principal, ZkProofData>,
+    HashMap<String, Principal>,
+    Option<Vec<u8>>,
+    HashMap<ProofType, Vec<u8>>,
+    HashMap<String, YouTubeMetrics>,
+);
+
 #[pre_upgrade]
 fn pre_upgrade() {
     // Save the existing NFT state
@@ -855,27 +931,24 @@ fn pre_upgrade() {
         youtube_metrics
     );
     
-    match ic_cdk::storage::stable_save((state,)) {
+    let registered_vaults = REGISTERED_VAULTS.with(|v| v.borrow().clone());
+    
+    match ic_cdk::storage::stable_save((state, registered_vaults)) {
         Ok(_) => (),
-        Err(e) => ic_cdk::trap(&format!("Failed to save state: {:?}", e)),
+        Err(e) => ic_cdk::trap(format!("Failed to save state: {:?}", e)),
     }
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    // Try to restore with the new state format first
-    let new_format = ic_cdk::storage::stable_restore::<((
-        HashMap<TokenId, TokenMetadata>,
-        HashMap<TokenId, Principal>,
-        HashMap<(Principal, Principal), bool>,
-        TokenId,
-        CollectionMetadata,
-        HashMap<Principal, ZkProofData>,
-        HashMap<String, Principal>,
-        Option<Vec<u8>>,
-        HashMap<ProofType, Vec<u8>>,
-        HashMap<String, YouTubeMetrics>
-    ),)>();
+    // Try to restore with the new state format first, then without the registered vaults
+    let new_format = match ic_cdk::storage::stable_restore::<(RegistryState, Vec<Principal>)>() {
+        Ok((state, registered_vaults)) => {
+            REGISTERED_VAULTS.with(|v| *v.borrow_mut() = registered_vaults);
+            Ok((state,))
+        },
+        Err(_) => ic_cdk::storage::stable_restore::<(RegistryState,)>(),
+    };
     
     match new_format {
         Ok((state,)) => {
@@ -1,10 +1,10 @@
-use ic_cdk::api::{msg_caller, time, canister_self};
+use ic_cdk::api::{msg_caller, time};
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
 use candid::{CandidType, Principal};
 use ic_cdk_macros::*;
-use ic_cdk::api::call::{call, CallResult};
-use ic_cdk::api::management_canister::http_request::{
-    CanisterHttpRequestArgument, HttpMethod, HttpHeader, HttpResponse, TransformArgs,
-    http_request,
+use ic_cdk::call::{Call, CallResult};
+use ic_cdk::management_canister::{
+    http_request, HttpHeader, HttpMethod, HttpRequestArgs, HttpRequestResult, TransformArgs,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -40,6 +40,32 @@ pub struct RevenueData {
     pub verified: bool,
 }
 
+// Mirrors the vault's RevenueReport record
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevenueReport {
+    pub idempotency_key: String,
+    pub amount: u64,
+    pub source: String,
+    pub period_start: u64,
+    pub period_end: u64,
+}
+
+// Mirrors the vault's VaultOperation and VaultError
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum VaultOperation {
+    Invest,
+    UpdateRevenue,
+    DistributePayouts,
+    StreamTransfers,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum VaultError {
+    VaultPaused { reason: String },
+    OperationPaused { operation: VaultOperation, reason: String, paused_at: u64 },
+    Rejected { message: String },
+}
+
 thread_local! {
     static ORACLE_CONFIGS: RefCell<HashMap<u64, OracleConfig>> = RefCell::new(HashMap::new());
     static REVENUE_HISTORY: RefCell<HashMap<(u64, u64), RevenueData>> = RefCell::new(HashMap::new());
@@ -50,6 +76,14 @@ fn init() {
     ic_cdk::println!("Oracle Aggregator initialized");
 }
 
+async fn call<A: ArgumentEncoder, R: for<'de> ArgumentDecoder<'de>>(
+    canister: Principal,
+    method: &str,
+    args: A,
+) -> CallResult<R> {
+    Ok(Call::unbounded_wait(canister, method).with_args(&args).await?.candid_tuple()?)
+}
+
 #[update]
 async fn register_campaign_oracle(
     campaign_id: u64,
@@ -62,7 +96,7 @@ async fn register_campaign_oracle(
     // Authorization check: only vault canister or campaign creator can register
     if caller != vault_canister {
         // Query vault canister for campaign creator
-        let creator_result: Result<(Principal,), _> = ic_cdk::api::call::call(
+        let creator_result: CallResult<(Principal,)> = call(
             vault_canister,
             "get_campaign_creator",
             (campaign_id,)
@@ -101,12 +135,13 @@ async fn fetch_revenue_data(campaign_id: u64) -> Result<Vec<RevenueData>, String
     let config = ORACLE_CONFIGS.with(|configs| configs.borrow().get(&campaign_id).cloned());
     
     match config {
-        Some(mut config) => {
+        Some(config) => {
             if !config.is_active {
                 return Err("Oracle is not active for this campaign".to_string());
             }
             
             let mut results = Vec::new();
+            let period_start = config.last_update;
             
             for endpoint in &config.endpoints {
                 match fetch_from_endpoint(campaign_id, endpoint).await {
@@ -125,16 +160,27 @@ async fn fetch_revenue_data(campaign_id: u64) -> Result<Vec<RevenueData>, String
                 }
             }
             
-            // Update last fetch time
-            config.last_update = time();
-            ORACLE_CONFIGS.with(|configs| {
-                configs.borrow_mut().insert(campaign_id, config.clone());
-            });
-            
             // Send updates to vault canister
             if !results.is_empty() {
+                let period_end = time();
                 let total_revenue: u64 = results.iter().map(|r| r.amount).sum();
-                let _ = update_vault_revenue(config.vault_canister, total_revenue).await;
+                let report = RevenueReport {
+                    idempotency_key: format!("{}:{}:{}", campaign_id, period_start, period_end),
+                    amount: total_revenue,
+                    source: "oracle_aggregated".to_string(),
+                    period_start,
+                    period_end,
+                };
+                
+                // The period only closes once the vault has it, a failed report is fetched again next time
+                update_vault_revenue(config.vault_canister, report).await
+                    .map_err(|e| format!("Failed to report revenue for campaign {}: {}", campaign_id, e))?;
+                
+                ORACLE_CONFIGS.with(|configs| {
+                    if let Some(config) = configs.borrow_mut().get_mut(&campaign_id) {
+                        config.last_update = config.last_update.max(period_end);
+                    }
+                });
             }
             
             Ok(results)
@@ -158,7 +204,7 @@ async fn fetch_from_endpoint(campaign_id: u64, endpoint: &ApiEndpoint) -> Result
         });
     }
     
-    let request = CanisterHttpRequestArgument {
+    let request = HttpRequestArgs {
         url: endpoint.url.clone(),
         max_response_bytes: Some(1024 * 10), // 10KB max
         method: HttpMethod::GET,
@@ -167,8 +213,9 @@ async fn fetch_from_endpoint(campaign_id: u64, endpoint: &ApiEndpoint) -> Result
         transform: None,
     };
     
-    match http_request(request, 25_000_000_000).await {
-        Ok((response,)) => {
+    // The cycles the outcall costs are attached by http_request
+    match http_request(&request).await {
+        Ok(response) => {
             if response.status == 200u8 {
                 let body_str = String::from_utf8(response.body)
                     .map_err(|_| "Invalid UTF-8 response")?;
@@ -178,7 +225,7 @@ async fn fetch_from_endpoint(campaign_id: u64, endpoint: &ApiEndpoint) -> Result
                 Err(format!("HTTP error: {}", response.status))
             }
         }
-        Err((code, msg)) => Err(format!("Request failed: {:?} - {}", code, msg)),
+        Err(e) => Err(format!("Request failed: {:?}", e)),
     }
 }
 
@@ -234,24 +281,23 @@ fn extract_substack_revenue(json: &Value) -> Result<u64, String> {
         .ok_or_else(|| "Failed to extract Substack revenue".to_string())
 }
 
-async fn update_vault_revenue(vault_canister: Principal, amount: u64) -> Result<(), String> {
-    let result: CallResult<(Result<(), String>,)> = call(
+async fn update_vault_revenue(vault_canister: Principal, report: RevenueReport) -> Result<u64, String> {
+    let result: CallResult<(Result<u64, VaultError>,)> = call(
         vault_canister,
         "update_revenue",
-        (amount, "oracle_aggregated".to_string(), true),
+        (report,),
     ).await;
     
     match result {
-        Ok((Ok(()),)) => Ok(()),
-        Ok((Err(e),)) => Err(e),
+        Ok((Ok(report_id),)) => Ok(report_id),
+        Ok((Err(e),)) => Err(format!("Vault rejected revenue report: {:?}", e)),
         Err(e) => Err(format!("Failed to call vault: {:?}", e)),
     }
 }
 
 #[update]
-fn transform_response(args: TransformArgs) -> HttpResponse {
-   
-    HttpResponse {
+fn transform_response(args: TransformArgs) -> HttpRequestResult {
+    HttpRequestResult {
         status: candid::Nat::from(200u8),
         headers: args.response.headers.clone(),
         body: args.response.body.clone(),
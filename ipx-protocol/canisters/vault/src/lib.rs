@@ -1,16 +1,41 @@
 use ic_cdk::api::{msg_caller, time, canister_self};
-use candid::{CandidType, Principal};
+use candid::utils::{ArgumentDecoder, ArgumentEncoder};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
 use ic_cdk_macros::*;
-use ic_cdk::api::call::{call, CallResult};
+use ic_cdk::call::{Call, CallResult};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
-    DefaultMemoryImpl, StableBTreeMap,
+    storable::Bound,
+    DefaultMemoryImpl, StableBTreeMap, StableCell, Storable,
 };
+use std::borrow::Cow;
 use std::cmp;
+use std::time::Duration;
 
 type Memory = VirtualMemory<DefaultMemoryImpl>;
+type Payouts = Vec<(Principal, u64)>; // (recipient, amount) pairs
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+const NANOS_PER_HOUR: u64 = 60 * 60 * 1_000_000_000;
+const MAX_COOLING_OFF_HOURS: u64 = 14 * 24;
+const MAX_BOND_LOCK_DAYS: u64 = 10 * 365;
+const DISTRIBUTION_RETRY_BASE_SECS: u64 = 5 * 60; // Doubles with every failed attempt
+const MAX_DISTRIBUTION_RETRIES: u32 = 5;
+const CLAIM_APPEAL_WINDOW_DAYS: u64 = 14;
+const SLASH_CHALLENGE_PERIOD_DAYS: u64 = 7;
+const CONDITION_CHECK_INTERVAL_SECS: u64 = 24 * 60 * 60;
+const AUTO_SLASH_PERCENTAGE: u8 = 50;
+const REPORT_GRACE_PERIOD_DAYS: u64 = 3; // A report this late still counts as on time
+const MILESTONE_VOTING_PERIOD_DAYS: u64 = 7;
+const MILESTONE_QUORUM_PERCENTAGE: f64 = 20.0; // Share of the backers' voting weight that must vote
+const MAX_EVENT_PAGE_SIZE: u64 = 100;
+const MAX_EVENT_SCAN: u64 = 10_000; // Bounds the work a filtered query does per page
+const XRC_CALL_CYCLES: u128 = 1_000_000_000; // Fee the exchange rate canister charges per request
+const MAX_INVESTMENT_RATE_AGE_SECS: u64 = 10 * 60;
+const MAX_REVENUE_RATE_AGE_SECS: u64 = 24 * 60 * 60;
+const SETTLED_CLAIMS_PAGE_SIZE: u64 = 100;
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct VaultState {
@@ -25,16 +50,173 @@ pub struct VaultState {
     pub nft_registry_canister: Option<Principal>,
     pub stream_canister: Option<Principal>,
     pub oracle_canister: Option<Principal>,
+    pub ledger_canister: Option<Principal>,
+    pub dao_canister: Option<Principal>,
+    pub deployer: Principal, // Installed the vault, normally the campaign factory
     pub backers: HashMap<Principal, BackerInfo>,
     pub revenue_history: Vec<RevenueUpdate>,
+    pub pending_revenue_reports: Vec<RevenueUpdate>, // Creator-submitted reports awaiting oracle confirmation
+    pub revenue_report_counter: u64,
     pub created_at: u64,
     // Insurance pool related fields
     pub insurance_pool_balance: u64,
     pub insurance_fee_percentage: u8,
     pub insurance_coverage_ratio: u8,  // Percentage of investment covered by insurance
     pub insurance_claims: Vec<InsuranceClaim>,
+    pub insurance_triggers: Vec<InsuranceTriggerEvent>, // Events that open the insurance claim window
     pub slashing_conditions: SlashingConditions,
     pub slashed_creators: Vec<SlashEvent>,
+    pub slash_proposals: Vec<SlashProposal>,
+    pub creator_slash_percentage: u8, // Portion of the creator's revenue share redirected to backers
+    pub creator_notices: Vec<CreatorNotice>,
+    pub condition_metrics: Option<ConditionMetrics>, // Result of the last automated condition check
+    pub flagged_breaches: Vec<ConditionBreach>, // Each breach opens one slashing review
+    // Creator revenue share payouts
+    pub creator_payout_mode: CreatorPayoutMode,
+    pub creator_vesting_days: u64,
+    pub creator_withdrawn: u64,
+    pub creator_withdrawals: Vec<CreatorWithdrawal>,
+    // Milestone-gated release of raised capital
+    pub milestones: Vec<Milestone>,
+    pub capital_released: u64,
+    pub releases_paused: bool,
+    pub refundable_escrow: u64, // Escrow left when releases were paused, refunded pro rata
+    pub activated_at: Option<u64>,
+    pub creator_bond: Option<CreatorBond>,
+    // Revenue allocation
+    pub distribution_waterfall: Option<DistributionWaterfall>,
+    pub investor_revenue_total: u64, // Revenue allocated to backers so far
+    pub creator_revenue_total: u64,  // Revenue allocated to the creator so far, net of slashes
+    // Revenue share term and settlement
+    pub revenue_share_term: Option<RevenueShareTerm>,
+    pub insurance_leftover_rule: InsuranceLeftoverRule,
+    pub vault_status: VaultStatus,
+    pub settlement: Option<VaultSettlement>,
+    // Ledger reconciliation
+    pub total_paid_out: u64, // Raised capital released out of the vault
+    pub total_refunded: u64, // Raised capital returned to backers
+    pub paused: bool,
+    pub pause_reason: Option<String>,
+    pub last_reconciliation: Option<ReconciliationReport>,
+    // Emergency controls
+    pub guardian: Option<Principal>, // Set by the DAO, can pause but not unpause
+    pub circuit_breakers: Vec<CircuitBreaker>,
+    // Cover bought from the shared insurance pool canister, which the deployer or governance configures
+    pub insurance_canister: Option<Principal>,
+    pub insurance_policy: Option<InsurancePolicy>,
+    pub pending_premiums: u64, // Premiums collected from backers, not yet remitted
+    pub premiums_remitted: u64,
+    // Cooling-off window during which new investments can be cancelled
+    pub cooling_off_hours: u64,
+    pub pending_investments: Vec<PendingInvestment>,
+    pub pending_investment_counter: u64,
+    pub pending_funding: u64, // Investments after fee still in their cooling-off window
+    // Investor eligibility
+    pub eligibility_policy: Option<EligibilityPolicy>,
+    pub backer_attestations: HashMap<Principal, VerifiedAttestation>,
+    // Automatic distribution
+    pub distribution_schedule: DistributionSchedule,
+    pub distribution_rerun_requested: bool, // An automatic trigger arrived while a round was running
+    pub distribution_retry_attempt: u32,
+    pub distribution_rounds: Vec<DistributionRound>,
+    // Tokens other than the base ledger, valued in the base token through the exchange rate canister
+    pub accepted_tokens: Vec<AcceptedToken>, // Includes an entry for the base ledger once tokens are configured
+    pub xrc_canister: Option<Principal>,
+    pub token_rates: Vec<TokenRate>,
+    pub token_holdings: Vec<TokenHolding>, // Capital held in non-base tokens
+    // Revenue of opted-in backers waiting for room under the goal to buy shares
+    pub reinvestment_pool: u64,
+    // Revenue paid into the vault, per token; revenue payouts never draw on backer capital
+    pub revenue_funds: Vec<RevenueFund>,
+    pub pending_payouts: Vec<PendingPayout>,
+    pub payout_counter: u64,
+    pub stream_payouts_booked: u64, // Settled ipx-stream claims already recorded as payout events
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevenueFund {
+    pub token: Option<Principal>, // None for the base token
+    pub deposited: u64,
+    pub disbursed: u64, // Withdrawn by the creator, streamed to backers or reinvested as capital
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AcceptedToken {
+    pub ledger: Principal,
+    pub symbol: String, // Asset symbol known to the exchange rate canister, e.g. BTC, USDC, ICP
+    pub decimals: u8,
+}
+
+// Price of one whole token in the base token, scaled by 10^rate_decimals
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenRate {
+    pub ledger: Principal,
+    pub rate: u64,
+    pub rate_decimals: u32,
+    pub fetched_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenHolding {
+    pub ledger: Principal,
+    pub capital: u64,       // Token units the vault holds for investments, fees included
+    pub capital_value: u64, // The same capital in base units, as booked at investment time
+    pub insurance_fee_value: u64, // Base value of the insurance fees held in this token, less approved claims
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenEntitlement {
+    pub ledger: Principal,
+    pub entitled: u64, // Token units of revenue allocated to the backer
+    pub claimed: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DistributionSchedule {
+    Manual,
+    Daily,
+    Weekly,
+    Monthly, // Every 30 days
+    AfterVerifiedRevenue,
+}
+
+impl DistributionSchedule {
+    fn interval_secs(&self) -> Option<u64> {
+        match self {
+            DistributionSchedule::Daily => Some(24 * 60 * 60),
+            DistributionSchedule::Weekly => Some(7 * 24 * 60 * 60),
+            DistributionSchedule::Monthly => Some(30 * 24 * 60 * 60),
+            DistributionSchedule::Manual | DistributionSchedule::AfterVerifiedRevenue => None,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum DistributionTrigger {
+    Manual,
+    Scheduled,
+    RevenueVerified { report_id: u64 },
+    Retry { attempt: u32 },
+    Settlement,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum DistributionRoundStatus {
+    Succeeded { stream_ids: Vec<u64> },
+    Failed { error: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DistributionRound {
+    pub round_id: u64,
+    pub trigger: DistributionTrigger,
+    pub caller: Principal,
+    pub started_at: u64,
+    pub completed_at: u64,
+    pub recipients: u64,
+    pub total_amount: u64,
+    pub token_totals: Vec<(Principal, u64)>, // Amounts streamed in non-base tokens, per ledger
+    pub status: DistributionRoundStatus,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -44,14 +226,90 @@ pub struct BackerInfo {
     pub share_percentage: f64,
     pub total_claimed: u64,
     pub investment_timestamp: u64,
+    pub refunded_amount: u64,
+    pub principal: u64,            // Investment after insurance fee
+    pub revenue_entitlement: u64,  // Revenue allocated to this backer so far
+    pub tranche: BackerTranche,
+    pub token: Option<Principal>,  // Ledger the position was funded in, None for the base token
+    pub token_amount: u64,         // Token units invested, fees included
+    pub token_entitlements: Vec<TokenEntitlement>, // Revenue reported in other tokens, paid in those tokens
+    pub token_credited_value: u64, // Base value of token_entitlements, not claimable in the base token
+    pub auto_reinvest: bool,       // Distributions buy more shares instead of being streamed
+    pub reinvestment_balance: u64, // This backer's part of the reinvestment pool
+    pub sell_order: u64,           // Principal offered to the reinvestment pool at par
+    pub sale_proceeds: u64,        // Paid for shares sold to the pool, streamed like revenue
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PendingInvestment {
+    pub investment_id: u64,
+    pub backer: Principal,
+    pub amount: u64,        // Full amount, refunded in full if cancelled
+    pub insurance_fee: u64,
+    pub insured_by_pool: bool, // Fee goes to the shared insurance pool rather than the vault's own
+    pub token: Option<Principal>,
+    pub token_amount: u64,  // Token units refunded if cancelled
+    pub invested_at: u64,
+    pub confirms_at: u64,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct RevenueUpdate {
+    pub report_id: u64,
     pub amount: u64,
     pub source: String,
     pub timestamp: u64,
     pub oracle_verification: bool,
+    pub idempotency_key: String,
+    pub period_start: u64,
+    pub period_end: u64,
+    pub reported_by: Principal,
+    pub token: Option<Principal>, // Set when revenue was earned in a non-base token
+    pub token_amount: u64,        // Revenue in token units; `amount` holds its base value
+    pub investor_amount: u64,
+    pub creator_amount: u64,
+    pub phase: Option<WaterfallPhase>, // Set once the report is verified and allocated
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RevenueReport {
+    pub idempotency_key: String,
+    pub amount: u64,
+    pub source: String,
+    pub period_start: u64, // Inclusive, nanoseconds
+    pub period_end: u64,   // Exclusive, nanoseconds
+    pub token: Option<Principal>, // Ledger `amount` is denominated in, the base token if absent
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DistributionWaterfall {
+    pub recoupment_enabled: bool,              // Backers receive all revenue until their principal is recouped
+    pub post_recoupment_share_percentage: u8,  // Backer share once principal is recouped
+    pub return_cap_percentage: Option<u64>,    // Total backer returns as a percentage of principal, e.g. 200 = 2x
+    pub senior_tranche_enabled: bool,          // Senior backers are recouped before junior backers
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum WaterfallPhase {
+    FlatShare,
+    Recoupment,
+    PostRecoupment,
+    Capped,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum BackerTranche {
+    Senior,
+    Junior,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct WaterfallStatus {
+    pub phase: WaterfallPhase,
+    pub principal: u64,
+    pub investor_revenue_total: u64,
+    pub creator_revenue_total: u64,
+    pub return_cap: Option<u64>,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -60,6 +318,105 @@ pub struct InvestmentResult {
     pub nft_token_id: Option<u64>,
     pub share_percentage: f64,
     pub message: String,
+    pub error: Option<VaultError>,
+    pub rejection: Option<EligibilityRejection>, // Why the backer may not invest, if that was the problem
+}
+
+// Per-vault rules on who may invest and how much
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct EligibilityPolicy {
+    pub min_investment: Option<u64>,
+    pub max_investment_per_backer: Option<u64>, // Across all of a backer's investments
+    pub max_share_percentage: Option<u8>,        // Concentration cap on a single backer's share
+    pub allowlist: Option<Vec<Principal>>,
+    pub attestation: Option<AttestationRequirement>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct AttestationRequirement {
+    pub issuer: Principal, // KYC provider canister
+    pub credential_type: String,
+    pub allowed_jurisdictions: Vec<String>, // Empty allows any jurisdiction
+}
+
+// Credential as returned by the issuer's get_attestation
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Attestation {
+    pub subject: Principal,
+    pub credential_type: String,
+    pub jurisdiction: String,
+    pub issued_at: u64,
+    pub expires_at: u64,
+    pub revoked: bool,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct VerifiedAttestation {
+    pub issuer: Principal,
+    pub credential_type: String,
+    pub jurisdiction: String,
+    pub expires_at: u64,
+    pub verified_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum EligibilityRejection {
+    NotAllowlisted,
+    BelowMinimum { minimum: u64, amount: u64 },
+    AboveBackerMaximum { maximum: u64, already_invested: u64, amount: u64 },
+    ConcentrationCapExceeded { max_share_percentage: u8, resulting_share_percentage: f64 },
+    AttestationMissing { issuer: Principal, credential_type: String },
+    AttestationExpired { expired_at: u64 },
+    JurisdictionNotAllowed { jurisdiction: String },
+}
+
+impl EligibilityRejection {
+    fn describe(&self) -> String {
+        match self {
+            EligibilityRejection::NotAllowlisted => "Backer is not on this vault's allowlist".to_string(),
+            EligibilityRejection::BelowMinimum { minimum, amount } =>
+                format!("Investment of {} is below the minimum of {}", amount, minimum),
+            EligibilityRejection::AboveBackerMaximum { maximum, already_invested, amount } =>
+                format!("Investment of {} would exceed the per-backer maximum of {} ({} already invested)", amount, maximum, already_invested),
+            EligibilityRejection::ConcentrationCapExceeded { max_share_percentage, resulting_share_percentage } =>
+                format!("Investment would give a {:.2}% share, above the {}% cap", resulting_share_percentage, max_share_percentage),
+            EligibilityRejection::AttestationMissing { issuer, credential_type } =>
+                format!("A {} attestation from {} is required, call present_attestation first", credential_type, issuer.to_text()),
+            EligibilityRejection::AttestationExpired { expired_at } =>
+                format!("Attestation expired at {}, present a fresh one", expired_at),
+            EligibilityRejection::JurisdictionNotAllowed { jurisdiction } =>
+                format!("Investors from {} are not eligible for this vault", jurisdiction),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum VaultOperation {
+    Invest,
+    UpdateRevenue,
+    DistributePayouts,
+    StreamTransfers, // Freezes claims on the vault's streams in ipx-stream
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CircuitBreaker {
+    pub operation: VaultOperation,
+    pub reason: String,
+    pub tripped_by: Principal,
+    pub tripped_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum VaultError {
+    VaultPaused { reason: String },
+    OperationPaused { operation: VaultOperation, reason: String, paused_at: u64 },
+    Rejected { message: String },
+}
+
+impl From<String> for VaultError {
+    fn from(message: String) -> Self {
+        VaultError::Rejected { message }
+    }
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
@@ -67,12 +424,14 @@ pub enum ClaimStatus {
     Pending,
     Approved,
     Rejected,
+    Appealed,
     Paid
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct InsuranceClaim {
     pub claim_id: u64,
+    pub trigger_id: u64,
     pub claimer: Principal,
     pub amount: u64,
     pub reason: String,
@@ -81,19 +440,41 @@ pub struct InsuranceClaim {
     pub filed_at: u64,
     pub resolved_at: Option<u64>,
     pub approver: Option<Principal>,
+    pub appeal_deadline: Option<u64>, // Set when a claim is rejected for the first time
+    pub appeal_reason: Option<String>,
+    pub resolution_notes: Option<String>, // Reasoning of the latest decision, an appeal answers it
+    pub paid_at: Option<u64>,
+    pub payout_block_index: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum InsuranceTrigger {
+    CampaignFailure,
+    SlashExecuted { slash_id: u64 },
+    MissedRevenueReports { missed_reports: u8 },
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InsuranceTriggerEvent {
+    pub trigger_id: u64,
+    pub trigger: InsuranceTrigger,
+    pub recorded_at: u64,
+    pub recorded_by: Principal,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct SlashingConditions {
     pub missed_revenue_reports_threshold: u8,
     pub revenue_decline_threshold_percentage: u8,
     pub minimum_active_period_days: u64,
     pub governance_votes_required: u8,
+    pub expected_report_interval_days: u64,
 }
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub enum SlashReason {
     MissedRevenueReports,
+    RevenueDecline,
     RevenueFraud,
     ProjectAbandonment,
     GovernanceDecision,
@@ -102,403 +483,5552 @@ pub enum SlashReason {
 
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
 pub struct SlashEvent {
+    pub proposal_id: u64,
     pub creator: Principal,
     pub campaign_id: u64,
     pub reason: SlashReason,
+    pub evidence: Vec<String>,
+    pub slash_percentage: u8,
     pub amount_slashed: u64,
     pub beneficiaries: Vec<Principal>,
     pub executed_at: u64,
     pub approved_by: Vec<Principal>,
 }
 
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum SlashProposalStatus {
+    Open,     // Challenge period: the creator can respond and backers vote
+    Executed,
+    Rejected,
+}
+
 #[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
-pub struct CampaignMetadata {
+pub struct SlashProposal {
+    pub proposal_id: u64,
     pub creator: Principal,
-    pub title: String,
-    pub description: String,
-    pub funding_goal: u64,
-    pub revenue_share_percentage: u8,
-    pub oracle_endpoints: Vec<String>,
+    pub reason: SlashReason,
+    pub evidence: Vec<String>,
+    pub slash_percentage: u8, // Percentage of the creator's remaining revenue share to redirect
+    pub proposed_by: Principal,
+    pub proposed_at: u64,
+    pub challenge_deadline: u64,
+    pub creator_response: Option<String>,
+    pub creator_evidence: Vec<String>,
+    pub votes_for: f64,     // Weighted by backer share percentage
+    pub votes_against: f64,
+    pub voters: Vec<Principal>,
+    pub supporters: Vec<Principal>, // Voters in favour of the slash
+    pub votes_required: u8, // Share of votes needed to slash, fixed when the proposal is made
+    pub dao_decision: Option<bool>,
+    pub status: SlashProposalStatus,
+    pub resolved_at: Option<u64>,
+    pub slash_id: Option<u64>,
 }
 
-thread_local! {
-    static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> = MemoryManager::init(DefaultMemoryImpl::default());
-    
-    static VAULT_STATE: std::cell::RefCell<Option<VaultState>> = std::cell::RefCell::new(None);
+// The reporting period a breach was found in, identified by the period end of the last report
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum ConditionBreach {
+    MissedReports { since: u64 },
+    RevenueDecline { period_end: u64 },
 }
 
-#[init]
-fn init() {
-    // Initialize with default state - can be configured later via update calls
-    let vault_state = VaultState {
-        campaign_id: 0,
-        creator: Principal::anonymous(),
-        title: "Default Vault".to_string(),
-        funding_goal: 1000000, // 1M default goal
-        current_funding: 0,
-        revenue_share_percentage: 10, // Default 10%
-        total_revenue: 0,
-        oracle_endpoints: Vec::new(), // Empty by default
-        nft_registry_canister: None,
-        stream_canister: None,
-        oracle_canister: None,
-        backers: HashMap::new(),
-        revenue_history: Vec::new(),
-        created_at: time(),
-        // Insurance pool defaults
-        insurance_pool_balance: 0,
-        insurance_fee_percentage: 2, // Default 2% insurance fee
-        insurance_coverage_ratio: 80, // Default 80% coverage of investment
-        insurance_claims: Vec::new(),
-        slashing_conditions: SlashingConditions {
-            missed_revenue_reports_threshold: 3, // 3 missed reports
-            revenue_decline_threshold_percentage: 70, // 70% decline triggers review
-            minimum_active_period_days: 30, // Must be active for 30 days
-            governance_votes_required: 51, // 51% votes required for slashing
-        },
-        slashed_creators: Vec::new(),
-    };
-    
-    VAULT_STATE.with(|state| {
-        *state.borrow_mut() = Some(vault_state);
-    });
-    
-    ic_cdk::println!("Vault initialized with default settings including insurance pool");
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ConditionMetrics {
+    pub evaluated_at: u64,
+    pub active_days: u64,
+    pub last_report_period_end: Option<u64>,
+    pub missed_reports: u64,
+    pub current_window_revenue: u64,  // Revenue for periods ending in the last reporting interval
+    pub previous_window_revenue: u64, // Revenue for periods ending in the interval before that
+    pub revenue_decline_percentage: u8,
 }
 
-#[update]
-async fn invest(amount: u64) -> InvestmentResult {
-    let caller = msg_caller();
-    
-    VAULT_STATE.with(|state_ref| {
-        let mut state_opt = state_ref.borrow_mut();
-        if let Some(ref mut state) = *state_opt {
-           
-            if state.current_funding >= state.funding_goal {
-                return InvestmentResult {
-                    success: false,
-                    nft_token_id: None,
-                    share_percentage: 0.0,
-                    message: "Campaign already fully funded".to_string(),
-                };
-            }
-           
-            let remaining_funding = state.funding_goal - state.current_funding;
-            let actual_investment = amount.min(remaining_funding);
-            
-            // Calculate insurance fee
-            let insurance_fee = (actual_investment * state.insurance_fee_percentage as u64) / 100;
-            let investment_after_fee = actual_investment - insurance_fee;
-            
-            // Add to insurance pool
-            state.insurance_pool_balance += insurance_fee;
-            
-            // Calculate share percentage based on investment after fee
-            let share_percentage = (investment_after_fee as f64 / state.funding_goal as f64) * 100.0;
-            
-            // Update total funding with investment after fee
-            state.current_funding += investment_after_fee;
-            
-            let backer_info = BackerInfo {
-                amount_invested: actual_investment, // Track full amount including insurance fee
-                nft_token_id: None,
-                share_percentage,
-                total_claimed: 0,
-                investment_timestamp: time(),
-            };
-            
-            state.backers.insert(caller, backer_info.clone());
-            
-            InvestmentResult {
-                success: true,
-                nft_token_id: None, 
-                share_percentage,
-                message: format!(
-                    "Investment successful: {} contributed ({} to campaign, {} to insurance pool)", 
-                    actual_investment, 
-                    investment_after_fee, 
-                    insurance_fee
-                ),
-            }
-        } else {
-            InvestmentResult {
-                success: false,
-                nft_token_id: None,
-                share_percentage: 0.0,
-                message: "Vault not initialized".to_string(),
-            }
-        }
-    })
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CreatorNotice {
+    pub notice_id: u64,
+    pub message: String,
+    pub slash_proposal_id: Option<u64>,
+    pub created_at: u64,
 }
 
-#[update]
-async fn mint_nft_for_backer(backer: Principal) -> Result<u64, String> {
-   
-    let backer_info = VAULT_STATE.with(|state_ref| {
-        let state_opt = state_ref.borrow();
-        if let Some(ref state) = *state_opt {
-            state.backers.get(&backer).cloned()
-        } else {
-            None
-        }
-    });
-    
-    if let Some(info) = backer_info {
-        
-        if let Some(nft_registry) = get_nft_registry_canister() {
-            let metadata = format!(
-                "{{\"campaign_id\":{},\"investment\":{},\"share\":{:.2}}}",
-                get_campaign_id(),
-                info.amount_invested,
-                info.share_percentage
-            );
-            
-            let result: CallResult<(Result<u64, String>,)> = call(
-                nft_registry,
-                "mint",
-                (backer, metadata),
-            ).await;
-            
-            match result {
-                Ok((Ok(token_id),)) => {
-            
-                    VAULT_STATE.with(|state_ref| {
-                        let mut state_opt = state_ref.borrow_mut();
-                        if let Some(ref mut state) = *state_opt {
-                            if let Some(ref mut backer_info) = state.backers.get_mut(&backer) {
-                                backer_info.nft_token_id = Some(token_id);
-                            }
-                        }
-                    });
-                    Ok(token_id)
-                },
-                Ok((Err(e),)) => Err(e),
-                Err(e) => Err(format!("Failed to call NFT registry: {:?}", e)),
-            }
-        } else {
-            Err("NFT registry not configured".to_string())
-        }
-    } else {
-        Err("Backer not found".to_string())
-    }
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum CreatorPayoutMode {
+    Vesting, // Each report's creator share vests linearly in the vault and is withdrawn via the ledger
+    Stream,  // The accrued creator share is handed to ipx-stream, which streams it over the vesting period
 }
 
-#[update]
-fn update_revenue(amount: u64, source: String, verified: bool) -> Result<(), String> {
-    
-    let caller = msg_caller();
-    
-    VAULT_STATE.with(|state_ref| {
-        let mut state_opt = state_ref.borrow_mut();
-        if let Some(ref mut state) = *state_opt {
-            let source_clone = source.clone();
-            
-            let revenue_update = RevenueUpdate {
-                amount,
-                source,
-                timestamp: time(),
-                oracle_verification: verified,
-            };
-            
-            state.total_revenue += amount;
-            state.revenue_history.push(revenue_update);
-            
-            ic_cdk::println!("Revenue updated: {} from {}", amount, source_clone);
-            Ok(())
-        } else {
-            Err("Vault not initialized".to_string())
-        }
-    })
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CreatorWithdrawal {
+    pub withdrawal_id: u64,
+    pub amount: u64,
+    pub mode: CreatorPayoutMode,
+    pub reference: u64, // Ledger block index or stream ID
+    pub withdrawn_at: u64,
 }
 
-#[update]
-async fn distribute_payouts() -> Result<Vec<(Principal, u64)>, String> {
-    let mut payouts = Vec::new();
-    let mut creator_slashed = false;
-    let mut creator_slash_percentage = 0;
-    
-    VAULT_STATE.with(|state_ref| {
-        let state_opt = state_ref.borrow();
-        if let Some(ref state) = *state_opt {
-            // Check if creator has been slashed
-            if !state.slashed_creators.is_empty() {
-                creator_slashed = true;
-               
-                creator_slash_percentage = 50;
-            }
-            
-            // Calculate the investor share from revenue
-            let mut investor_share = (state.total_revenue * state.revenue_share_percentage as u64) / 100;
-            
-            // If creator was slashed, add that portion to investor share
-            if creator_slashed {
-                let creator_share = (state.total_revenue * (100 - state.revenue_share_percentage) as u64) / 100;
-                let slashed_amount = (creator_share * creator_slash_percentage) / 100;
-                investor_share += slashed_amount;
-            }
-            
-            // Distribute to backers according to their share percentage
-            for (backer, info) in &state.backers {
-                let backer_share = (investor_share as f64 * info.share_percentage / 100.0) as u64;
-                let claimable = backer_share - info.total_claimed;
-                
-                if claimable > 0 {
-                    payouts.push((*backer, claimable));
-                }
-            }
-            
-            // If we have any approved insurance claims that haven't been paid yet, add those
-            for claim in &state.insurance_claims {
-                if matches!(claim.status, ClaimStatus::Approved) {
-                    // Check if this backer is already getting a payout
-                    let existing_payout = payouts.iter_mut()
-                        .find(|(principal, _)| *principal == claim.claimer);
-                    
-                    if let Some((_, amount)) = existing_payout {
-                        // Add to existing payout
-                        *amount += claim.amount;
-                    } else {
-                        // Create new payout
-                        payouts.push((claim.claimer, claim.amount));
-                    }
-                }
-            }
-        }
-    });
-    
-    
-    if let Some(stream_canister) = get_stream_canister() {
-        let result: CallResult<(Result<(), String>,)> = call(
-            stream_canister,
-            "create_streams",
-            (payouts.clone(),),
-        ).await;
-        
-        match result {
-            Ok((Ok(()),)) => {
-                
-                VAULT_STATE.with(|state_ref| {
-                    let mut state_opt = state_ref.borrow_mut();
-                    if let Some(ref mut state) = *state_opt {
-                        for (backer, amount) in &payouts {
-                            if let Some(ref mut info) = state.backers.get_mut(backer) {
-                                info.total_claimed += amount;
-                            }
-                        }
-                    }
-                });
-                Ok(payouts)
-            },
-            Ok((Err(e),)) => Err(e),
-            Err(e) => Err(format!("Failed to create streams: {:?}", e)),
-        }
-    } else {
-        Err("Stream canister not configured".to_string())
-    }
+// A payout booked in the state until its ledger transfer settles. The memo and created_at_time are
+// fixed when it is booked, so the ledger deduplicates a retry after an unknown outcome.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PendingPayout {
+    pub payout_id: u64,
+    pub kind: PayoutKind,
+    pub token: Option<Principal>, // None for the base ledger
+    pub to: Principal,
+    pub amount: u64,              // In units of `token`
+    pub memo: u64,
+    pub created_at_time: u64,
+    pub attempts: u32,
+    pub last_error: Option<String>,
 }
 
+// What a payout pays for, with what is needed to commit or undo its booking
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum PayoutKind {
+    InvestmentRefund { investment: PendingInvestment },
+    InsuranceClaim { claim_id: u64, value: u64 },
+    EscrowRefund { value: u64 },
+    BondRelease,
+    CreatorShare,
+    TrancheRelease { milestone_id: u64, part_index: u64, value: u64 },
+    InsuranceLeftover,
+}
 
-fn get_campaign_id() -> u64 {
-    VAULT_STATE.with(|state_ref| {
-        state_ref.borrow().as_ref().map(|s| s.campaign_id).unwrap_or(0)
-    })
+enum TransferOutcome {
+    Completed(u64),   // Block index of the transfer
+    Rejected(String), // The ledger refused it, nothing moved
+    Unknown(String),  // The transfer may or may not have happened
 }
 
-fn get_nft_registry_canister() -> Option<Principal> {
-    VAULT_STATE.with(|state_ref| {
-        state_ref.borrow().as_ref().and_then(|s| s.nft_registry_canister)
-    })
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CreatorStatement {
+    pub accrued: u64,
+    pub vested: u64,
+    pub withdrawn: u64,
+    pub withheld: u64,
+    pub available: u64,
+    pub awaiting_deposit: u64, // Withdrawable once enough revenue has been deposited
+    pub slash_percentage: u8,
+    pub pending_slash_proposals: u64,
 }
 
-fn get_stream_canister() -> Option<Principal> {
-    VAULT_STATE.with(|state_ref| {
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum MilestoneStatus {
+    Pending,
+    Voting,
+    Approved, // Approved by backers, tranche transfer not yet completed
+    Released,
+    Rejected,
+    Missed,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct MilestoneInput {
+    pub description: String,
+    pub tranche_amount: u64,
+    pub due_date: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Milestone {
+    pub milestone_id: u64,
+    pub description: String,
+    pub tranche_amount: u64,
+    pub due_date: u64,
+    pub status: MilestoneStatus,
+    pub voting_deadline: Option<u64>,
+    pub votes_for: f64,     // Weighted by backer share percentage
+    pub votes_against: f64,
+    pub voters: Vec<Principal>,
+    pub resolved_at: Option<u64>,
+    pub release_block_index: Option<u64>,
+    pub token_releases: Vec<TokenRelease>, // Parts of the tranche paid in each token the vault holds
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenRelease {
+    pub token: Option<Principal>, // None for the base token
+    pub amount: u64,              // Token units
+    pub value: u64,               // Base value counted against the tranche
+    pub block_index: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CreatorBond {
+    pub amount: u64,
+    pub deposited_at: u64,
+    pub locked_until: u64,
+    pub slashed_amount: u64, // Moved into the insurance pool by executed slashes
+    pub deposit_block_index: u64,
+    pub released_at: Option<u64>,
+    pub release_block_index: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RevenueShareTerm {
+    Duration { days: u64 },      // Counted from vault activation
+    EndDate { timestamp: u64 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum InsuranceLeftoverRule {
+    ReturnToBackers, // Credited pro rata to backers and paid in the final distribution
+    TransferToDao,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum VaultStatus {
+    Active,
+    Settling,
+    Closed,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct VaultSettlement {
+    pub term_end: u64,
+    pub settled_at: u64,
+    pub settled_by: Principal,
+    pub discarded_pending_reports: u64, // Unconfirmed reports dropped at settlement
+    pub insurance_leftover: u64,
+    pub leftover_rule: InsuranceLeftoverRule,
+    pub leftover_block_index: Option<u64>,
+    pub final_distribution: Vec<(Principal, u64)>,
+    pub distribution_error: Option<String>, // Entitlements stay claimable via distribute_payouts
+    pub tokens_retired: Option<u64>,
+    pub retirement_error: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum Discrepancy {
+    LedgerShortfall { expected: u64, actual: u64 },
+    LedgerUnavailable { error: String },
+    PositionsMismatch { sum_of_positions: u64, current_funding: u64 },
+    ClaimsExceedEntitlement { backer: Principal, claimed: u64, entitlement: u64 },
+    TokenShortfall { ledger: Principal, expected: u64, actual: u64 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ReconciliationReport {
+    pub checked_at: u64,
+    pub current_funding: u64,
+    pub insurance_pool_balance: u64,
+    pub reserved_claims: u64, // Approved claims not yet paid, still held by the vault
+    pub bond_held: u64,
+    pub revenue_held: u64, // Deposited base revenue not yet disbursed
+    pub total_paid_out: u64,
+    pub total_refunded: u64,
+    pub expected_balance: u64,
+    pub ledger_balance: Option<u64>, // A surplus is funds the books do not track, such as revenue sent without deposit_revenue
+    pub discrepancies: Vec<Discrepancy>,
+    pub paused: bool,
+}
+
+// Append-only vault event log
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum VaultEventKind {
+    Investment { backer: Principal, amount: u64, insurance_fee: u64, share_percentage: f64 },
+    InvestmentPending { investment_id: u64, backer: Principal, amount: u64, confirms_at: u64 },
+    InvestmentCancelled { investment_id: u64, backer: Principal, amount: u64, block_index: u64 },
+    NftMinted { backer: Principal, token_id: u64 },
+    RevenueReported { report_id: u64, amount: u64, source: String, verified: bool },
+    RevenueAllocated { report_id: u64, investor_amount: u64, creator_amount: u64, phase: WaterfallPhase },
+    RevenueRejected { report_id: u64, reason: String },
+    EntitlementsCredited { source: CreditSource, credits: Vec<(Principal, u64)> },
+    Distribution { payouts: Vec<(Principal, u64)> },
+    DistributionFailed { round_id: u64, error: String },
+    StreamPayout { recipient: Principal, amount: u64, block_index: u64 },
+    InsuranceTriggered { trigger_id: u64, trigger: InsuranceTrigger },
+    InsuranceClaim { claim_id: u64, claimer: Principal, amount: u64, status: ClaimStatus, block_index: Option<u64>, notes: Option<String> },
+    SlashProposed { proposal_id: u64, reason: SlashReason, slash_percentage: u8 },
+    SlashResolved { proposal_id: u64, status: SlashProposalStatus, slash_id: Option<u64> },
+    CreatorWithdrawal { amount: u64, mode: CreatorPayoutMode, reference: u64 },
+    CapitalReleased { milestone_id: u64, amount: u64, block_index: u64 },
+    Refund { backer: Principal, amount: u64, block_index: u64 },
+    SettingsChanged { setting: String, value: String },
+    VaultActivated { bond_amount: u64, bond_block_index: Option<u64> },
+    BondReleased { amount: u64, block_index: Option<u64> },
+    VaultPaused { operations: Option<Vec<VaultOperation>>, reason: String }, // None pauses the whole vault
+    VaultUnpaused { operations: Option<Vec<VaultOperation>> },
+    VaultSettled { insurance_leftover: u64, leftover_rule: InsuranceLeftoverRule },
+    InsurancePolicyOpened { insurance_canister: Principal, policy_id: u64, premium_bps: u64, coverage_limit: u64 },
+    PremiumsRemitted { policy_id: u64, amount: u64, block_index: u64 },
+    TokenDistribution { ledger: Principal, payouts: Vec<(Principal, u64)> },
+    TokenStreamPayout { ledger: Principal, recipient: Principal, amount: u64, block_index: u64 },
+    ExchangeRateUpdated { ledger: Principal, rate: u64, rate_decimals: u32 },
+    Reinvestment { backer: Principal, invested: u64, pooled: u64 },
+    SharesSold { seller: Principal, buyer: Principal, amount: u64 },
+    RevenueDeposited { token: Option<Principal>, amount: u64, block_index: u64 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum CreditSource {
+    Revenue { report_id: u64 },
+    SlashDiversion { proposal_id: u64 },
+    InsuranceLeftover,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum VaultEventCategory {
+    Investment,
+    Mint,
+    Revenue,
+    Distribution,
+    Claim,
+    Slash,
+    Creator,
+    Settings,
+    Refund,
+    Lifecycle,
+}
+
+impl VaultEventKind {
+    fn category(&self) -> VaultEventCategory {
+        match self {
+            VaultEventKind::Investment { .. }
+            | VaultEventKind::InvestmentPending { .. }
+            | VaultEventKind::InvestmentCancelled { .. }
+            | VaultEventKind::Reinvestment { .. }
+            | VaultEventKind::SharesSold { .. } => VaultEventCategory::Investment,
+            VaultEventKind::NftMinted { .. } => VaultEventCategory::Mint,
+            VaultEventKind::RevenueReported { .. }
+            | VaultEventKind::RevenueAllocated { .. }
+            | VaultEventKind::RevenueRejected { .. }
+            | VaultEventKind::EntitlementsCredited { .. }
+            | VaultEventKind::RevenueDeposited { .. } => VaultEventCategory::Revenue,
+            VaultEventKind::Distribution { .. }
+            | VaultEventKind::DistributionFailed { .. }
+            | VaultEventKind::StreamPayout { .. }
+            | VaultEventKind::TokenDistribution { .. }
+            | VaultEventKind::TokenStreamPayout { .. } => VaultEventCategory::Distribution,
+            VaultEventKind::InsuranceTriggered { .. }
+            | VaultEventKind::InsuranceClaim { .. }
+            | VaultEventKind::PremiumsRemitted { .. } => VaultEventCategory::Claim,
+            VaultEventKind::SlashProposed { .. }
+            | VaultEventKind::SlashResolved { .. } => VaultEventCategory::Slash,
+            VaultEventKind::CreatorWithdrawal { .. }
+            | VaultEventKind::CapitalReleased { .. } => VaultEventCategory::Creator,
+            VaultEventKind::Refund { .. } => VaultEventCategory::Refund,
+            VaultEventKind::SettingsChanged { .. }
+            | VaultEventKind::InsurancePolicyOpened { .. }
+            | VaultEventKind::ExchangeRateUpdated { .. } => VaultEventCategory::Settings,
+            VaultEventKind::VaultActivated { .. }
+            | VaultEventKind::BondReleased { .. }
+            | VaultEventKind::VaultPaused { .. }
+            | VaultEventKind::VaultUnpaused { .. }
+            | VaultEventKind::VaultSettled { .. } => VaultEventCategory::Lifecycle,
+        }
+    }
+    
+    // Whether the event concerns a backer other than its caller
+    fn involves(&self, principal: Principal) -> bool {
+        match self {
+            VaultEventKind::Investment { backer, .. }
+            | VaultEventKind::InvestmentPending { backer, .. }
+            | VaultEventKind::InvestmentCancelled { backer, .. }
+            | VaultEventKind::NftMinted { backer, .. }
+            | VaultEventKind::Refund { backer, .. }
+            | VaultEventKind::Reinvestment { backer, .. } => *backer == principal,
+            VaultEventKind::InsuranceClaim { claimer, .. } => *claimer == principal,
+            VaultEventKind::SharesSold { seller, buyer, .. } => *seller == principal || *buyer == principal,
+            VaultEventKind::StreamPayout { recipient, .. }
+            | VaultEventKind::TokenStreamPayout { recipient, .. } => *recipient == principal,
+            VaultEventKind::Distribution { payouts }
+            | VaultEventKind::TokenDistribution { payouts, .. } => payouts.iter().any(|(backer, _)| *backer == principal),
+            VaultEventKind::EntitlementsCredited { credits, .. } => credits.iter().any(|(backer, _)| *backer == principal),
+            _ => false,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct VaultEvent {
+    pub event_id: u64,
+    pub timestamp: u64,
+    pub caller: Principal,
+    pub kind: VaultEventKind,
+}
+
+impl Storable for VaultEvent {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode vault event"))
+    }
+    
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode vault event")
+    }
+    
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct VaultEventFilter {
+    pub category: Option<VaultEventCategory>,
+    pub principal: Option<Principal>, // Matches the caller or the backer the event is about
+    pub from_timestamp: Option<u64>,
+    pub to_timestamp: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct VaultEventPage {
+    pub events: Vec<VaultEvent>,
+    pub next_event_id: Option<u64>, // Pass as `start_event_id` to continue, None when the log is exhausted
+    pub total_events: u64,
+}
+
+// Backer income statements
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum StatementLineType {
+    Investment,
+    EntitlementAccrued,
+    StreamPayout,
+    InsurancePayout,
+    Refund,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct StatementLine {
+    pub event_id: u64,
+    pub timestamp: u64,
+    pub line_type: StatementLineType,
+    pub amount: u64,
+    pub ledger_block_index: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct BackerStatement {
+    pub backer: Principal,
+    pub from: u64, // Inclusive, nanoseconds
+    pub to: u64,   // Exclusive, nanoseconds
+    pub generated_at: u64,
+    pub invested: u64, // Including insurance fees
+    pub insurance_fees: u64,
+    pub entitlements_accrued: u64,
+    pub stream_payouts: u64,
+    pub insurance_payouts: u64,
+    pub refunds: u64,
+    pub lines: Vec<StatementLine>,
+}
+
+// Mirrors ipx-stream's StreamType
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum StreamType {
+    Linear,
+    Cliff,
+    CliffLinear { cliff_seconds: u64 },
+    Steps { interval_seconds: u64 },
+    Custom { breakpoints: Vec<VestingPoint> },
+}
+
+// Mirrors ipx-stream's VestingPoint
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct VestingPoint {
+    pub offset_seconds: u64,
+    pub vested_bps: u32,
+}
+
+// Mirrors ipx-stream's SettledClaim
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct SettledClaim {
+    pub claim_id: u64,
+    pub stream_id: u64,
+    pub recipient: Principal,
+    pub ledger: Principal,
+    pub amount: u64,
+    pub block_index: u64,
+    pub settled_at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct CampaignMetadata {
+    pub creator: Principal,
+    pub title: String,
+    pub description: String,
+    pub funding_goal: u64,
+    pub revenue_share_percentage: u8,
+    pub oracle_endpoints: Vec<String>,
+}
+
+// Shared insurance pool interface
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct RiskProfile {
+    pub creator: Principal,
+    pub campaign_id: u64,
+    pub funding_goal: u64,
+    pub verified_revenue_reports: u64,
+    pub verified_revenue_total: u64,
+}
+
+// Subset of the insurance pool's PremiumQuote
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct PremiumQuote {
+    pub premium_bps: u64,
+}
+
+// Subset of the insurance pool's Policy
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct InsurancePolicy {
+    pub policy_id: u64,
+    pub coverage_limit: u64,
+    pub quote: PremiumQuote,
+}
+
+// ICRC-1 ledger interface
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// ICRC-2 ledger interface
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ApproveArgs {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub spender: Account,
+    pub amount: Nat,
+    pub expected_allowance: Option<Nat>,
+    pub expires_at: Option<u64>,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ApproveError {
+    BadFee { expected_fee: Nat },
+    InsufficientFunds { balance: Nat },
+    AllowanceChanged { current_allowance: Nat },
+    Expired { ledger_time: u64 },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// Exchange rate canister (XRC) types, only the fields the vault reads
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum AssetClass {
+    Cryptocurrency,
+    FiatCurrency,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Asset {
+    pub symbol: String,
+    pub class: AssetClass,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct GetExchangeRateRequest {
+    pub base_asset: Asset,
+    pub quote_asset: Asset,
+    pub timestamp: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ExchangeRateMetadata {
+    pub decimals: u32,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct ExchangeRate {
+    pub timestamp: u64,
+    pub rate: u64,
+    pub metadata: ExchangeRateMetadata,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum ExchangeRateError {
+    AnonymousPrincipalNotAllowed,
+    Pending,
+    CryptoBaseAssetNotFound,
+    CryptoQuoteAssetNotFound,
+    StablecoinRateNotFound,
+    StablecoinRateTooFewRates,
+    StablecoinRateZeroRate,
+    ForexInvalidTimestamp,
+    ForexBaseAssetNotFound,
+    ForexQuoteAssetNotFound,
+    ForexAssetsNotFound,
+    RateLimited,
+    NotEnoughCycles,
+    FailedToAcceptCycles,
+    InconsistentRatesReceived,
+    Other { code: u32, description: String },
+}
+
+// Reentrancy guards for async update methods
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum GuardedOperation {
+    Distribution,
+    Mint,
+    CancelInvestment,
+    Attestation,
+    InsuranceApplication,
+    PremiumRemittance,
+    InsurancePayout,
+    CreatorWithdrawal,
+    Activation,
+    BondRelease,
+    Reconcile,
+    Settlement,
+    MilestoneRelease,
+    EscrowRefund,
+    Investment,
+    RateRefresh,
+    StreamPayoutSync,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum GuardKey {
+    Operation(GuardedOperation),
+    Principal(GuardedOperation, Principal),
+    Payout(u64),
+}
+
+// Held across the awaits of an async call. It is released on drop, which also happens
+// when ic-cdk cleans up after a trap in a callback, so a failed call cannot leave it locked.
+struct CallGuard {
+    key: GuardKey,
+}
+
+impl CallGuard {
+    fn acquire(key: GuardKey) -> Result<Self, String> {
+        CALL_GUARDS.with(|guards| {
+            if guards.borrow_mut().insert(key.clone()) {
+                Ok(CallGuard { key })
+            } else {
+                Err(match key {
+                    GuardKey::Operation(op) => format!("{:?} is already in progress", op),
+                    GuardKey::Principal(op, principal) =>
+                        format!("{:?} is already in progress for {}", op, principal.to_text()),
+                    GuardKey::Payout(payout_id) => format!("Payout {} is already being settled", payout_id),
+                })
+            }
+        })
+    }
+    
+    // One call at a time for the whole vault
+    fn operation(op: GuardedOperation) -> Result<Self, String> {
+        Self::acquire(GuardKey::Operation(op))
+    }
+    
+    // One call at a time per principal, different principals run concurrently
+    fn principal(op: GuardedOperation, principal: Principal) -> Result<Self, String> {
+        Self::acquire(GuardKey::Principal(op, principal))
+    }
+    
+    fn payout(payout_id: u64) -> Result<Self, String> {
+        Self::acquire(GuardKey::Payout(payout_id))
+    }
+}
+
+impl Drop for CallGuard {
+    fn drop(&mut self) {
+        CALL_GUARDS.with(|guards| {
+            guards.borrow_mut().remove(&self.key);
+        });
+    }
+}
+
+thread_local! {
+    static MEMORY_MANAGER: MemoryManager<DefaultMemoryImpl> = MemoryManager::init(DefaultMemoryImpl::default());
+    
+    static VAULT_STATE: std::cell::RefCell<Option<VaultState>> = const { std::cell::RefCell::new(None) };
+    
+    static CALL_GUARDS: std::cell::RefCell<HashSet<GuardKey>> = std::cell::RefCell::new(HashSet::new());
+    
+    static DISTRIBUTION_TIMER: std::cell::RefCell<Option<ic_cdk_timers::TimerId>> = const { std::cell::RefCell::new(None) };
+    
+    static EVENT_LOG: std::cell::RefCell<StableBTreeMap<u64, VaultEvent, Memory>> = std::cell::RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.get(MemoryId::new(0))))
+    );
+    
+    // The vault state is kept here across upgrades, the event log already lives in stable memory
+    static UPGRADE_STATE: std::cell::RefCell<StableCell<Vec<u8>, Memory>> = std::cell::RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|m| m.get(MemoryId::new(1))), Vec::new())
+            .expect("Failed to initialize the upgrade state cell")
+    );
+}
+
+#[init]
+fn init() {
+    // Initialize with default state - can be configured later via update calls
+    let mut vault_state = default_vault_state(time());
+    vault_state.deployer = msg_caller();
+    
+    VAULT_STATE.with(|state| {
+        *state.borrow_mut() = Some(vault_state);
+    });
+    
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(CONDITION_CHECK_INTERVAL_SECS),
+        run_scheduled_checks,
+    );
+    
+    ic_cdk::println!("Vault initialized with default settings including insurance pool");
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let bytes = VAULT_STATE.with(|state_ref| Encode!(&*state_ref.borrow()))
+        .expect("Failed to encode vault state");
+    UPGRADE_STATE.with(|cell| cell.borrow_mut().set(bytes))
+        .expect("Failed to save vault state");
+}
+
+// Timers do not survive an upgrade, so they are armed again from the restored state
+#[post_upgrade]
+fn post_upgrade() {
+    let bytes = UPGRADE_STATE.with(|cell| cell.borrow().get().clone());
+    if !bytes.is_empty() {
+        let state = Decode!(&bytes, Option<VaultState>).expect("Failed to decode vault state");
+        VAULT_STATE.with(|state_ref| *state_ref.borrow_mut() = state);
+    }
+    
+    ic_cdk_timers::set_timer_interval(
+        Duration::from_secs(CONDITION_CHECK_INTERVAL_SECS),
+        run_scheduled_checks,
+    );
+    
+    let schedule = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.distribution_schedule.clone())
+    });
+    if let Some(schedule) = schedule {
+        install_distribution_timer(&schedule);
+    }
+}
+
+fn default_vault_state(created_at: u64) -> VaultState {
+    VaultState {
+        campaign_id: 0,
+        creator: Principal::anonymous(),
+        title: "Default Vault".to_string(),
+        funding_goal: 1000000, // 1M default goal
+        current_funding: 0,
+        revenue_share_percentage: 10, // Default 10%
+        total_revenue: 0,
+        oracle_endpoints: Vec::new(), // Empty by default
+        nft_registry_canister: None,
+        stream_canister: None,
+        oracle_canister: None,
+        ledger_canister: None,
+        dao_canister: None,
+        deployer: Principal::anonymous(),
+        backers: HashMap::new(),
+        revenue_history: Vec::new(),
+        pending_revenue_reports: Vec::new(),
+        revenue_report_counter: 0,
+        created_at,
+        // Insurance pool defaults
+        insurance_pool_balance: 0,
+        insurance_fee_percentage: 2, // Default 2% insurance fee
+        insurance_coverage_ratio: 80, // Default 80% coverage of investment
+        insurance_claims: Vec::new(),
+        insurance_triggers: Vec::new(),
+        slashing_conditions: SlashingConditions {
+            missed_revenue_reports_threshold: 3, // 3 missed reports
+            revenue_decline_threshold_percentage: 70, // 70% decline triggers review
+            minimum_active_period_days: 30, // Must be active for 30 days
+            governance_votes_required: 51, // 51% votes required for slashing
+            expected_report_interval_days: 30, // Monthly revenue reports
+        },
+        slashed_creators: Vec::new(),
+        slash_proposals: Vec::new(),
+        creator_slash_percentage: 0,
+        creator_notices: Vec::new(),
+        condition_metrics: None,
+        flagged_breaches: Vec::new(),
+        creator_payout_mode: CreatorPayoutMode::Vesting,
+        creator_vesting_days: 30, // Creator share vests over 30 days
+        creator_withdrawn: 0,
+        creator_withdrawals: Vec::new(),
+        milestones: Vec::new(),
+        capital_released: 0,
+        releases_paused: false,
+        refundable_escrow: 0,
+        activated_at: None,
+        creator_bond: None,
+        distribution_waterfall: None,
+        investor_revenue_total: 0,
+        creator_revenue_total: 0,
+        revenue_share_term: None,
+        insurance_leftover_rule: InsuranceLeftoverRule::ReturnToBackers,
+        vault_status: VaultStatus::Active,
+        settlement: None,
+        total_paid_out: 0,
+        total_refunded: 0,
+        paused: false,
+        pause_reason: None,
+        last_reconciliation: None,
+        guardian: None,
+        circuit_breakers: Vec::new(),
+        insurance_canister: None,
+        insurance_policy: None,
+        pending_premiums: 0,
+        premiums_remitted: 0,
+        cooling_off_hours: 48,
+        pending_investments: Vec::new(),
+        pending_investment_counter: 0,
+        pending_funding: 0,
+        eligibility_policy: None,
+        backer_attestations: HashMap::new(),
+        distribution_schedule: DistributionSchedule::Manual,
+        distribution_rerun_requested: false,
+        distribution_retry_attempt: 0,
+        distribution_rounds: Vec::new(),
+        accepted_tokens: Vec::new(),
+        xrc_canister: None,
+        token_rates: Vec::new(),
+        token_holdings: Vec::new(),
+        reinvestment_pool: 0,
+        revenue_funds: Vec::new(),
+        pending_payouts: Vec::new(),
+        payout_counter: 0,
+        stream_payouts_booked: 0,
+    }
+}
+
+// Invests in the base token. The deposit is pulled through an ICRC-2 allowance before the position
+// is booked, so the backer approves the vault for `amount` plus the ledger fee beforehand.
+#[update]
+async fn invest(amount: u64) -> InvestmentResult {
+    let caller = msg_caller();
+    let _guard = match CallGuard::principal(GuardedOperation::Investment, caller) {
+        Ok(guard) => guard,
+        Err(e) => return failed_investment(e, None, None),
+    };
+    
+    if amount == 0 {
+        return failed_investment("Investment amount must be greater than 0".to_string(), None, None);
+    }
+    
+    // Hold room under the goal while the deposit is pulled
+    let reserved: Result<_, Box<InvestmentResult>> = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        let state = match *state_opt {
+            Some(ref mut state) => state,
+            None => return Err(failed_investment("Vault not initialized".to_string(), None, None).into()),
+        };
+        
+        let (actual_investment, insurance_fee) = admit_investment(state, caller, None, amount)?;
+        state.pending_funding += actual_investment - insurance_fee;
+        Ok((actual_investment, insurance_fee))
+    });
+    let (actual_investment, insurance_fee) = match reserved {
+        Ok(reserved) => reserved,
+        Err(result) => return *result,
+    };
+    
+    // Only what the ledger confirms is booked, and later refunded
+    let pulled = ledger_transfer_from(caller, actual_investment, time()).await;
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            state.pending_funding -= actual_investment - insurance_fee;
+            
+            if let Err(e) = pulled {
+                return failed_investment(format!("Could not collect the investment: {}", e), None, None);
+            }
+            
+            place_investment(state, caller, None, actual_investment, actual_investment, insurance_fee)
+        } else {
+            failed_investment("Vault not initialized".to_string(), None, None)
+        }
+    })
+}
+
+// Invests in one of the vault's other accepted tokens, valued in the base token at the current
+// exchange rate. The backer approves the vault for `amount` plus the ledger fee beforehand.
+#[update]
+async fn invest_with_token(ledger: Principal, amount: u64) -> InvestmentResult {
+    let caller = msg_caller();
+    // Shares the guard with `invest`, so a backer can't open positions in two tokens at once
+    let _guard = match CallGuard::principal(GuardedOperation::Investment, caller) {
+        Ok(guard) => guard,
+        Err(e) => return failed_investment(e, None, None),
+    };
+    
+    let rate = match current_rate(ledger, MAX_INVESTMENT_RATE_AGE_SECS).await {
+        Ok(rate) => rate,
+        Err(e) => return failed_investment(e, None, None),
+    };
+    
+    // Hold room under the goal while the tokens are pulled
+    let reserved: Result<_, Box<InvestmentResult>> = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        let state = match *state_opt {
+            Some(ref mut state) => state,
+            None => return Err(failed_investment("Vault not initialized".to_string(), None, None).into()),
+        };
+        
+        if state.insurance_policy.is_some() {
+            return Err(failed_investment(
+                "Vaults insured through the shared pool only accept the base token".to_string(),
+                None,
+                None,
+            ).into());
+        }
+        
+        let value = to_base_value(state, ledger, amount, &rate)
+            .map_err(|e| failed_investment(e, None, None))?;
+        let (actual_investment, insurance_fee) = admit_investment(state, caller, Some(ledger), value)?;
+        
+        // When the goal caps the investment, only the matching part of the tokens is pulled
+        let token_amount = if actual_investment < value {
+            (amount as u128 * actual_investment as u128 / value as u128) as u64
+        } else {
+            amount
+        };
+        if token_amount == 0 || actual_investment == 0 {
+            return Err(failed_investment("Investment is too small to value in the base token".to_string(), None, None).into());
+        }
+        
+        state.pending_funding += actual_investment - insurance_fee;
+        Ok((token_amount, actual_investment, insurance_fee))
+    });
+    let (token_amount, actual_investment, insurance_fee) = match reserved {
+        Ok(reserved) => reserved,
+        Err(result) => return *result,
+    };
+    
+    let pulled = token_transfer_from(ledger, caller, token_amount, time()).await;
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            state.pending_funding -= actual_investment - insurance_fee;
+            
+            if let Err(e) = pulled {
+                return failed_investment(format!("Could not collect the investment: {}", e), None, None);
+            }
+            
+            adjust_token_capital(state, Some(ledger), token_amount as i128, actual_investment as i128);
+            place_investment(state, caller, Some(ledger), token_amount, actual_investment, insurance_fee)
+        } else {
+            failed_investment("Vault not initialized".to_string(), None, None)
+        }
+    })
+}
+
+fn failed_investment(message: String, error: Option<VaultError>, rejection: Option<EligibilityRejection>) -> InvestmentResult {
+    InvestmentResult {
+        success: false,
+        nft_token_id: None,
+        share_percentage: 0.0,
+        message,
+        error,
+        rejection,
+    }
+}
+
+// Checks that `caller` may invest `value` (in base units) and returns the accepted amount and its insurance fee
+fn admit_investment(
+    state: &VaultState,
+    caller: Principal,
+    token: Option<Principal>,
+    value: u64,
+) -> Result<(u64, u64), Box<InvestmentResult>> {
+    if state.vault_status != VaultStatus::Active {
+        return Err(failed_investment("Vault is closed".to_string(), None, None).into());
+    }
+    
+    if let Err(e) = ensure_operation_enabled(state, VaultOperation::Invest) {
+        return Err(failed_investment(format!("{:?}", e), Some(e), None).into());
+    }
+    
+    if state.releases_paused {
+        return Err(failed_investment(
+            "Campaign milestones failed, escrow is being refunded".to_string(),
+            None,
+            None,
+        ).into());
+    }
+    
+    if state.current_funding + state.pending_funding >= state.funding_goal {
+        return Err(failed_investment("Campaign already fully funded".to_string(), None, None).into());
+    }
+    
+    // A position is held in a single token so it can be refunded in that token
+    let held_token = state.backers.get(&caller).map(|b| b.token)
+        .or_else(|| state.pending_investments.iter().find(|p| p.backer == caller).map(|p| p.token));
+    if let Some(held) = held_token {
+        if held != token {
+            return Err(failed_investment(
+                "Backer already holds a position in another token".to_string(),
+                None,
+                None,
+            ).into());
+        }
+    }
+    
+    // Investments still in their cooling-off window hold their place against the goal
+    let remaining_funding = state.funding_goal - state.current_funding - state.pending_funding;
+    let actual_investment = value.min(remaining_funding);
+    
+    let insurance_fee = insurance_fee_for(state, actual_investment);
+    let investment_after_fee = actual_investment - insurance_fee;
+    
+    if let Err(rejection) = check_eligibility(state, caller, actual_investment, investment_after_fee, time()) {
+        return Err(failed_investment(rejection.describe(), None, Some(rejection)).into());
+    }
+    
+    Ok((actual_investment, insurance_fee))
+}
+
+// Records an admitted investment, confirming it at once when there is no cooling-off window
+fn place_investment(
+    state: &mut VaultState,
+    caller: Principal,
+    token: Option<Principal>,
+    token_amount: u64,
+    actual_investment: u64,
+    insurance_fee: u64,
+) -> InvestmentResult {
+    let investment_after_fee = actual_investment - insurance_fee;
+    
+    // Calculate share percentage based on investment after fee
+    let share_percentage = (investment_after_fee as f64 / state.funding_goal as f64) * 100.0;
+    
+    let now = time();
+    state.pending_investment_counter += 1;
+    let pending = PendingInvestment {
+        investment_id: state.pending_investment_counter,
+        backer: caller,
+        amount: actual_investment,
+        insurance_fee,
+        insured_by_pool: state.insurance_policy.is_some(),
+        token,
+        token_amount,
+        invested_at: now,
+        confirms_at: now + state.cooling_off_hours * NANOS_PER_HOUR,
+    };
+    
+    if state.cooling_off_hours == 0 {
+        confirm_investment(state, pending, caller, now);
+        
+        return InvestmentResult {
+            success: true,
+            nft_token_id: None,
+            share_percentage,
+            message: format!(
+                "Investment successful: {} contributed ({} to campaign, {} to insurance)",
+                actual_investment,
+                investment_after_fee,
+                insurance_fee
+            ),
+            error: None,
+            rejection: None,
+        };
+    }
+    
+    record_event(caller, VaultEventKind::InvestmentPending {
+        investment_id: pending.investment_id,
+        backer: caller,
+        amount: actual_investment,
+        confirms_at: pending.confirms_at,
+    });
+    
+    state.pending_funding += investment_after_fee;
+    let confirms_at = pending.confirms_at;
+    state.pending_investments.push(pending);
+    
+    // Lost on upgrade, confirm_pending_investments picks up anything it misses
+    ic_cdk_timers::set_timer(
+        Duration::from_secs(state.cooling_off_hours * 60 * 60),
+        confirm_matured_investments,
+    );
+    
+    InvestmentResult {
+        success: true,
+        nft_token_id: None,
+        share_percentage,
+        message: format!(
+            "Investment received: {} contributed ({} to campaign, {} to insurance), cancellable until {}",
+            actual_investment,
+            investment_after_fee,
+            insurance_fee,
+            confirms_at
+        ),
+        error: None,
+        rejection: None,
+    }
+}
+
+// Insured vaults collect the pool's risk-based premium, others fund their own pool
+fn insurance_fee_for(state: &VaultState, amount: u64) -> u64 {
+    match state.insurance_policy {
+        Some(ref policy) => amount * policy.quote.premium_bps / 10_000,
+        None => (amount * state.insurance_fee_percentage as u64) / 100,
+    }
+}
+
+// Evaluates the vault's eligibility policy for a new investment of `amount` (`after_fee` counts toward shares)
+fn check_eligibility(
+    state: &VaultState,
+    backer: Principal,
+    amount: u64,
+    after_fee: u64,
+    now: u64,
+) -> Result<(), EligibilityRejection> {
+    let policy = match state.eligibility_policy {
+        Some(ref policy) => policy,
+        None => return Ok(()),
+    };
+    
+    if let Some(ref allowlist) = policy.allowlist {
+        if !allowlist.contains(&backer) {
+            return Err(EligibilityRejection::NotAllowlisted);
+        }
+    }
+    
+    if let Some(ref requirement) = policy.attestation {
+        let attestation = state.backer_attestations.get(&backer)
+            .filter(|a| a.issuer == requirement.issuer && a.credential_type == requirement.credential_type)
+            .ok_or_else(|| EligibilityRejection::AttestationMissing {
+                issuer: requirement.issuer,
+                credential_type: requirement.credential_type.clone(),
+            })?;
+        if attestation.expires_at <= now {
+            return Err(EligibilityRejection::AttestationExpired { expired_at: attestation.expires_at });
+        }
+        if !requirement.allowed_jurisdictions.is_empty()
+            && !requirement.allowed_jurisdictions.contains(&attestation.jurisdiction)
+        {
+            return Err(EligibilityRejection::JurisdictionNotAllowed { jurisdiction: attestation.jurisdiction.clone() });
+        }
+    }
+    
+    if let Some(minimum) = policy.min_investment {
+        if amount < minimum {
+            return Err(EligibilityRejection::BelowMinimum { minimum, amount });
+        }
+    }
+    
+    // Pending investments count, otherwise caps could be dodged by investing inside the window
+    let pending = state.pending_investments.iter().filter(|p| p.backer == backer);
+    let (pending_amount, pending_after_fee) = pending.fold((0, 0), |(total, net), p| {
+        (total + p.amount, net + p.amount - p.insurance_fee)
+    });
+    let position = state.backers.get(&backer);
+    
+    if let Some(maximum) = policy.max_investment_per_backer {
+        let already_invested = position.map_or(0, |b| b.amount_invested - b.refunded_amount) + pending_amount;
+        if already_invested + amount > maximum {
+            return Err(EligibilityRejection::AboveBackerMaximum { maximum, already_invested, amount });
+        }
+    }
+    
+    if let Some(max_share_percentage) = policy.max_share_percentage {
+        let resulting_principal = position.map_or(0, |b| b.principal) + pending_after_fee + after_fee;
+        if resulting_principal as u128 * 100 > state.funding_goal as u128 * max_share_percentage as u128 {
+            return Err(EligibilityRejection::ConcentrationCapExceeded {
+                max_share_percentage,
+                resulting_share_percentage: (resulting_principal as f64 / state.funding_goal as f64) * 100.0,
+            });
+        }
+    }
+    
+    Ok(())
+}
+
+#[update]
+fn set_eligibility_policy(policy: Option<EligibilityPolicy>) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can set the eligibility policy".to_string());
+            }
+            
+            if let Some(ref p) = policy {
+                if let (Some(min), Some(max)) = (p.min_investment, p.max_investment_per_backer) {
+                    if min > max {
+                        return Err("Minimum investment cannot exceed the per-backer maximum".to_string());
+                    }
+                }
+                if p.max_share_percentage.is_some_and(|cap| cap == 0 || cap > 100) {
+                    return Err("Concentration cap must be between 1 and 100%".to_string());
+                }
+            }
+            
+            // Existing positions are not affected, the policy applies to new investments
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "eligibility_policy".to_string(),
+                value: format!("{:?}", policy),
+            });
+            
+            state.eligibility_policy = policy;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Fetches the caller's credential from the required issuer and records it once verified
+#[update]
+async fn present_attestation() -> Result<VerifiedAttestation, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::principal(GuardedOperation::Attestation, caller)?;
+    
+    let requirement = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref()
+            .ok_or_else(|| "Vault not initialized".to_string())?
+            .eligibility_policy.as_ref()
+            .and_then(|p| p.attestation.clone())
+            .ok_or_else(|| "Vault does not require an attestation".to_string())
+    })?;
+    
+    let result: CallResult<(Option<Attestation>,)> = call(
+        requirement.issuer,
+        "get_attestation",
+        (caller, requirement.credential_type.clone()),
+    ).await;
+    
+    let attestation = match result {
+        Ok((Some(attestation),)) => attestation,
+        Ok((None,)) => return Err("Issuer has no attestation for the caller".to_string()),
+        Err(e) => return Err(format!("Failed to call attestation issuer: {:?}", e)),
+    };
+    
+    let now = time();
+    if attestation.subject != caller || attestation.credential_type != requirement.credential_type {
+        return Err("Issuer returned an attestation for a different subject or credential".to_string());
+    }
+    if attestation.revoked {
+        return Err("Attestation has been revoked".to_string());
+    }
+    if attestation.expires_at <= now {
+        return Err("Attestation has expired".to_string());
+    }
+    
+    let verified = VerifiedAttestation {
+        issuer: requirement.issuer,
+        credential_type: attestation.credential_type,
+        jurisdiction: attestation.jurisdiction,
+        expires_at: attestation.expires_at,
+        verified_at: now,
+    };
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            state.backer_attestations.insert(caller, verified.clone());
+            Ok(verified)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Dry run of the eligibility policy, lets a backer see why an investment would be refused
+#[query]
+fn check_investment_eligibility(backer: Principal, amount: u64) -> Result<(), EligibilityRejection> {
+    VAULT_STATE.with(|state_ref| {
+        match state_ref.borrow().as_ref() {
+            Some(state) => {
+                let remaining = state.funding_goal
+                    .saturating_sub(state.current_funding + state.pending_funding);
+                let amount = amount.min(remaining);
+                let fee = insurance_fee_for(state, amount);
+                check_eligibility(state, backer, amount, amount - fee, time())
+            }
+            None => Ok(()),
+        }
+    })
+}
+
+#[query]
+fn get_eligibility_policy() -> Option<EligibilityPolicy> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.eligibility_policy.clone())
+    })
+}
+
+// Turns an investment into a backer position; the position NFT can only be minted after this
+fn confirm_investment(state: &mut VaultState, pending: PendingInvestment, caller: Principal, now: u64) {
+    if pending.insured_by_pool {
+        state.pending_premiums += pending.insurance_fee;
+    } else {
+        state.insurance_pool_balance += pending.insurance_fee;
+        if let Some(ledger) = pending.token {
+            token_holding(state, ledger).insurance_fee_value += pending.insurance_fee;
+        }
+    }
+    
+    let investment_after_fee = pending.amount - pending.insurance_fee;
+    state.current_funding += investment_after_fee;
+    
+    // Repeat investments add to the backer's existing position
+    let backer_info = state.backers.entry(pending.backer).or_insert(BackerInfo {
+        amount_invested: 0,
+        nft_token_id: None,
+        share_percentage: 0.0,
+        total_claimed: 0,
+        investment_timestamp: pending.invested_at,
+        refunded_amount: 0,
+        principal: 0,
+        revenue_entitlement: 0,
+        tranche: BackerTranche::Junior,
+        token: pending.token,
+        token_amount: 0,
+        token_entitlements: Vec::new(),
+        token_credited_value: 0,
+        auto_reinvest: false,
+        reinvestment_balance: 0,
+        sell_order: 0,
+        sale_proceeds: 0,
+    });
+    backer_info.amount_invested += pending.amount; // Track full amount including insurance fee
+    backer_info.principal += investment_after_fee;
+    backer_info.token_amount += pending.token_amount;
+    backer_info.share_percentage = (backer_info.principal as f64 / state.funding_goal as f64) * 100.0;
+    
+    record_event_at(caller, VaultEventKind::Investment {
+        backer: pending.backer,
+        amount: pending.amount,
+        insurance_fee: pending.insurance_fee,
+        share_percentage: (investment_after_fee as f64 / state.funding_goal as f64) * 100.0,
+    }, now);
+}
+
+fn confirm_matured(state: &mut VaultState, now: u64, caller: Principal) -> u64 {
+    let (matured, waiting): (Vec<PendingInvestment>, Vec<PendingInvestment>) =
+        std::mem::take(&mut state.pending_investments)
+            .into_iter()
+            .partition(|p| p.confirms_at <= now);
+    state.pending_investments = waiting;
+    
+    let confirmed = matured.len() as u64;
+    for pending in matured {
+        state.pending_funding -= pending.amount - pending.insurance_fee;
+        confirm_investment(state, pending, caller, now);
+    }
+    confirmed
+}
+
+fn confirm_matured_investments() {
+    let now = time();
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            confirm_matured(state, now, canister_self());
+        }
+    });
+}
+
+// Confirms every investment whose cooling-off window has ended, returns how many
+#[update]
+fn confirm_pending_investments() -> Result<u64, String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            Ok(confirm_matured(state, now, caller))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Cancels an investment inside its cooling-off window and refunds it in full, insurance fee included
+#[update]
+async fn cancel_investment(investment_id: u64) -> Result<u64, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::principal(GuardedOperation::CancelInvestment, caller)?;
+    let now = time();
+    
+    let payout_id = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            book_investment_refund(state, investment_id, caller, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    settle_payout(payout_id).await
+}
+
+// Takes the investment off the pending list before the refund so it can neither confirm nor be cancelled twice
+fn book_investment_refund(state: &mut VaultState, investment_id: u64, caller: Principal, now: u64) -> Result<u64, String> {
+    ensure_not_paused(state)?;
+    
+    let index = state.pending_investments.iter()
+        .position(|p| p.investment_id == investment_id && p.backer == caller)
+        .ok_or_else(|| "Pending investment not found".to_string())?;
+    
+    if now >= state.pending_investments[index].confirms_at {
+        return Err("Cooling-off window has ended".to_string());
+    }
+    
+    let pending = state.pending_investments.remove(index);
+    state.pending_funding -= pending.amount - pending.insurance_fee;
+    
+    // Refunded in the token it was paid in
+    let (token, token_amount) = (pending.token, pending.token_amount);
+    let kind = PayoutKind::InvestmentRefund { investment: pending };
+    Ok(book_payout(state, kind, token, caller, token_amount, investment_id, now))
+}
+
+#[update]
+fn set_cooling_off_period(hours: u64) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can set the cooling-off period".to_string());
+            }
+            if hours > MAX_COOLING_OFF_HOURS {
+                return Err(format!("Cooling-off period cannot exceed {} hours", MAX_COOLING_OFF_HOURS));
+            }
+            
+            // Applies to new investments, pending ones keep the window they were given
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "cooling_off_hours".to_string(),
+                value: hours.to_string(),
+            });
+            
+            state.cooling_off_hours = hours;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[query]
+fn get_pending_investments(backer: Option<Principal>) -> Vec<PendingInvestment> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map_or_else(Vec::new, |s| {
+            s.pending_investments.iter()
+                .filter(|p| backer.is_none_or(|b| p.backer == b))
+                .cloned()
+                .collect()
+        })
+    })
+}
+
+#[update]
+async fn mint_nft_for_backer(backer: Principal) -> Result<u64, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::principal(GuardedOperation::Mint, backer)?;
+    
+    let info = VAULT_STATE.with(|state_ref| {
+        match state_ref.borrow().as_ref() {
+            Some(state) => mint_candidate(state, backer),
+            None => Err("Vault not initialized".to_string()),
+        }
+    })?;
+    let nft_registry = get_nft_registry_canister().ok_or_else(|| "NFT registry not configured".to_string())?;
+    
+    let metadata = format!(
+        "{{\"campaign_id\":{},\"investment\":{},\"share\":{:.2}}}",
+        get_campaign_id(),
+        info.amount_invested,
+        info.share_percentage
+    );
+    
+    let result: CallResult<(Result<u64, String>,)> = call(
+        nft_registry,
+        "mint",
+        (backer, get_campaign_id(), canister_self(), info.amount_invested, info.share_percentage, metadata),
+    ).await;
+    
+    match result {
+        Ok((Ok(token_id),)) => {
+            VAULT_STATE.with(|state_ref| {
+                if let Some(ref mut state) = *state_ref.borrow_mut() {
+                    if let Some(backer_info) = state.backers.get_mut(&backer) {
+                        backer_info.nft_token_id = Some(token_id);
+                    }
+                }
+            });
+            record_event(caller, VaultEventKind::NftMinted { backer, token_id });
+            Ok(token_id)
+        },
+        Ok((Err(e),)) => Err(e),
+        Err(e) => Err(format!("Failed to call NFT registry: {:?}", e)),
+    }
+}
+
+// A backer gets one position NFT, minted once the investment is confirmed
+fn mint_candidate(state: &VaultState, backer: Principal) -> Result<BackerInfo, String> {
+    let info = state.backers.get(&backer).ok_or_else(|| "Backer not found".to_string())?;
+    if let Some(token_id) = info.nft_token_id {
+        return Err(format!("Backer already holds position NFT {}", token_id));
+    }
+    Ok(info.clone())
+}
+
+#[update]
+fn update_revenue(report: RevenueReport) -> Result<u64, VaultError> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            ensure_operation_enabled(state, VaultOperation::UpdateRevenue)?;
+            Ok(submit_revenue_report(state, report, caller, now)?)
+        } else {
+            Err("Vault not initialized".to_string().into())
+        }
+    })
+}
+
+fn submit_revenue_report(state: &mut VaultState, report: RevenueReport, caller: Principal, now: u64) -> Result<u64, String> {
+    // Only the configured oracle reports verified revenue; the creator may
+    // self-report, but those reports are held until the oracle confirms them
+    let verified = if state.oracle_canister == Some(caller) {
+        true
+    } else if state.creator == caller {
+        false
+    } else {
+        return Err("Only the oracle canister or the creator can report revenue".to_string());
+    };
+    
+    if state.vault_status != VaultStatus::Active {
+        return Err("Vault is closed".to_string());
+    }
+    
+    validate_revenue_report(state, &report, now)?;
+    
+    // Revenue in another token is booked at its base value and paid out in that token
+    let (amount, token) = match report.token.filter(|t| state.ledger_canister != Some(*t)) {
+        Some(ledger) => {
+            let rate = cached_rate(state, ledger, MAX_REVENUE_RATE_AGE_SECS, now)
+                .ok_or_else(|| "No recent exchange rate for the revenue token, call refresh_exchange_rate first".to_string())?;
+            (to_base_value(state, ledger, report.amount, &rate)?, Some(ledger))
+        }
+        None => (report.amount, None),
+    };
+    
+    state.revenue_report_counter += 1;
+    let report_id = state.revenue_report_counter;
+    
+    let revenue_update = RevenueUpdate {
+        report_id,
+        amount,
+        source: report.source,
+        timestamp: now,
+        oracle_verification: verified,
+        idempotency_key: report.idempotency_key,
+        period_start: report.period_start,
+        period_end: report.period_end,
+        reported_by: caller,
+        token,
+        token_amount: report.amount,
+        investor_amount: 0,
+        creator_amount: 0,
+        phase: None,
+    };
+    
+    record_event(caller, VaultEventKind::RevenueReported {
+        report_id,
+        amount: revenue_update.amount,
+        source: revenue_update.source.clone(),
+        verified,
+    });
+    
+    if verified {
+        ic_cdk::println!("Revenue updated: {} from {}", revenue_update.amount, revenue_update.source);
+        apply_verified_revenue(state, revenue_update, caller, now);
+    } else {
+        ic_cdk::println!("Unverified revenue report {} held for confirmation", report_id);
+        state.pending_revenue_reports.push(revenue_update);
+    }
+    
+    Ok(report_id)
+}
+
+fn validate_revenue_report(state: &VaultState, report: &RevenueReport, now: u64) -> Result<(), String> {
+    if report.idempotency_key.is_empty() {
+        return Err("Revenue report requires an idempotency key".to_string());
+    }
+    
+    if report.period_start >= report.period_end {
+        return Err("Revenue period start must be before period end".to_string());
+    }
+    
+    if report.period_end > now {
+        return Err("Revenue period cannot end in the future".to_string());
+    }
+    
+    // Revenue earned after the term belongs to the creator alone
+    if let Some(end) = term_end(state) {
+        if report.period_end > end {
+            return Err("Revenue period extends past the end of the revenue share term".to_string());
+        }
+    }
+    
+    let known_reports = state.revenue_history.iter().chain(state.pending_revenue_reports.iter());
+    for existing in known_reports {
+        if existing.idempotency_key == report.idempotency_key {
+            return Err(format!(
+                "Duplicate revenue report: key {} already used by report {}",
+                report.idempotency_key, existing.report_id
+            ));
+        }
+        
+        // Periods for the same source must not overlap, otherwise revenue is double counted
+        if existing.source == report.source
+            && report.period_start < existing.period_end
+            && existing.period_start < report.period_end
+        {
+            return Err(format!(
+                "Revenue period overlaps report {} from source {}",
+                existing.report_id, existing.source
+            ));
+        }
+    }
+    
+    Ok(())
+}
+
+// Splits verified revenue between backers and the creator and records it
+fn apply_verified_revenue(state: &mut VaultState, mut revenue_update: RevenueUpdate, verified_by: Principal, now: u64) {
+    let (mut investor_amount, mut creator_amount, phase) = allocate_revenue(state, revenue_update.amount);
+    
+    // Slashed portions of the creator share belong to backers
+    let diverted = creator_amount * state.creator_slash_percentage as u64 / 100;
+    creator_amount -= diverted;
+    investor_amount += diverted;
+    
+    let senior_first = phase == WaterfallPhase::Recoupment
+        && state.distribution_waterfall.as_ref().is_some_and(|w| w.senior_tranche_enabled);
+    let credits = credit_and_record(
+        state,
+        investor_amount,
+        senior_first,
+        CreditSource::Revenue { report_id: revenue_update.report_id },
+        verified_by,
+        now,
+    );
+    
+    // The creator's share stays booked in base units
+    if let Some(ledger) = revenue_update.token {
+        credit_token_revenue(state, ledger, &credits, revenue_update.amount, revenue_update.token_amount);
+    }
+    
+    state.total_revenue += revenue_update.amount;
+    state.investor_revenue_total += investor_amount;
+    state.creator_revenue_total += creator_amount;
+    
+    record_event_at(verified_by, VaultEventKind::RevenueAllocated {
+        report_id: revenue_update.report_id,
+        investor_amount,
+        creator_amount,
+        phase: phase.clone(),
+    }, now);
+    
+    // Runs after this call returns, once the new entitlements are in place
+    if state.distribution_schedule == DistributionSchedule::AfterVerifiedRevenue {
+        let report_id = revenue_update.report_id;
+        ic_cdk_timers::set_timer(Duration::ZERO, move || {
+            spawn_distribution(DistributionTrigger::RevenueVerified { report_id })
+        });
+    }
+    
+    revenue_update.investor_amount = investor_amount;
+    revenue_update.creator_amount = creator_amount;
+    revenue_update.phase = Some(phase);
+    state.revenue_history.push(revenue_update);
+}
+
+fn total_principal(state: &VaultState) -> u64 {
+    state.backers.values().map(|b| b.principal).sum()
+}
+
+fn return_cap(state: &VaultState) -> Option<u64> {
+    state.distribution_waterfall.as_ref()
+        .and_then(|w| w.return_cap_percentage)
+        .map(|cap| (total_principal(state) as u128 * cap as u128 / 100) as u64)
+}
+
+fn waterfall_phase(state: &VaultState, investor_total: u64) -> WaterfallPhase {
+    match state.distribution_waterfall {
+        None => WaterfallPhase::FlatShare,
+        Some(ref waterfall) => {
+            if return_cap(state).is_some_and(|cap| investor_total >= cap) {
+                WaterfallPhase::Capped
+            } else if waterfall.recoupment_enabled && investor_total < total_principal(state) {
+                WaterfallPhase::Recoupment
+            } else {
+                WaterfallPhase::PostRecoupment
+            }
+        }
+    }
+}
+
+// Runs `amount` through the waterfall, returning the backer and creator portions and the starting phase
+fn allocate_revenue(state: &VaultState, amount: u64) -> (u64, u64, WaterfallPhase) {
+    let starting_phase = waterfall_phase(state, state.investor_revenue_total);
+    let principal = total_principal(state);
+    let cap = return_cap(state);
+    
+    let mut remaining = amount;
+    let mut investor = 0u64;
+    let mut creator = 0u64;
+    
+    // A revenue update can cross phase boundaries, so allocate it step by step
+    while remaining > 0 {
+        let investor_total = state.investor_revenue_total + investor;
+        
+        match waterfall_phase(state, investor_total) {
+            WaterfallPhase::Recoupment => {
+                let recouped = cmp::min(remaining, principal - investor_total);
+                investor += recouped;
+                remaining -= recouped;
+            },
+            WaterfallPhase::Capped => {
+                creator += remaining;
+                remaining = 0;
+            },
+            phase => {
+                let share_percentage = match (phase, state.distribution_waterfall.as_ref()) {
+                    (WaterfallPhase::PostRecoupment, Some(waterfall)) => waterfall.post_recoupment_share_percentage,
+                    _ => state.revenue_share_percentage,
+                } as u64;
+                let portion = remaining * share_percentage / 100;
+                let room = cap.map_or(u64::MAX, |cap| cap - investor_total);
+                
+                if portion <= room || share_percentage == 0 {
+                    investor += portion;
+                    creator += remaining - portion;
+                    remaining = 0;
+                } else {
+                    // Only part of this revenue fits under the cap
+                    let consumed = cmp::min(remaining, room * 100 / share_percentage);
+                    investor += room;
+                    creator += consumed - room.min(consumed);
+                    remaining -= consumed;
+                    if consumed == 0 {
+                        creator += remaining;
+                        remaining = 0;
+                    }
+                }
+            },
+        }
+    }
+    
+    (investor, creator, starting_phase)
+}
+
+// Adds `amount` to backer entitlements pro rata by principal, senior backers first when requested
+// Credits backers and logs what each one received
+fn credit_and_record(
+    state: &mut VaultState,
+    amount: u64,
+    senior_first: bool,
+    source: CreditSource,
+    caller: Principal,
+    now: u64,
+) -> Vec<(Principal, u64)> {
+    let before: HashMap<Principal, u64> = state.backers.iter()
+        .map(|(backer, info)| (*backer, info.revenue_entitlement))
+        .collect();
+    
+    credit_backers(state, amount, senior_first);
+    
+    let credits: Vec<(Principal, u64)> = state.backers.iter()
+        .map(|(backer, info)| (*backer, info.revenue_entitlement - before.get(backer).copied().unwrap_or(0)))
+        .filter(|(_, credit)| *credit > 0)
+        .collect();
+    
+    if !credits.is_empty() {
+        record_event_at(caller, VaultEventKind::EntitlementsCredited { source, credits: credits.clone() }, now);
+    }
+    credits
+}
+
+// Moves base credits from token revenue into entitlements in that token, so they are paid in it
+fn credit_token_revenue(state: &mut VaultState, ledger: Principal, credits: &[(Principal, u64)], value: u64, token_amount: u64) {
+    if value == 0 {
+        return;
+    }
+    
+    for (backer, credit) in credits {
+        let tokens = (*credit as u128 * token_amount as u128 / value as u128) as u64;
+        if let Some(info) = state.backers.get_mut(backer) {
+            info.token_credited_value += credit;
+            match info.token_entitlements.iter_mut().find(|e| e.ledger == ledger) {
+                Some(entitlement) => entitlement.entitled += tokens,
+                None => info.token_entitlements.push(TokenEntitlement { ledger, entitled: tokens, claimed: 0 }),
+            }
+        }
+    }
+}
+
+fn credit_backers(state: &mut VaultState, amount: u64, senior_first: bool) {
+    let mut remaining = amount;
+    
+    if senior_first {
+        let outstanding: Vec<(Principal, u64)> = state.backers.iter()
+            .filter(|(_, info)| info.tranche == BackerTranche::Senior)
+            .map(|(backer, info)| (*backer, revenue_weight(info).saturating_sub(info.revenue_entitlement)))
+            .filter(|(_, owed)| *owed > 0)
+            .collect();
+        let total_outstanding: u64 = outstanding.iter().map(|(_, owed)| owed).sum();
+        
+        if total_outstanding > 0 {
+            let senior_amount = cmp::min(remaining, total_outstanding);
+            for (backer, owed) in outstanding {
+                let credit = (senior_amount as u128 * owed as u128 / total_outstanding as u128) as u64;
+                if let Some(info) = state.backers.get_mut(&backer) {
+                    info.revenue_entitlement += credit;
+                }
+                remaining -= credit;
+            }
+        }
+        
+        if remaining == 0 {
+            return;
+        }
+        
+        let juniors: Vec<(Principal, u64)> = state.backers.iter()
+            .filter(|(_, info)| info.tranche == BackerTranche::Junior)
+            .map(|(backer, info)| (*backer, revenue_weight(info)))
+            .collect();
+        
+        // With no junior backers left to recoup, the remainder goes to everyone
+        if juniors.iter().any(|(_, principal)| *principal > 0) {
+            credit_pro_rata(state, &juniors, remaining);
+            return;
+        }
+    }
+    
+    let everyone: Vec<(Principal, u64)> = state.backers.iter()
+        .map(|(backer, info)| (*backer, revenue_weight(info)))
+        .collect();
+    credit_pro_rata(state, &everyone, remaining);
+}
+
+// Backers who took an escrow refund have exited and earn no further revenue
+fn revenue_weight(info: &BackerInfo) -> u64 {
+    if info.refunded_amount > 0 {
+        0
+    } else {
+        info.principal
+    }
+}
+
+fn credit_pro_rata(state: &mut VaultState, weights: &[(Principal, u64)], amount: u64) {
+    let total_weight: u64 = weights.iter().map(|(_, weight)| weight).sum();
+    if total_weight == 0 {
+        return;
+    }
+    
+    for (backer, weight) in weights {
+        let credit = (amount as u128 * *weight as u128 / total_weight as u128) as u64;
+        if let Some(info) = state.backers.get_mut(backer) {
+            info.revenue_entitlement += credit;
+        }
+    }
+}
+
+#[update]
+fn set_distribution_waterfall(waterfall: Option<DistributionWaterfall>) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can configure the distribution waterfall".to_string());
+            }
+            
+            // Backers invested under these terms, so they are fixed once revenue has been allocated
+            if !state.revenue_history.is_empty() {
+                return Err("Distribution waterfall cannot change after revenue has been allocated".to_string());
+            }
+            
+            if let Some(ref w) = waterfall {
+                if w.post_recoupment_share_percentage > 100 {
+                    return Err("Post-recoupment share cannot exceed 100%".to_string());
+                }
+                if w.return_cap_percentage.is_some_and(|cap| cap < 100) {
+                    return Err("Return cap must be at least 100% of principal".to_string());
+                }
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "distribution_waterfall".to_string(),
+                value: format!("{:?}", waterfall),
+            });
+            
+            state.distribution_waterfall = waterfall;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[update]
+fn set_backer_tranche(backer: Principal, tranche: BackerTranche) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can assign tranches".to_string());
+            }
+            
+            if !state.revenue_history.is_empty() {
+                return Err("Tranches cannot change after revenue has been allocated".to_string());
+            }
+            
+            match state.backers.get_mut(&backer) {
+                Some(info) => {
+                    record_event(caller, VaultEventKind::SettingsChanged {
+                        setting: "backer_tranche".to_string(),
+                        value: format!("{}: {:?}", backer.to_text(), tranche),
+                    });
+                    
+                    info.tranche = tranche;
+                    Ok(())
+                },
+                None => Err("Backer not found".to_string()),
+            }
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[query]
+fn get_waterfall_status() -> Option<WaterfallStatus> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| WaterfallStatus {
+            phase: waterfall_phase(s, s.investor_revenue_total),
+            principal: total_principal(s),
+            investor_revenue_total: s.investor_revenue_total,
+            creator_revenue_total: s.creator_revenue_total,
+            return_cap: return_cap(s),
+        })
+    })
+}
+
+#[update]
+fn confirm_revenue_report(report_id: u64) -> Result<(), VaultError> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            ensure_operation_enabled(state, VaultOperation::UpdateRevenue)?;
+            
+            if state.oracle_canister != Some(caller) {
+                return Err("Only the oracle canister can confirm revenue reports".to_string().into());
+            }
+            
+            if state.vault_status != VaultStatus::Active {
+                return Err("Vault is closed".to_string().into());
+            }
+            
+            if let Some(index) = state.pending_revenue_reports.iter().position(|r| r.report_id == report_id) {
+                let mut revenue_update = state.pending_revenue_reports.remove(index);
+                revenue_update.oracle_verification = true;
+                
+                apply_verified_revenue(state, revenue_update, caller, time());
+                
+                Ok(())
+            } else {
+                Err(format!("Pending revenue report {} not found", report_id).into())
+            }
+        } else {
+            Err("Vault not initialized".to_string().into())
+        }
+    })
+}
+
+#[update]
+fn reject_revenue_report(report_id: u64, reason: String) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.oracle_canister != Some(caller) {
+                return Err("Only the oracle canister can reject revenue reports".to_string());
+            }
+            
+            if let Some(index) = state.pending_revenue_reports.iter().position(|r| r.report_id == report_id) {
+                state.pending_revenue_reports.remove(index);
+                ic_cdk::println!("Revenue report {} rejected: {}", report_id, reason);
+                record_event(caller, VaultEventKind::RevenueRejected { report_id, reason });
+                Ok(())
+            } else {
+                Err(format!("Pending revenue report {} not found", report_id))
+            }
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[query]
+fn get_pending_revenue_reports() -> Vec<RevenueUpdate> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.pending_revenue_reports.clone()).unwrap_or_default()
+    })
+}
+
+// Reported revenue is only a number until it is paid in. Anyone can deposit it, usually the creator,
+// after approving the vault for `amount` plus the ledger fee on the token's ledger.
+#[update]
+async fn deposit_revenue(token: Option<Principal>, amount: u64) -> Result<u64, String> {
+    let caller = msg_caller();
+    
+    if amount == 0 {
+        return Err("Deposit amount must be greater than 0".to_string());
+    }
+    
+    let ledger = VAULT_STATE.with(|state_ref| {
+        let state_opt = state_ref.borrow();
+        if let Some(ref state) = *state_opt {
+            match token {
+                Some(ledger) => accepted_token(state, ledger).map(|t| t.ledger),
+                None => state.ledger_canister.ok_or_else(|| "Ledger canister not configured".to_string()),
+            }
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    let block_index = token_transfer_from(ledger, caller, amount, time()).await?;
+    
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            revenue_fund(state, token).deposited += amount;
+            record_event(caller, VaultEventKind::RevenueDeposited { token, amount, block_index });
+        }
+    });
+    
+    Ok(block_index)
+}
+
+fn revenue_fund(state: &mut VaultState, token: Option<Principal>) -> &mut RevenueFund {
+    let index = match state.revenue_funds.iter().position(|f| f.token == token) {
+        Some(index) => index,
+        None => {
+            state.revenue_funds.push(RevenueFund { token, deposited: 0, disbursed: 0 });
+            state.revenue_funds.len() - 1
+        }
+    };
+    &mut state.revenue_funds[index]
+}
+
+// Deposited revenue in `token` that has not been paid out yet
+fn revenue_available(state: &VaultState, token: Option<Principal>) -> u64 {
+    state.revenue_funds.iter()
+        .find(|f| f.token == token)
+        .map_or(0, |f| f.deposited - f.disbursed)
+}
+
+// What a backer can still be paid in the base token. Revenue credited in other tokens is streamed
+// from those ledgers instead.
+fn base_claimable(info: &BackerInfo) -> u64 {
+    (info.revenue_entitlement + info.sale_proceeds)
+        .saturating_sub(info.token_credited_value)
+        .saturating_sub(info.total_claimed)
+}
+
+// Base revenue left once backers' unpaid entitlements and the reinvestment pool are covered
+fn creator_revenue_available(state: &VaultState) -> u64 {
+    let owed_to_backers: u64 = state.backers.values().map(base_claimable).sum();
+    revenue_available(state, None).saturating_sub(owed_to_backers + state.reinvestment_pool)
+}
+
+#[query]
+fn get_revenue_funds() -> Vec<RevenueFund> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.revenue_funds.clone()).unwrap_or_default()
+    })
+}
+
+#[update]
+async fn distribute_payouts() -> Result<Vec<(Principal, u64)>, VaultError> {
+    run_distribution(DistributionTrigger::Manual, msg_caller()).await
+}
+
+// Streams every backer's unclaimed entitlement and records the round
+async fn run_distribution(trigger: DistributionTrigger, caller: Principal) -> Result<Vec<(Principal, u64)>, VaultError> {
+    let automatic = !matches!(trigger, DistributionTrigger::Manual | DistributionTrigger::Settlement);
+    
+    let _guard = match CallGuard::operation(GuardedOperation::Distribution) {
+        Ok(guard) => guard,
+        Err(e) => {
+            // The running round will pick up this trigger's entitlements once it finishes
+            if automatic {
+                VAULT_STATE.with(|state_ref| {
+                    if let Some(ref mut state) = *state_ref.borrow_mut() {
+                        state.distribution_rerun_requested = true;
+                    }
+                });
+            }
+            return Err(e.into());
+        }
+    };
+    
+    let (stream_canister, base_ledger, payouts, token_payouts) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            ensure_operation_enabled(state, VaultOperation::DistributePayouts)?;
+            
+            let stream_canister = state.stream_canister
+                .ok_or_else(|| "Stream canister not configured".to_string())?;
+            let base_ledger = state.ledger_canister
+                .ok_or_else(|| "Ledger canister not configured".to_string())?;
+            
+            // Opted-in backers' entitlements are taken out before payouts are reserved
+            reinvest_entitlements(state, caller, time());
+            
+            // Only deposited revenue is streamed, the rest stays claimable for a later round
+            let reserved = reserve_payouts(state);
+            let (payouts, unfunded) = fund_payouts(state, None, reserved);
+            release_payouts(state, &unfunded);
+            
+            let mut token_payouts = Vec::new();
+            for (ledger, reserved) in reserve_token_payouts(state) {
+                let (group, unfunded) = fund_payouts(state, Some(ledger), reserved);
+                release_token_payouts(state, ledger, &unfunded);
+                if !group.is_empty() {
+                    token_payouts.push((ledger, group));
+                }
+            }
+            Ok((stream_canister, base_ledger, payouts, token_payouts))
+        } else {
+            Err(VaultError::from("Vault not initialized".to_string()))
+        }
+    })?;
+    
+    if payouts.is_empty() && token_payouts.is_empty() {
+        return Ok(payouts);
+    }
+    
+    // Each token is streamed separately, so one ledger failing does not hold back the others
+    let started_at = time();
+    let base_outcome = if payouts.is_empty() {
+        Ok(Vec::new())
+    } else {
+        create_payout_streams(stream_canister, base_ledger, &payouts).await
+    };
+    let mut token_outcomes = Vec::new();
+    for (ledger, group) in &token_payouts {
+        token_outcomes.push(create_payout_streams(stream_canister, *ledger, group).await);
+    }
+    
+    let (retry_attempt, rerun, failure) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        let state = match *state_opt {
+            Some(ref mut state) => state,
+            None => return (None, false, Some("Vault not initialized".to_string())),
+        };
+        
+        let round_id = state.distribution_rounds.len() as u64 + 1;
+        let mut retry_attempt = None;
+        let mut stream_ids = Vec::new();
+        let mut errors = Vec::new();
+        
+        match base_outcome {
+            Ok(ref ids) => {
+                if !payouts.is_empty() {
+                    record_event(caller, VaultEventKind::Distribution { payouts: payouts.clone() });
+                }
+                stream_ids.extend(ids.iter().copied());
+            }
+            Err(ref e) => {
+                release_payouts(state, &payouts);
+                revenue_fund(state, None).disbursed -= payouts.iter().map(|(_, amount)| amount).sum::<u64>();
+                errors.push(e.clone());
+            }
+        }
+        
+        let mut token_totals = Vec::new();
+        for ((ledger, group), outcome) in token_payouts.iter().zip(token_outcomes) {
+            match outcome {
+                Ok(ids) => {
+                    record_event(caller, VaultEventKind::TokenDistribution { ledger: *ledger, payouts: group.clone() });
+                    token_totals.push((*ledger, group.iter().map(|(_, amount)| amount).sum()));
+                    stream_ids.extend(ids);
+                }
+                Err(e) => {
+                    release_token_payouts(state, *ledger, group);
+                    revenue_fund(state, Some(*ledger)).disbursed -= group.iter().map(|(_, amount)| amount).sum::<u64>();
+                    errors.push(format!("{}: {}", ledger.to_text(), e));
+                }
+            }
+        }
+        
+        let failure = if errors.is_empty() { None } else { Some(errors.join("; ")) };
+        let status = match failure {
+            None => {
+                state.distribution_retry_attempt = 0;
+                DistributionRoundStatus::Succeeded { stream_ids }
+            }
+            Some(ref error) => {
+                record_event(caller, VaultEventKind::DistributionFailed { round_id, error: error.clone() });
+                
+                // Automatic rounds retry with backoff, manual callers get the error back
+                if automatic {
+                    if state.distribution_retry_attempt < MAX_DISTRIBUTION_RETRIES {
+                        state.distribution_retry_attempt += 1;
+                        retry_attempt = Some(state.distribution_retry_attempt);
+                    } else {
+                        state.distribution_retry_attempt = 0;
+                    }
+                }
+                DistributionRoundStatus::Failed { error: error.clone() }
+            }
+        };
+        
+        let base_streamed = base_outcome.is_ok();
+        state.distribution_rounds.push(DistributionRound {
+            round_id,
+            trigger,
+            caller,
+            started_at,
+            completed_at: time(),
+            recipients: if base_streamed { payouts.len() as u64 } else { 0 },
+            total_amount: if base_streamed { payouts.iter().map(|(_, amount)| amount).sum() } else { 0 },
+            token_totals,
+            status,
+        });
+        
+        let rerun = std::mem::take(&mut state.distribution_rerun_requested);
+        (retry_attempt, rerun && retry_attempt.is_none(), failure)
+    });
+    
+    if let Some(attempt) = retry_attempt {
+        let delay = DISTRIBUTION_RETRY_BASE_SECS << (attempt - 1);
+        ic_cdk_timers::set_timer(Duration::from_secs(delay), move || {
+            spawn_distribution(DistributionTrigger::Retry { attempt })
+        });
+    } else if rerun {
+        ic_cdk_timers::set_timer(Duration::ZERO, || spawn_distribution(DistributionTrigger::Scheduled));
+    }
+    
+    match failure {
+        Some(error) => Err(VaultError::from(error)),
+        None => Ok(payouts),
+    }
+}
+
+// Streams are funded up front: ipx-stream pulls the whole batch into the vault's escrow subaccount
+async fn create_payout_streams(
+    stream_canister: Principal,
+    ledger: Principal,
+    payouts: &[(Principal, u64)],
+) -> Result<Vec<u64>, String> {
+    let total: u64 = payouts.iter().map(|(_, amount)| amount).sum();
+    token_approve(ledger, stream_canister, total).await?;
+    
+    let result: CallResult<(Result<Vec<u64>, String>,)> = call(
+        stream_canister,
+        "create_streams",
+        (ledger, payouts.to_vec()),
+    ).await;
+    
+    match result {
+        Ok((Ok(stream_ids),)) => Ok(stream_ids),
+        Ok((Err(e),)) => Err(e),
+        Err(e) => Err(format!("Failed to create streams: {:?}", e)),
+    }
+}
+
+// Marks every backer's unstreamed entitlement as claimed, so an overlapping round finds nothing to pay
+fn reserve_payouts(state: &mut VaultState) -> Vec<(Principal, u64)> {
+    let mut payouts = Vec::new();
+    for (backer, info) in state.backers.iter_mut() {
+        let claimable = base_claimable(info);
+        
+        if claimable > 0 {
+            info.total_claimed += claimable;
+            payouts.push((*backer, claimable));
+        }
+    }
+    payouts
+}
+
+// Cuts reserved payouts down pro rata to the deposited revenue in `token` and books the funded part
+// as disbursed. Returns the payouts to stream and the unfunded remainder to release.
+fn fund_payouts(
+    state: &mut VaultState,
+    token: Option<Principal>,
+    reserved: Vec<(Principal, u64)>,
+) -> (Payouts, Payouts) {
+    let total: u64 = reserved.iter().map(|(_, amount)| amount).sum();
+    let available = revenue_available(state, token);
+    if total <= available {
+        revenue_fund(state, token).disbursed += total;
+        return (reserved, Vec::new());
+    }
+    
+    let mut funded = Vec::new();
+    let mut unfunded = Vec::new();
+    for (backer, amount) in reserved {
+        let share = (amount as u128 * available as u128 / total as u128) as u64;
+        if share > 0 {
+            funded.push((backer, share));
+        }
+        if amount > share {
+            unfunded.push((backer, amount - share));
+        }
+    }
+    revenue_fund(state, token).disbursed += funded.iter().map(|(_, amount)| amount).sum::<u64>();
+    (funded, unfunded)
+}
+
+// Turns opted-in backers' unclaimed base entitlements into shares. Once the campaign is fully
+// funded they wait in the reinvestment pool, which buys shares whenever room opens under the goal
+// and otherwise from backers' sell orders.
+fn reinvest_entitlements(state: &mut VaultState, caller: Principal, now: u64) {
+    // A closing or refunding vault pays the pool out with everything else
+    if state.vault_status != VaultStatus::Active || state.releases_paused {
+        release_reinvestment_pool(state);
+        return;
+    }
+    
+    let mut added = HashMap::new();
+    for (backer, info) in state.backers.iter_mut().filter(|(_, b)| b.auto_reinvest && b.token.is_none()) {
+        let claimable = base_claimable(info);
+        
+        if claimable > 0 {
+            info.total_claimed += claimable;
+            info.reinvestment_balance += claimable;
+            state.reinvestment_pool += claimable;
+            added.insert(*backer, claimable);
+        }
+    }
+    
+    let invested = buy_from_reinvestment_pool(state, caller, now);
+    
+    let mut backers: Vec<Principal> = added.keys().chain(invested.keys()).copied().collect();
+    backers.sort();
+    backers.dedup();
+    for backer in backers {
+        record_event_at(caller, VaultEventKind::Reinvestment {
+            backer,
+            invested: invested.get(&backer).copied().unwrap_or(0),
+            pooled: state.backers.get(&backer).map_or(0, |b| b.reinvestment_balance),
+        }, now);
+    }
+}
+
+// Buys shares at par, the price every backer paid, and returns what each backer invested
+fn buy_from_reinvestment_pool(state: &mut VaultState, caller: Principal, now: u64) -> HashMap<Principal, u64> {
+    // Largest balances first, ties by principal, so the order does not depend on the map
+    let mut pooled: Vec<(Principal, u64)> = state.backers.iter()
+        .filter(|(_, info)| info.reinvestment_balance > 0)
+        .map(|(backer, info)| (*backer, info.reinvestment_balance))
+        .collect();
+    pooled.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    
+    let mut invested = HashMap::new();
+    for (backer, balance) in pooled {
+        let remaining_funding = state.funding_goal
+            .saturating_sub(state.current_funding + state.pending_funding);
+        // Shares are only bought with revenue that has actually been deposited
+        let amount = balance.min(remaining_funding).min(revenue_available(state, None));
+        if amount == 0 {
+            break;
+        }
+        
+        // A backer at their cap keeps the balance pooled until they opt out
+        let insurance_fee = insurance_fee_for(state, amount);
+        if check_eligibility(state, backer, amount, amount - insurance_fee, now).is_err() {
+            continue;
+        }
+        
+        state.reinvestment_pool -= amount;
+        revenue_fund(state, None).disbursed += amount;
+        if let Some(info) = state.backers.get_mut(&backer) {
+            info.reinvestment_balance -= amount;
+        }
+        
+        state.pending_investment_counter += 1;
+        let investment = PendingInvestment {
+            investment_id: state.pending_investment_counter,
+            backer,
+            amount,
+            insurance_fee,
+            insured_by_pool: state.insurance_policy.is_some(),
+            token: None,
+            token_amount: amount,
+            invested_at: now,
+            confirms_at: now,
+        };
+        confirm_investment(state, investment, caller, now);
+        invested.insert(backer, amount);
+    }
+    
+    for (buyer, amount) in buy_from_sell_orders(state, caller, now) {
+        *invested.entry(buyer).or_insert(0) += amount;
+    }
+    invested
+}
+
+// Matches what is left in the pool against sell orders at par. The seller's principal moves to the
+// buyer, and the price comes out of the buyer's pooled revenue and is owed to the seller, who is paid
+// by the next distribution. The bought principal joins the buyer's tranche.
+fn buy_from_sell_orders(state: &mut VaultState, caller: Principal, now: u64) -> HashMap<Principal, u64> {
+    // Oldest positions sell first, ties by principal
+    let mut orders: Vec<(Principal, u64, u64)> = state.backers.iter()
+        .filter(|(_, info)| info.sell_order > 0)
+        .map(|(seller, info)| (*seller, info.investment_timestamp, info.sell_order))
+        .collect();
+    orders.sort_by(|a, b| a.1.cmp(&b.1).then(a.0.cmp(&b.0)));
+    
+    let mut pooled: Vec<(Principal, u64)> = state.backers.iter()
+        .filter(|(_, info)| info.reinvestment_balance > 0)
+        .map(|(backer, info)| (*backer, info.reinvestment_balance))
+        .collect();
+    pooled.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    
+    let mut bought = HashMap::new();
+    for (buyer, mut balance) in pooled {
+        for (seller, _, order) in orders.iter_mut() {
+            if balance == 0 {
+                break;
+            }
+            if *seller == buyer || *order == 0 {
+                continue;
+            }
+            
+            let position = state.backers.get(seller).map_or(0, |info| info.principal);
+            let amount = balance.min(*order).min(position);
+            if amount == 0 || check_eligibility(state, buyer, amount, amount, now).is_err() {
+                continue;
+            }
+            
+            if let Some(info) = state.backers.get_mut(seller) {
+                info.principal -= amount;
+                info.amount_invested = info.amount_invested.saturating_sub(amount);
+                info.sell_order -= amount;
+                info.sale_proceeds += amount;
+                info.share_percentage = (info.principal as f64 / state.funding_goal as f64) * 100.0;
+            }
+            if let Some(info) = state.backers.get_mut(&buyer) {
+                info.principal += amount;
+                info.amount_invested += amount;
+                info.reinvestment_balance -= amount;
+                info.share_percentage = (info.principal as f64 / state.funding_goal as f64) * 100.0;
+            }
+            state.reinvestment_pool -= amount;
+            *order -= amount;
+            balance -= amount;
+            *bought.entry(buyer).or_insert(0) += amount;
+            
+            record_event_at(caller, VaultEventKind::SharesSold { seller: *seller, buyer, amount }, now);
+        }
+    }
+    bought
+}
+
+// Offers part of the caller's position to the reinvestment pool at par, zero withdraws the order
+#[update]
+fn place_sell_order(amount: u64) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if amount > 0 && (state.vault_status != VaultStatus::Active || state.releases_paused) {
+                return Err("Sell orders are only matched while the vault is active".to_string());
+            }
+            
+            let info = state.backers.get_mut(&caller)
+                .ok_or_else(|| "Only backers can sell shares".to_string())?;
+            
+            if amount > 0 {
+                // Token positions are backed by token capital the pool cannot take over
+                if info.token.is_some() {
+                    return Err("Only positions in the base token can be sold to the pool".to_string());
+                }
+                if info.refunded_amount > 0 {
+                    return Err("Position was refunded from escrow".to_string());
+                }
+                if info.auto_reinvest {
+                    return Err("Turn off auto-reinvest before selling shares".to_string());
+                }
+                if amount > info.principal {
+                    return Err("Sell order exceeds the position".to_string());
+                }
+            }
+            
+            info.sell_order = amount;
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: format!("sell_order:{}", caller.to_text()),
+                value: amount.to_string(),
+            });
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Makes pooled balances claimable again, so the next distribution streams them
+fn release_reinvestment_pool(state: &mut VaultState) {
+    for info in state.backers.values_mut() {
+        info.total_claimed = info.total_claimed.saturating_sub(info.reinvestment_balance);
+        info.reinvestment_balance = 0;
+    }
+    state.reinvestment_pool = 0;
+}
+
+#[update]
+fn set_auto_reinvest(enabled: bool) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            let info = state.backers.get_mut(&caller)
+                .ok_or_else(|| "Only backers can change reinvestment".to_string())?;
+            
+            if enabled && info.token.is_some() {
+                return Err("Reinvestment is only available for positions in the base token".to_string());
+            }
+            if enabled && info.sell_order > 0 {
+                return Err("Withdraw the sell order before turning on auto-reinvest".to_string());
+            }
+            
+            info.auto_reinvest = enabled;
+            
+            // Opting out hands back anything still waiting in the pool
+            if !enabled && info.reinvestment_balance > 0 {
+                let balance = info.reinvestment_balance;
+                info.total_claimed -= balance;
+                info.reinvestment_balance = 0;
+                state.reinvestment_pool -= balance;
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: format!("auto_reinvest:{}", caller.to_text()),
+                value: enabled.to_string(),
+            });
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Undoes reserve_payouts when the streams could not be created
+fn release_payouts(state: &mut VaultState, payouts: &[(Principal, u64)]) {
+    for (backer, amount) in payouts {
+        if let Some(info) = state.backers.get_mut(backer) {
+            info.total_claimed = info.total_claimed.saturating_sub(*amount);
+        }
+    }
+}
+
+// The same as reserve_payouts for token entitlements, grouped by ledger
+fn reserve_token_payouts(state: &mut VaultState) -> Vec<(Principal, Vec<(Principal, u64)>)> {
+    let mut groups: Vec<(Principal, Vec<(Principal, u64)>)> = Vec::new();
+    for (backer, info) in state.backers.iter_mut() {
+        for entitlement in info.token_entitlements.iter_mut() {
+            let claimable = entitlement.entitled.saturating_sub(entitlement.claimed);
+            if claimable == 0 {
+                continue;
+            }
+            
+            entitlement.claimed += claimable;
+            match groups.iter_mut().find(|(ledger, _)| *ledger == entitlement.ledger) {
+                Some((_, payouts)) => payouts.push((*backer, claimable)),
+                None => groups.push((entitlement.ledger, vec![(*backer, claimable)])),
+            }
+        }
+    }
+    groups
+}
+
+fn release_token_payouts(state: &mut VaultState, ledger: Principal, payouts: &[(Principal, u64)]) {
+    for (backer, amount) in payouts {
+        if let Some(entitlement) = state.backers.get_mut(backer)
+            .and_then(|info| info.token_entitlements.iter_mut().find(|e| e.ledger == ledger))
+        {
+            entitlement.claimed = entitlement.claimed.saturating_sub(*amount);
+        }
+    }
+}
+
+fn spawn_distribution(trigger: DistributionTrigger) {
+    ic_cdk::futures::spawn(async move {
+        // Failures are recorded on the round and retried from there
+        let _ = run_distribution(trigger, canister_self()).await;
+    });
+}
+
+// Replaces the periodic distribution timer to match the schedule
+fn install_distribution_timer(schedule: &DistributionSchedule) {
+    DISTRIBUTION_TIMER.with(|timer_ref| {
+        let mut timer = timer_ref.borrow_mut();
+        if let Some(timer_id) = timer.take() {
+            ic_cdk_timers::clear_timer(timer_id);
+        }
+        if let Some(secs) = schedule.interval_secs() {
+            *timer = Some(ic_cdk_timers::set_timer_interval(
+                Duration::from_secs(secs),
+                || spawn_distribution(DistributionTrigger::Scheduled),
+            ));
+        }
+    });
+}
+
+#[update]
+fn set_distribution_schedule(schedule: DistributionSchedule) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller && !is_governance(state, caller) {
+                return Err("Only the creator or governance can set the distribution schedule".to_string());
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "distribution_schedule".to_string(),
+                value: format!("{:?}", schedule),
+            });
+            
+            install_distribution_timer(&schedule);
+            state.distribution_schedule = schedule;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[query]
+fn get_distribution_schedule() -> Option<DistributionSchedule> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.distribution_schedule.clone())
+    })
+}
+
+// Most recent rounds first
+#[query]
+fn get_distribution_rounds(limit: u64) -> Vec<DistributionRound> {
+    let limit = limit.min(MAX_EVENT_PAGE_SIZE) as usize;
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map_or_else(Vec::new, |s| {
+            s.distribution_rounds.iter().rev().take(limit).cloned().collect()
+        })
+    })
+}
+
+// Stream claims are paid by ipx-stream from the vault's escrow, so the payouts are read back from it
+// and recorded in settlement order. Anyone can trigger it, the timer also does.
+#[update]
+async fn sync_stream_payouts() -> Result<u64, String> {
+    let _guard = CallGuard::operation(GuardedOperation::StreamPayoutSync)?;
+    
+    let stream_canister = VAULT_STATE.with(|state_ref| state_ref.borrow().as_ref().and_then(|s| s.stream_canister))
+        .ok_or_else(|| "Stream canister not configured".to_string())?;
+    
+    let mut booked = 0;
+    loop {
+        let offset = VAULT_STATE.with(|state_ref| {
+            state_ref.borrow().as_ref().map(|s| s.stream_payouts_booked).unwrap_or(0)
+        });
+        
+        let result: CallResult<(Vec<SettledClaim>,)> = call(
+            stream_canister,
+            "get_settled_claims",
+            (canister_self(), offset, SETTLED_CLAIMS_PAGE_SIZE),
+        ).await;
+        let (claims,) = result.map_err(|e| format!("Failed to fetch settled claims: {:?}", e))?;
+        
+        let now = time();
+        VAULT_STATE.with(|state_ref| {
+            if let Some(ref mut state) = *state_ref.borrow_mut() {
+                book_stream_payouts(state, stream_canister, &claims, now);
+            }
+        });
+        
+        booked += claims.len() as u64;
+        if (claims.len() as u64) < SETTLED_CLAIMS_PAGE_SIZE {
+            return Ok(booked);
+        }
+    }
+}
+
+fn book_stream_payouts(state: &mut VaultState, stream_canister: Principal, claims: &[SettledClaim], now: u64) {
+    for claim in claims {
+        let kind = if Some(claim.ledger) == state.ledger_canister {
+            VaultEventKind::StreamPayout {
+                recipient: claim.recipient,
+                amount: claim.amount,
+                block_index: claim.block_index,
+            }
+        } else {
+            VaultEventKind::TokenStreamPayout {
+                ledger: claim.ledger,
+                recipient: claim.recipient,
+                amount: claim.amount,
+                block_index: claim.block_index,
+            }
+        };
+        record_event_at(stream_canister, kind, now);
+    }
+    state.stream_payouts_booked += claims.len() as u64;
+}
+
+fn get_campaign_id() -> u64 {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.campaign_id).unwrap_or(0)
+    })
+}
+
+fn get_nft_registry_canister() -> Option<Principal> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.nft_registry_canister)
+    })
+}
+
+fn get_stream_canister() -> Option<Principal> {
+    VAULT_STATE.with(|state_ref| {
         state_ref.borrow().as_ref().and_then(|s| s.stream_canister)
     })
 }
 
-#[query]
-fn get_vault_state() -> Option<VaultState> {
-    VAULT_STATE.with(|state_ref| {
-        state_ref.borrow().clone()
-    })
+fn get_ledger_canister() -> Option<Principal> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.ledger_canister)
+    })
+}
+
+fn is_governance(state: &VaultState, caller: Principal) -> bool {
+    state.dao_canister == Some(caller)
+}
+
+// Payouts from the vault's default account
+
+fn book_payout(
+    state: &mut VaultState,
+    kind: PayoutKind,
+    token: Option<Principal>,
+    to: Principal,
+    amount: u64,
+    memo: u64,
+    now: u64,
+) -> u64 {
+    state.payout_counter += 1;
+    state.pending_payouts.push(PendingPayout {
+        payout_id: state.payout_counter,
+        kind,
+        token,
+        to,
+        amount,
+        memo,
+        created_at_time: now,
+        attempts: 0,
+        last_error: None,
+    });
+    state.payout_counter
+}
+
+async fn settle_payout(payout_id: u64) -> Result<u64, String> {
+    match send_payout(payout_id).await {
+        TransferOutcome::Completed(block_index) => Ok(block_index),
+        TransferOutcome::Rejected(error) => Err(error),
+        TransferOutcome::Unknown(error) => {
+            Err(format!("Payout {} is pending, retry it to settle: {}", payout_id, error))
+        },
+    }
+}
+
+// Sends a booked payout and commits or undoes its booking; an unknown outcome leaves it pending
+async fn send_payout(payout_id: u64) -> TransferOutcome {
+    let _guard = match CallGuard::payout(payout_id) {
+        Ok(guard) => guard,
+        Err(e) => return TransferOutcome::Unknown(e),
+    };
+    
+    let payout = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        let state = state_opt.as_mut()?;
+        let base_ledger = state.ledger_canister;
+        let payout = state.pending_payouts.iter_mut().find(|p| p.payout_id == payout_id)?;
+        payout.attempts += 1;
+        Some((payout.clone(), payout.token.or(base_ledger)))
+    });
+    
+    let outcome = match payout {
+        Some((payout, Some(ledger))) => {
+            token_transfer(ledger, payout.to, payout.amount, payout.memo, payout.created_at_time).await
+        },
+        Some((_, None)) => TransferOutcome::Rejected("Ledger canister not configured".to_string()),
+        None => return TransferOutcome::Rejected("Pending payout not found".to_string()),
+    };
+    
+    let (caller, now) = (msg_caller(), time());
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            match &outcome {
+                TransferOutcome::Completed(block_index) => finish_payout(state, payout_id, Some(*block_index), caller, now),
+                TransferOutcome::Rejected(_) => finish_payout(state, payout_id, None, caller, now),
+                TransferOutcome::Unknown(error) => {
+                    if let Some(payout) = state.pending_payouts.iter_mut().find(|p| p.payout_id == payout_id) {
+                        payout.last_error = Some(error.clone());
+                    }
+                },
+            }
+        }
+    });
+    
+    outcome
+}
+
+// Commits the booking when the transfer landed at `block_index`, undoes it when nothing moved
+fn finish_payout(state: &mut VaultState, payout_id: u64, block_index: Option<u64>, caller: Principal, now: u64) {
+    let Some(index) = state.pending_payouts.iter().position(|p| p.payout_id == payout_id) else {
+        return;
+    };
+    let payout = state.pending_payouts.remove(index);
+    
+    match (payout.kind, block_index) {
+        (PayoutKind::InvestmentRefund { investment }, Some(block_index)) => {
+            adjust_token_capital(state, investment.token, -(investment.token_amount as i128), -(investment.amount as i128));
+            record_event_at(caller, VaultEventKind::InvestmentCancelled {
+                investment_id: investment.investment_id,
+                backer: investment.backer,
+                amount: investment.amount,
+                block_index,
+            }, now);
+        },
+        (PayoutKind::InvestmentRefund { investment }, None) => {
+            state.pending_funding += investment.amount - investment.insurance_fee;
+            state.pending_investments.push(investment);
+        },
+        (PayoutKind::InsuranceClaim { claim_id, value }, Some(block_index)) => {
+            if let Some(claim) = state.insurance_claims.iter_mut().find(|c| c.claim_id == claim_id) {
+                claim.payout_block_index = Some(block_index);
+            }
+            record_event_at(caller, VaultEventKind::InsuranceClaim {
+                claim_id,
+                claimer: payout.to,
+                amount: value,
+                status: ClaimStatus::Paid,
+                block_index: Some(block_index),
+                notes: None,
+            }, now);
+        },
+        (PayoutKind::InsuranceClaim { claim_id, value }, None) => {
+            // Release the claim so the payout can be retried
+            if let Some(claim) = state.insurance_claims.iter_mut().find(|c| c.claim_id == claim_id) {
+                claim.status = ClaimStatus::Approved;
+                claim.paid_at = None;
+            }
+            adjust_token_capital(state, payout.token, payout.amount as i128, value as i128);
+        },
+        (PayoutKind::EscrowRefund { value }, Some(block_index)) => {
+            record_event_at(caller, VaultEventKind::Refund { backer: payout.to, amount: value, block_index }, now);
+        },
+        (PayoutKind::EscrowRefund { value }, None) => {
+            if let Some(backer) = state.backers.get_mut(&payout.to) {
+                backer.refunded_amount = 0;
+                backer.share_percentage = (backer.principal as f64 / state.funding_goal as f64) * 100.0;
+            }
+            state.total_refunded -= value;
+            adjust_token_capital(state, payout.token, payout.amount as i128, value as i128);
+        },
+        (PayoutKind::BondRelease, Some(block_index)) => {
+            if let Some(bond) = state.creator_bond.as_mut() {
+                bond.release_block_index = Some(block_index);
+            }
+            record_event_at(caller, VaultEventKind::BondReleased { amount: payout.amount, block_index: Some(block_index) }, now);
+        },
+        (PayoutKind::BondRelease, None) => {
+            if let Some(bond) = state.creator_bond.as_mut() {
+                bond.released_at = None;
+            }
+        },
+        (PayoutKind::CreatorShare, Some(block_index)) => {
+            let withdrawal_id = state.creator_withdrawals.len() as u64;
+            state.creator_withdrawals.push(CreatorWithdrawal {
+                withdrawal_id,
+                amount: payout.amount,
+                mode: CreatorPayoutMode::Vesting,
+                reference: block_index,
+                withdrawn_at: payout.created_at_time,
+            });
+            record_event_at(caller, VaultEventKind::CreatorWithdrawal {
+                amount: payout.amount,
+                mode: CreatorPayoutMode::Vesting,
+                reference: block_index,
+            }, now);
+        },
+        (PayoutKind::CreatorShare, None) => {
+            state.creator_withdrawn -= payout.amount;
+            revenue_fund(state, None).disbursed -= payout.amount;
+        },
+        (PayoutKind::TrancheRelease { milestone_id, part_index, value }, Some(block_index)) => {
+            if let Some(milestone) = state.milestones.iter_mut().find(|m| m.milestone_id == milestone_id) {
+                milestone.token_releases[part_index as usize].block_index = Some(block_index);
+                if milestone.token_releases.iter().all(|r| r.block_index.is_some()) {
+                    milestone.status = MilestoneStatus::Released;
+                    milestone.release_block_index = Some(block_index);
+                }
+            }
+            record_event_at(caller, VaultEventKind::CapitalReleased { milestone_id, amount: value, block_index }, now);
+        },
+        (PayoutKind::TrancheRelease { value, .. }, None) => {
+            state.capital_released -= value;
+            state.total_paid_out -= value;
+            adjust_token_capital(state, payout.token, payout.amount as i128, value as i128);
+        },
+        (PayoutKind::InsuranceLeftover, Some(block_index)) => {
+            if let Some(settlement) = state.settlement.as_mut() {
+                settlement.leftover_block_index = Some(block_index);
+            }
+        },
+        (PayoutKind::InsuranceLeftover, None) => state.insurance_pool_balance += payout.amount,
+    }
+}
+
+// Re-sends a payout left pending by an unknown outcome, with the same memo and created_at_time
+#[update]
+async fn retry_payout(payout_id: u64) -> Result<u64, String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let state_opt = state_ref.borrow();
+        if let Some(ref state) = *state_opt {
+            let payout = state.pending_payouts.iter().find(|p| p.payout_id == payout_id)
+                .ok_or_else(|| "Pending payout not found".to_string())?;
+            if payout.to != caller && !is_governance(state, caller) {
+                return Err("Only the payee or governance can retry a payout".to_string());
+            }
+            ensure_not_paused(state)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    settle_payout(payout_id).await
+}
+
+// Settles a payout the ledger can no longer deduplicate: commit it with the block that paid it, or undo it
+#[update]
+fn resolve_payout(payout_id: u64, block_index: Option<u64>) -> Result<(), String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::payout(payout_id)?;
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if !is_governance(state, caller) {
+                return Err("Only governance can resolve a payout".to_string());
+            }
+            if !state.pending_payouts.iter().any(|p| p.payout_id == payout_id) {
+                return Err("Pending payout not found".to_string());
+            }
+            finish_payout(state, payout_id, block_index, caller, time());
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[query]
+fn get_pending_payouts() -> Vec<PendingPayout> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.pending_payouts.clone()).unwrap_or_default()
+    })
+}
+
+// Inter-canister calls with the arguments and results as tuples, on ic-cdk's unbounded-wait Call API
+async fn call<A: ArgumentEncoder, R: for<'de> ArgumentDecoder<'de>>(
+    canister: Principal,
+    method: &str,
+    args: A,
+) -> CallResult<R> {
+    call_with_cycles(canister, method, args, 0).await
+}
+
+async fn call_with_cycles<A: ArgumentEncoder, R: for<'de> ArgumentDecoder<'de>>(
+    canister: Principal,
+    method: &str,
+    args: A,
+    cycles: u128,
+) -> CallResult<R> {
+    Ok(Call::unbounded_wait(canister, method).with_args(&args).with_cycles(cycles).await?.candid_tuple()?)
+}
+
+async fn token_transfer(ledger: Principal, to: Principal, amount: u64, memo: u64, created_at_time: u64) -> TransferOutcome {
+    let args = TransferArg {
+        from_subaccount: None,
+        to: Account { owner: to, subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(memo.to_be_bytes().to_vec()),
+        created_at_time: Some(created_at_time),
+    };
+    
+    let result: CallResult<(Result<Nat, TransferError>,)> = call(
+        ledger,
+        "icrc1_transfer",
+        (args,),
+    ).await;
+    
+    match result {
+        Ok((Ok(block_index),)) | Ok((Err(TransferError::Duplicate { duplicate_of: block_index }),)) => {
+            match u64::try_from(block_index.0) {
+                Ok(block_index) => TransferOutcome::Completed(block_index),
+                Err(_) => TransferOutcome::Unknown("Ledger block index does not fit in u64".to_string()),
+            }
+        },
+        // Past the ledger's deduplication window a retry can't tell whether the first attempt landed
+        Ok((Err(TransferError::TooOld),)) => TransferOutcome::Unknown(
+            "Payout is too old for the ledger to deduplicate, governance has to resolve it".to_string()
+        ),
+        Ok((Err(e),)) => TransferOutcome::Rejected(format!("Ledger transfer failed: {:?}", e)),
+        Err(e) => TransferOutcome::Unknown(format!("Failed to call ledger: {:?}", e)),
+    }
+}
+
+// Pulls `amount` from an account that approved the vault into the vault's default account
+async fn ledger_transfer_from(from: Principal, amount: u64, memo: u64) -> Result<u64, String> {
+    let ledger = get_ledger_canister().ok_or_else(|| "Ledger canister not configured".to_string())?;
+    token_transfer_from(ledger, from, amount, memo).await
+}
+
+async fn token_transfer_from(ledger: Principal, from: Principal, amount: u64, memo: u64) -> Result<u64, String> {
+    let args = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account { owner: from, subaccount: None },
+        to: Account { owner: canister_self(), subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(memo.to_be_bytes().to_vec()),
+        created_at_time: Some(time()),
+    };
+    
+    let result: CallResult<(Result<Nat, TransferFromError>,)> = call(
+        ledger,
+        "icrc2_transfer_from",
+        (args,),
+    ).await;
+    
+    match result {
+        Ok((Ok(block_index),)) => u64::try_from(block_index.0)
+            .map_err(|_| "Ledger block index does not fit in u64".to_string()),
+        Ok((Err(e),)) => Err(format!("Ledger transfer failed: {:?}", e)),
+        Err(e) => Err(format!("Failed to call ledger: {:?}", e)),
+    }
+}
+
+// Lets `spender` pull `amount` from the vault's default account, covering the ledger fee as well
+async fn ledger_approve(spender: Principal, amount: u64) -> Result<u64, String> {
+    let ledger = get_ledger_canister().ok_or_else(|| "Ledger canister not configured".to_string())?;
+    token_approve(ledger, spender, amount).await
+}
+
+async fn token_approve(ledger: Principal, spender: Principal, amount: u64) -> Result<u64, String> {
+    let fee: CallResult<(Nat,)> = call(ledger, "icrc1_fee", ()).await;
+    let fee = fee.map_err(|e| format!("Failed to query ledger fee: {:?}", e))?.0;
+    
+    let args = ApproveArgs {
+        from_subaccount: None,
+        spender: Account { owner: spender, subaccount: None },
+        amount: Nat::from(amount) + fee,
+        expected_allowance: None,
+        expires_at: None,
+        fee: None,
+        memo: None,
+        created_at_time: Some(time()),
+    };
+    
+    let result: CallResult<(Result<Nat, ApproveError>,)> = call(
+        ledger,
+        "icrc2_approve",
+        (args,),
+    ).await;
+    
+    match result {
+        Ok((Ok(block_index),)) => u64::try_from(block_index.0)
+            .map_err(|_| "Ledger block index does not fit in u64".to_string()),
+        Ok((Err(e),)) => Err(format!("Ledger approve failed: {:?}", e)),
+        Err(e) => Err(format!("Failed to call ledger: {:?}", e)),
+    }
+}
+
+// Accepted tokens and exchange rates
+
+#[update]
+fn set_accepted_tokens(xrc_canister: Principal, tokens: Vec<AcceptedToken>) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller && !is_governance(state, caller) {
+                return Err("Only the creator or governance can set accepted tokens".to_string());
+            }
+            
+            let base_ledger = state.ledger_canister
+                .ok_or_else(|| "Ledger canister not configured".to_string())?;
+            if !tokens.iter().any(|t| t.ledger == base_ledger) {
+                return Err("Accepted tokens must include the base ledger".to_string());
+            }
+            
+            // Capital already raised in a token has to stay refundable in it
+            for holding in &state.token_holdings {
+                if holding.capital > 0 && !tokens.iter().any(|t| t.ledger == holding.ledger) {
+                    return Err(format!("Vault still holds capital in {}", holding.ledger.to_text()));
+                }
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "accepted_tokens".to_string(),
+                value: tokens.iter().map(|t| t.symbol.clone()).collect::<Vec<_>>().join(","),
+            });
+            
+            state.token_rates.retain(|r| tokens.iter().any(|t| t.ledger == r.ledger));
+            state.xrc_canister = Some(xrc_canister);
+            state.accepted_tokens = tokens;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Fetches a fresh rate for `ledger` from the exchange rate canister, which costs the vault cycles
+#[update]
+async fn refresh_exchange_rate(ledger: Principal) -> Result<TokenRate, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::operation(GuardedOperation::RateRefresh)?;
+    
+    VAULT_STATE.with(|state_ref| {
+        let state_opt = state_ref.borrow();
+        if let Some(ref state) = *state_opt {
+            if state.creator != caller && state.oracle_canister != Some(caller) && !is_governance(state, caller) {
+                return Err("Only the creator, the oracle or governance can refresh exchange rates".to_string());
+            }
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    fetch_exchange_rate(ledger).await
+}
+
+fn base_token(state: &VaultState) -> Result<&AcceptedToken, String> {
+    let base_ledger = state.ledger_canister.ok_or_else(|| "Ledger canister not configured".to_string())?;
+    state.accepted_tokens.iter()
+        .find(|t| t.ledger == base_ledger)
+        .ok_or_else(|| "Vault does not accept other tokens".to_string())
+}
+
+// Looks up an accepted token other than the base ledger
+fn accepted_token(state: &VaultState, ledger: Principal) -> Result<&AcceptedToken, String> {
+    if state.ledger_canister == Some(ledger) {
+        return Err("The base ledger needs no exchange rate".to_string());
+    }
+    state.accepted_tokens.iter()
+        .find(|t| t.ledger == ledger)
+        .ok_or_else(|| format!("Token {} is not accepted by this vault", ledger.to_text()))
+}
+
+// Values `amount` token units in base units: amount * rate * 10^base_decimals / 10^(rate_decimals + token_decimals)
+fn to_base_value(state: &VaultState, ledger: Principal, amount: u64, rate: &TokenRate) -> Result<u64, String> {
+    let token = accepted_token(state, ledger)?;
+    let base = base_token(state)?;
+    
+    let scaled = amount as u128 * rate.rate as u128;
+    let exponent = base.decimals as i64 - rate.rate_decimals as i64 - token.decimals as i64;
+    let value = if exponent >= 0 {
+        10u128.checked_pow(exponent as u32).and_then(|factor| scaled.checked_mul(factor))
+    } else {
+        10u128.checked_pow((-exponent) as u32).map(|factor| scaled / factor)
+    };
+    
+    value.and_then(|v| u64::try_from(v).ok())
+        .ok_or_else(|| "Token value does not fit in u64".to_string())
+}
+
+fn cached_rate(state: &VaultState, ledger: Principal, max_age_secs: u64, now: u64) -> Option<TokenRate> {
+    state.token_rates.iter()
+        .find(|r| r.ledger == ledger && now.saturating_sub(r.fetched_at) <= max_age_secs * 1_000_000_000)
+        .cloned()
+}
+
+async fn current_rate(ledger: Principal, max_age_secs: u64) -> Result<TokenRate, String> {
+    let cached = VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| cached_rate(s, ledger, max_age_secs, time()))
+    });
+    
+    match cached {
+        Some(rate) => Ok(rate),
+        None => fetch_exchange_rate(ledger).await,
+    }
+}
+
+async fn fetch_exchange_rate(ledger: Principal) -> Result<TokenRate, String> {
+    let (xrc_canister, request) = VAULT_STATE.with(|state_ref| {
+        let state_opt = state_ref.borrow();
+        if let Some(ref state) = *state_opt {
+            let xrc_canister = state.xrc_canister
+                .ok_or_else(|| "Exchange rate canister not configured".to_string())?;
+            let token = accepted_token(state, ledger)?;
+            let base = base_token(state)?;
+            
+            Ok((xrc_canister, GetExchangeRateRequest {
+                base_asset: Asset { symbol: token.symbol.clone(), class: AssetClass::Cryptocurrency },
+                quote_asset: Asset { symbol: base.symbol.clone(), class: AssetClass::Cryptocurrency },
+                timestamp: None,
+            }))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    let result: CallResult<(Result<ExchangeRate, ExchangeRateError>,)> = call_with_cycles(
+        xrc_canister,
+        "get_exchange_rate",
+        (request,),
+        XRC_CALL_CYCLES,
+    ).await;
+    
+    let rate = match result {
+        Ok((Ok(rate),)) => rate,
+        Ok((Err(e),)) => return Err(format!("Exchange rate unavailable: {:?}", e)),
+        Err(e) => return Err(format!("Failed to call exchange rate canister: {:?}", e)),
+    };
+    
+    if rate.rate == 0 {
+        return Err("Exchange rate canister returned a zero rate".to_string());
+    }
+    
+    let token_rate = TokenRate {
+        ledger,
+        rate: rate.rate,
+        rate_decimals: rate.metadata.decimals,
+        fetched_at: time(),
+    };
+    
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            state.token_rates.retain(|r| r.ledger != ledger);
+            state.token_rates.push(token_rate.clone());
+        }
+    });
+    record_event(canister_self(), VaultEventKind::ExchangeRateUpdated {
+        ledger,
+        rate: token_rate.rate,
+        rate_decimals: token_rate.rate_decimals,
+    });
+    
+    Ok(token_rate)
+}
+
+// Moves capital held in a non-base token; the base ledger is covered by the regular books
+fn adjust_token_capital(state: &mut VaultState, token: Option<Principal>, amount: i128, value: i128) {
+    let ledger = match token {
+        Some(ledger) => ledger,
+        None => return,
+    };
+    
+    let holding = token_holding(state, ledger);
+    holding.capital = (holding.capital as i128 + amount).max(0) as u64;
+    holding.capital_value = (holding.capital_value as i128 + value).max(0) as u64;
+}
+
+fn token_holding(state: &mut VaultState, ledger: Principal) -> &mut TokenHolding {
+    let index = match state.token_holdings.iter().position(|h| h.ledger == ledger) {
+        Some(index) => index,
+        None => {
+            state.token_holdings.push(TokenHolding { ledger, capital: 0, capital_value: 0, insurance_fee_value: 0 });
+            state.token_holdings.len() - 1
+        }
+    };
+    &mut state.token_holdings[index]
+}
+
+// Converts a base amount owed to a backer into their position's token, at the rate they invested at
+fn position_amount(info: &BackerInfo, base_amount: u64) -> (Option<Principal>, u64) {
+    match info.token {
+        Some(ledger) if info.amount_invested > 0 => (
+            Some(ledger),
+            (base_amount as u128 * info.token_amount as u128 / info.amount_invested as u128) as u64,
+        ),
+        _ => (None, base_amount),
+    }
+}
+
+#[query]
+fn get_accepted_tokens() -> Vec<AcceptedToken> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map_or_else(Vec::new, |s| s.accepted_tokens.clone())
+    })
+}
+
+#[query]
+fn get_token_rates() -> Vec<TokenRate> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map_or_else(Vec::new, |s| s.token_rates.clone())
+    })
+}
+
+#[query]
+fn get_token_holdings() -> Vec<TokenHolding> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map_or_else(Vec::new, |s| s.token_holdings.clone())
+    })
+}
+
+// Vault event log
+
+fn record_event(caller: Principal, kind: VaultEventKind) -> u64 {
+    record_event_at(caller, kind, time())
+}
+
+fn record_event_at(caller: Principal, kind: VaultEventKind, timestamp: u64) -> u64 {
+    EVENT_LOG.with(|log_ref| {
+        let mut log = log_ref.borrow_mut();
+        // Events are never removed, so the last id is always the highest
+        let event_id = log.last_key_value().map_or(0, |(id, _)| id + 1);
+        
+        log.insert(event_id, VaultEvent {
+            event_id,
+            timestamp,
+            caller,
+            kind,
+        });
+        
+        event_id
+    })
+}
+
+fn event_matches(event: &VaultEvent, filter: &VaultEventFilter) -> bool {
+    if let Some(ref category) = filter.category {
+        if event.kind.category() != *category {
+            return false;
+        }
+    }
+    
+    if let Some(principal) = filter.principal {
+        if event.caller != principal && !event.kind.involves(principal) {
+            return false;
+        }
+    }
+    
+    filter.from_timestamp.is_none_or(|from| event.timestamp >= from)
+        && filter.to_timestamp.is_none_or(|to| event.timestamp < to)
+}
+
+#[query]
+fn get_events(start_event_id: u64, limit: u64, filter: Option<VaultEventFilter>) -> VaultEventPage {
+    let limit = limit.clamp(1, MAX_EVENT_PAGE_SIZE) as usize;
+    
+    EVENT_LOG.with(|log_ref| {
+        let log = log_ref.borrow();
+        let mut events = Vec::new();
+        let mut next_event_id = None;
+        
+        for (scanned, (event_id, event)) in log.range(start_event_id..).enumerate() {
+            if events.len() == limit || scanned as u64 == MAX_EVENT_SCAN {
+                next_event_id = Some(event_id);
+                break;
+            }
+            
+            if filter.as_ref().is_none_or(|f| event_matches(&event, f)) {
+                events.push(event);
+            }
+        }
+        
+        VaultEventPage {
+            events,
+            next_event_id,
+            total_events: log.len(),
+        }
+    })
+}
+
+#[query]
+fn get_event(event_id: u64) -> Option<VaultEvent> {
+    EVENT_LOG.with(|log_ref| log_ref.borrow().get(&event_id))
+}
+
+// Backer income statements
+
+fn build_backer_statement(backer: Principal, from: u64, to: u64, now: u64) -> BackerStatement {
+    let mut statement = BackerStatement {
+        backer,
+        from,
+        to,
+        generated_at: now,
+        invested: 0,
+        insurance_fees: 0,
+        entitlements_accrued: 0,
+        stream_payouts: 0,
+        insurance_payouts: 0,
+        refunds: 0,
+        lines: Vec::new(),
+    };
+    
+    EVENT_LOG.with(|log_ref| {
+        // Event ids and timestamps grow together, so the scan stops at the end of the range
+        for (_, event) in log_ref.borrow().iter() {
+            if event.timestamp >= to {
+                break;
+            }
+            if event.timestamp < from {
+                continue;
+            }
+            
+            let line = match &event.kind {
+                VaultEventKind::Investment { backer: b, amount, insurance_fee, .. } if *b == backer => {
+                    statement.invested += amount;
+                    statement.insurance_fees += insurance_fee;
+                    Some((StatementLineType::Investment, *amount, None))
+                },
+                VaultEventKind::EntitlementsCredited { credits, .. } => {
+                    let credited: u64 = credits.iter().filter(|(b, _)| *b == backer).map(|(_, c)| c).sum();
+                    statement.entitlements_accrued += credited;
+                    (credited > 0).then_some((StatementLineType::EntitlementAccrued, credited, None))
+                },
+                VaultEventKind::StreamPayout { recipient, amount, block_index } if *recipient == backer => {
+                    statement.stream_payouts += amount;
+                    Some((StatementLineType::StreamPayout, *amount, Some(*block_index)))
+                },
+                VaultEventKind::InsuranceClaim { claimer, amount, status: ClaimStatus::Paid, block_index, .. }
+                    if *claimer == backer =>
+                {
+                    statement.insurance_payouts += amount;
+                    Some((StatementLineType::InsurancePayout, *amount, *block_index))
+                },
+                VaultEventKind::Refund { backer: b, amount, block_index } if *b == backer => {
+                    statement.refunds += amount;
+                    Some((StatementLineType::Refund, *amount, Some(*block_index)))
+                },
+                _ => None,
+            };
+            
+            if let Some((line_type, amount, ledger_block_index)) = line {
+                statement.lines.push(StatementLine {
+                    event_id: event.event_id,
+                    timestamp: event.timestamp,
+                    line_type,
+                    amount,
+                    ledger_block_index,
+                });
+            }
+        }
+    });
+    
+    statement
+}
+
+#[query]
+fn get_backer_statement(backer: Principal, from: u64, to: u64) -> Result<BackerStatement, String> {
+    if from >= to {
+        return Err("Statement range start must be before its end".to_string());
+    }
+    
+    Ok(build_backer_statement(backer, from, to, time()))
+}
+
+#[query]
+fn get_backer_statement_csv(backer: Principal, from: u64, to: u64) -> Result<String, String> {
+    get_backer_statement(backer, from, to).map(|statement| statement_csv(&statement))
+}
+
+fn statement_csv(statement: &BackerStatement) -> String {
+    let mut csv = String::from("event_id,timestamp,type,amount,ledger_block_index\n");
+    for line in &statement.lines {
+        csv.push_str(&format!(
+            "{},{},{:?},{},{}\n",
+            line.event_id,
+            line.timestamp,
+            line.line_type,
+            line.amount,
+            line.ledger_block_index.map(|i| i.to_string()).unwrap_or_default()
+        ));
+    }
+    
+    // Totals follow the detail rows with empty id and timestamp columns
+    let totals = [
+        ("TotalInvested", statement.invested),
+        ("TotalInsuranceFees", statement.insurance_fees),
+        ("TotalEntitlementsAccrued", statement.entitlements_accrued),
+        ("TotalStreamPayouts", statement.stream_payouts),
+        ("TotalInsurancePayouts", statement.insurance_payouts),
+        ("TotalRefunds", statement.refunds),
+    ];
+    for (label, amount) in totals {
+        csv.push_str(&format!(",,{},{},\n", label, amount));
+    }
+    
+    csv
+}
+
+#[query]
+fn get_vault_state() -> Option<VaultState> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().clone()
+    })
+}
+
+#[query]
+fn get_backer_info(backer: Principal) -> Option<BackerInfo> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.backers.get(&backer).cloned())
+    })
+}
+
+#[query]
+fn get_funding_progress() -> (u64, u64, f64) {
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref state) = *state_ref.borrow() {
+            let percentage = (state.current_funding as f64 / state.funding_goal as f64) * 100.0;
+            (state.current_funding, state.funding_goal, percentage)
+        } else {
+            (0, 0, 0.0)
+        }
+    })
+}
+
+// Insurance-related functions
+
+#[query]
+fn get_insurance_pool_info() -> (u64, u8, u8) {
+    VAULT_STATE.with(|state_ref| {
+        if let Some(ref state) = *state_ref.borrow() {
+            (
+                state.insurance_pool_balance,
+                state.insurance_fee_percentage,
+                state.insurance_coverage_ratio
+            )
+        } else {
+            (0, 0, 0)
+        }
+    })
+}
+
+#[update]
+fn record_insurance_trigger(trigger: InsuranceTrigger) -> Result<u64, String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if !is_governance(state, caller) {
+                return Err("Only governance can record insurance triggers".to_string());
+            }
+            
+            // Slash triggers are recorded by the vault itself when a slash executes
+            if matches!(trigger, InsuranceTrigger::SlashExecuted { .. }) {
+                return Err("Slash triggers are recorded automatically".to_string());
+            }
+            
+            Ok(push_insurance_trigger(state, trigger, caller, time()))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn push_insurance_trigger(state: &mut VaultState, trigger: InsuranceTrigger, recorded_by: Principal, now: u64) -> u64 {
+    let trigger_id = state.insurance_triggers.len() as u64;
+    
+    ic_cdk::println!("Insurance trigger {} recorded: {:?}", trigger_id, trigger);
+    record_event_at(recorded_by, VaultEventKind::InsuranceTriggered { trigger_id, trigger: trigger.clone() }, now);
+    
+    state.insurance_triggers.push(InsuranceTriggerEvent {
+        trigger_id,
+        trigger,
+        recorded_at: now,
+        recorded_by,
+    });
+    
+    trigger_id
+}
+
+// The pool is the fees actually received. They stay in the token they were paid in, so a claim is
+// paid from the fees received in the claimer's position token.
+fn insurance_pool_for(state: &VaultState, token: Option<Principal>) -> u64 {
+    match token {
+        Some(ledger) => state.token_holdings.iter()
+            .find(|h| h.ledger == ledger)
+            .map_or(0, |h| h.insurance_fee_value),
+        None => {
+            let held_in_tokens: u64 = state.token_holdings.iter().map(|h| h.insurance_fee_value).sum();
+            state.insurance_pool_balance.saturating_sub(held_in_tokens)
+        }
+    }
+}
+
+#[update]
+fn file_insurance_claim(trigger_id: u64, amount: u64, reason: String, evidence: Vec<String>) -> Result<u64, String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            file_claim(state, trigger_id, amount, reason, evidence, caller, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn file_claim(
+    state: &mut VaultState,
+    trigger_id: u64,
+    amount: u64,
+    reason: String,
+    evidence: Vec<String>,
+    caller: Principal,
+    now: u64,
+) -> Result<u64, String> {
+    if state.vault_status != VaultStatus::Active {
+        return Err("Vault is closed".to_string());
+    }
+    // Once the vault holds a pool policy, claims are filed with the insurance pool
+    if state.insurance_policy.is_some() {
+        return Err("Vault is insured by the insurance pool, file claims there".to_string());
+    }
+    
+    // Claims are only accepted against a recorded eligibility event
+    if !state.insurance_triggers.iter().any(|t| t.trigger_id == trigger_id) {
+        return Err(format!("Insurance trigger {} not found", trigger_id));
+    }
+    
+    // Check if caller is a backer
+    let backer_info = state.backers.get(&caller)
+        .ok_or_else(|| "Only backers can file insurance claims".to_string())?;
+    
+    // Calculate max claimable amount (coverage ratio * investment)
+    let max_claimable = (backer_info.amount_invested * state.insurance_coverage_ratio as u64) / 100;
+    
+    // Coverage is shared across all of a backer's claims that are still open or paid
+    let already_claimed: u64 = state.insurance_claims.iter()
+        .filter(|c| c.claimer == caller && !matches!(c.status, ClaimStatus::Rejected))
+        .map(|c| c.amount)
+        .sum();
+    
+    if amount + already_claimed > max_claimable {
+        return Err(format!("Claim exceeds maximum coverage of {}", max_claimable));
+    }
+    
+    if state.insurance_claims.iter().any(|c| c.claimer == caller && c.trigger_id == trigger_id) {
+        return Err(format!("A claim for trigger {} has already been filed", trigger_id));
+    }
+    
+    // Check if there's enough in the insurance pool
+    if amount > insurance_pool_for(state, backer_info.token) {
+        return Err("Insufficient funds in insurance pool".to_string());
+    }
+    
+    // Create claim
+    let claim_id = state.insurance_claims.len() as u64;
+    let claim = InsuranceClaim {
+        claim_id,
+        trigger_id,
+        claimer: caller,
+        amount,
+        reason,
+        evidence,
+        status: ClaimStatus::Pending,
+        filed_at: now,
+        resolved_at: None,
+        approver: None,
+        appeal_deadline: None,
+        appeal_reason: None,
+        resolution_notes: None,
+        paid_at: None,
+        payout_block_index: None,
+    };
+    
+    state.insurance_claims.push(claim);
+    
+    record_event_at(caller, VaultEventKind::InsuranceClaim {
+        claim_id,
+        claimer: caller,
+        amount,
+        status: ClaimStatus::Pending,
+        block_index: None,
+        notes: None,
+    }, now);
+    
+    Ok(claim_id)
+}
+
+#[update]
+fn process_insurance_claim(claim_id: u64, approve: bool, notes: String) -> Result<(), String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            adjudicate_insurance_claim(state, claim_id, approve, notes, caller, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn adjudicate_insurance_claim(
+    state: &mut VaultState,
+    claim_id: u64,
+    approve: bool,
+    notes: String,
+    caller: Principal,
+    now: u64,
+) -> Result<(), String> {
+    let governance = is_governance(state, caller);
+    
+    // Check if caller is creator or has governance rights
+    if state.creator != caller && !governance {
+        return Err("Only creator or governance can process claims".to_string());
+    }
+    
+    let claim_token = state.insurance_claims.iter()
+        .find(|c| c.claim_id == claim_id)
+        .and_then(|c| state.backers.get(&c.claimer))
+        .and_then(|info| info.token);
+    let pool_available = insurance_pool_for(state, claim_token);
+    
+    // Find the claim
+    let claim = state.insurance_claims.iter_mut().find(|c| c.claim_id == claim_id)
+        .ok_or_else(|| format!("Claim with ID {} not found", claim_id))?;
+    
+    let appealed = match claim.status {
+        ClaimStatus::Pending => false,
+        ClaimStatus::Appealed => true,
+        _ => return Err(format!("Claim is not pending. Current status: {:?}", claim.status)),
+    };
+    
+    // Appeals are decided by governance, not by the creator who may have rejected the claim
+    if appealed && !governance {
+        return Err("Only governance can decide appealed claims".to_string());
+    }
+    
+    if approve {
+        // Make sure we have enough in the pool
+        if claim.amount > pool_available {
+            return Err("Insufficient funds in insurance pool".to_string());
+        }
+        
+        // Update claim status
+        claim.status = ClaimStatus::Approved;
+        claim.resolved_at = Some(now);
+        claim.approver = Some(caller);
+        claim.appeal_deadline = None;
+        
+        // Reserve the payout in the insurance pool until it is paid
+        state.insurance_pool_balance -= claim.amount;
+        if let Some(holding) = state.token_holdings.iter_mut().find(|h| Some(h.ledger) == claim_token) {
+            holding.insurance_fee_value -= claim.amount;
+        }
+    } else {
+        // Reject the claim, a first rejection can be appealed
+        claim.status = ClaimStatus::Rejected;
+        claim.resolved_at = Some(now);
+        claim.approver = Some(caller);
+        claim.appeal_deadline = if appealed {
+            None
+        } else {
+            Some(now + CLAIM_APPEAL_WINDOW_DAYS * NANOS_PER_DAY)
+        };
+    }
+    
+    // Kept on the claim and in the log, an appeal is argued against it
+    claim.resolution_notes = Some(notes.clone());
+    record_event_at(caller, VaultEventKind::InsuranceClaim {
+        claim_id,
+        claimer: claim.claimer,
+        amount: claim.amount,
+        status: claim.status.clone(),
+        block_index: None,
+        notes: Some(notes),
+    }, now);
+    
+    Ok(())
+}
+
+#[update]
+fn appeal_insurance_claim(claim_id: u64, reason: String, evidence: Vec<String>) -> Result<(), String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            appeal_claim(state, claim_id, reason, evidence, caller, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn appeal_claim(
+    state: &mut VaultState,
+    claim_id: u64,
+    reason: String,
+    evidence: Vec<String>,
+    caller: Principal,
+    now: u64,
+) -> Result<(), String> {
+    let claim = state.insurance_claims.iter_mut().find(|c| c.claim_id == claim_id)
+        .ok_or_else(|| format!("Claim with ID {} not found", claim_id))?;
+    
+    if claim.claimer != caller {
+        return Err("Only the claimer can appeal a claim".to_string());
+    }
+    
+    if !matches!(claim.status, ClaimStatus::Rejected) {
+        return Err(format!("Only rejected claims can be appealed. Current status: {:?}", claim.status));
+    }
+    
+    match claim.appeal_deadline {
+        Some(deadline) if now <= deadline => {
+            claim.status = ClaimStatus::Appealed;
+            claim.appeal_reason = Some(reason);
+            claim.appeal_deadline = None;
+            claim.evidence.extend(evidence);
+            record_event_at(caller, VaultEventKind::InsuranceClaim {
+                claim_id,
+                claimer: caller,
+                amount: claim.amount,
+                status: ClaimStatus::Appealed,
+                block_index: None,
+                notes: claim.appeal_reason.clone(),
+            }, now);
+            Ok(())
+        },
+        Some(_) => Err("Appeal window has closed".to_string()),
+        None => Err("Claim is not eligible for appeal".to_string()),
+    }
+}
+
+#[update]
+async fn pay_insurance_claim(claim_id: u64) -> Result<u64, String> {
+    let caller = msg_caller();
+    let now = time();
+    let _guard = CallGuard::operation(GuardedOperation::InsurancePayout)?;
+    
+    let payout_id = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            book_claim_payout(state, claim_id, caller, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    settle_payout(payout_id).await
+}
+
+// Marks the claim as paid before the transfer so that a concurrent call cannot pay it twice
+fn book_claim_payout(state: &mut VaultState, claim_id: u64, caller: Principal, now: u64) -> Result<u64, String> {
+    let governance = is_governance(state, caller);
+    ensure_not_paused(state)?;
+    if state.insurance_policy.is_some() {
+        return Err("Vault is insured by the insurance pool, claims are paid there".to_string());
+    }
+    
+    let claim = state.insurance_claims.iter_mut().find(|c| c.claim_id == claim_id)
+        .ok_or_else(|| format!("Claim with ID {} not found", claim_id))?;
+    
+    if claim.claimer != caller && !governance {
+        return Err("Only the claimer or governance can trigger a claim payout".to_string());
+    }
+    
+    if !matches!(claim.status, ClaimStatus::Approved) {
+        return Err(format!("Claim is not approved. Current status: {:?}", claim.status));
+    }
+    
+    claim.status = ClaimStatus::Paid;
+    claim.paid_at = Some(now);
+    let (claimer, amount) = (claim.claimer, claim.amount);
+    
+    // Paid in the claimer's position token, which is where their premium went
+    let (token, token_amount) = state.backers.get(&claimer)
+        .map_or((None, amount), |info| position_amount(info, amount));
+    adjust_token_capital(state, token, -(token_amount as i128), -(amount as i128));
+    let kind = PayoutKind::InsuranceClaim { claim_id, value: amount };
+    Ok(book_payout(state, kind, token, claimer, token_amount, claim_id, now))
+}
+
+#[query]
+fn get_insurance_triggers() -> Vec<InsuranceTriggerEvent> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.insurance_triggers.clone()).unwrap_or_default()
+    })
+}
+
+// Shared insurance pool cover
+
+#[update]
+fn set_insurance_canister(insurance_canister: Principal) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if caller != state.deployer && !is_governance(state, caller) {
+                return Err("Only the deployer or governance can set the insurance canister".to_string());
+            }
+            if state.insurance_policy.is_some() {
+                return Err("Vault is already insured".to_string());
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "insurance_canister".to_string(),
+                value: insurance_canister.to_text(),
+            });
+            
+            state.insurance_canister = Some(insurance_canister);
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Buys cover from the configured pool; later investments pay its risk-based premium instead of the local fee
+#[update]
+async fn apply_for_insurance(requested_coverage: u64) -> Result<u64, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::operation(GuardedOperation::InsuranceApplication)?;
+    
+    let (insurance_canister, profile) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can insure the vault".to_string());
+            }
+            if state.vault_status != VaultStatus::Active {
+                return Err("Vault is closed".to_string());
+            }
+            if state.insurance_policy.is_some() {
+                return Err("Vault is already insured".to_string());
+            }
+            // Local claims have to be settled first, the pool takes over claims once the policy is open
+            let open_claims = state.insurance_claims.iter()
+                .any(|c| matches!(c.status, ClaimStatus::Pending | ClaimStatus::Approved | ClaimStatus::Appealed));
+            let pending_claim_payouts = state.pending_payouts.iter()
+                .any(|p| matches!(p.kind, PayoutKind::InsuranceClaim { .. }));
+            if open_claims || pending_claim_payouts {
+                return Err("Settle the vault's open insurance claims before insuring with the pool".to_string());
+            }
+            let insurance_canister = state.insurance_canister
+                .ok_or_else(|| "Insurance canister not configured".to_string())?;
+            
+            Ok((insurance_canister, RiskProfile {
+                creator: state.creator,
+                campaign_id: state.campaign_id,
+                funding_goal: state.funding_goal,
+                verified_revenue_reports: state.revenue_history.len() as u64,
+                verified_revenue_total: state.total_revenue,
+            }))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    let result: CallResult<(Result<InsurancePolicy, String>,)> = call(
+        insurance_canister,
+        "open_policy",
+        (profile, requested_coverage),
+    ).await;
+    
+    let policy = match result {
+        Ok((Ok(policy),)) => policy,
+        Ok((Err(e),)) => return Err(e),
+        Err(e) => return Err(format!("Failed to call insurance pool: {:?}", e)),
+    };
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            record_event(caller, VaultEventKind::InsurancePolicyOpened {
+                insurance_canister,
+                policy_id: policy.policy_id,
+                premium_bps: policy.quote.premium_bps,
+                coverage_limit: policy.coverage_limit,
+            });
+            
+            let policy_id = policy.policy_id;
+            state.insurance_policy = Some(policy);
+            Ok(policy_id)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Forwards collected premiums to the insurance pool, which pulls them through an ICRC-2 allowance
+#[update]
+async fn remit_premiums() -> Result<u64, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::operation(GuardedOperation::PremiumRemittance)?;
+    
+    let (insurance_canister, policy_id, amount) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            ensure_not_paused(state)?;
+            
+            let (insurance_canister, policy_id) = match (state.insurance_canister, state.insurance_policy.as_ref()) {
+                (Some(canister), Some(policy)) => (canister, policy.policy_id),
+                _ => return Err("Vault has no insurance policy".to_string()),
+            };
+            if state.pending_premiums == 0 {
+                return Err("No premiums to remit".to_string());
+            }
+            
+            // Taken out of the books before the await, restored if the remittance fails
+            let amount = state.pending_premiums;
+            state.pending_premiums = 0;
+            Ok((insurance_canister, policy_id, amount))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    let outcome = match ledger_approve(insurance_canister, amount).await {
+        Ok(_) => {
+            let result: CallResult<(Result<u64, String>,)> = call(
+                insurance_canister,
+                "pay_premium",
+                (policy_id, amount),
+            ).await;
+            
+            match result {
+                Ok((Ok(block_index),)) => Ok(block_index),
+                Ok((Err(e),)) => Err(e),
+                Err(e) => Err(format!("Failed to call insurance pool: {:?}", e)),
+            }
+        }
+        Err(e) => Err(e),
+    };
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            match outcome {
+                Ok(block_index) => {
+                    state.premiums_remitted += amount;
+                    record_event(caller, VaultEventKind::PremiumsRemitted { policy_id, amount, block_index });
+                    Ok(block_index)
+                }
+                Err(e) => {
+                    state.pending_premiums += amount;
+                    Err(e)
+                }
+            }
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[query]
+fn get_insurance_policy() -> Option<(Principal, InsurancePolicy, u64, u64)> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| {
+            match (s.insurance_canister, s.insurance_policy.clone()) {
+                (Some(canister), Some(policy)) => Some((canister, policy, s.pending_premiums, s.premiums_remitted)),
+                _ => None,
+            }
+        })
+    })
+}
+
+// Slashing-related functions
+
+#[update]
+fn propose_slashing(
+    creator: Principal,
+    reason: SlashReason,
+    evidence: Vec<String>,
+    slash_percentage: u8,
+) -> Result<u64, String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            // Basic validation
+            if creator != state.creator {
+                return Err("Target is not the creator of this campaign".to_string());
+            }
+            
+            if state.vault_status != VaultStatus::Active {
+                return Err("Vault is closed".to_string());
+            }
+            
+            if !state.backers.contains_key(&caller) && !is_governance(state, caller) {
+                return Err("Only backers or governance can propose slashing".to_string());
+            }
+            
+            if evidence.is_empty() {
+                return Err("Slashing proposals require evidence".to_string());
+            }
+            
+            if slash_percentage == 0 || slash_percentage > 100 {
+                return Err("Slash percentage must be between 1-100%".to_string());
+            }
+            
+            Ok(open_slash_proposal(state, reason, evidence, slash_percentage, caller, now))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn open_slash_proposal(
+    state: &mut VaultState,
+    reason: SlashReason,
+    evidence: Vec<String>,
+    slash_percentage: u8,
+    proposed_by: Principal,
+    now: u64,
+) -> u64 {
+    let proposal_id = state.slash_proposals.len() as u64;
+    let challenge_deadline = now + SLASH_CHALLENGE_PERIOD_DAYS * NANOS_PER_DAY;
+    
+    record_event_at(proposed_by, VaultEventKind::SlashProposed {
+        proposal_id,
+        reason: reason.clone(),
+        slash_percentage,
+    }, now);
+    
+    push_creator_notice(
+        state,
+        format!(
+            "Slashing proposal {} opened ({:?}, {}% of creator share). Respond before {}",
+            proposal_id, reason, slash_percentage, challenge_deadline
+        ),
+        Some(proposal_id),
+        now,
+    );
+    
+    state.slash_proposals.push(SlashProposal {
+        proposal_id,
+        creator: state.creator,
+        reason,
+        evidence,
+        slash_percentage,
+        proposed_by,
+        proposed_at: now,
+        challenge_deadline,
+        creator_response: None,
+        creator_evidence: Vec::new(),
+        votes_for: 0.0,
+        votes_against: 0.0,
+        voters: Vec::new(),
+        supporters: Vec::new(),
+        votes_required: state.slashing_conditions.governance_votes_required,
+        dao_decision: None,
+        status: SlashProposalStatus::Open,
+        resolved_at: None,
+        slash_id: None,
+    });
+    
+    proposal_id
+}
+
+fn push_creator_notice(state: &mut VaultState, message: String, slash_proposal_id: Option<u64>, now: u64) {
+    let notice_id = state.creator_notices.len() as u64;
+    
+    ic_cdk::println!("Notice {} for creator {}: {}", notice_id, state.creator.to_text(), message);
+    
+    state.creator_notices.push(CreatorNotice {
+        notice_id,
+        message,
+        slash_proposal_id,
+        created_at: now,
+    });
+}
+
+#[update]
+fn respond_to_slashing(proposal_id: u64, response: String, evidence: Vec<String>) -> Result<(), String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can respond to a slashing proposal".to_string());
+            }
+            
+            if let Some(proposal) = state.slash_proposals.iter_mut().find(|p| p.proposal_id == proposal_id) {
+                if proposal.status != SlashProposalStatus::Open || now > proposal.challenge_deadline {
+                    return Err("Challenge period has ended".to_string());
+                }
+                
+                proposal.creator_response = Some(response);
+                proposal.creator_evidence.extend(evidence);
+                Ok(())
+            } else {
+                Err(format!("Slashing proposal {} not found", proposal_id))
+            }
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[update]
+fn vote_on_slashing(proposal_id: u64, support: bool) -> Result<(), String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            cast_slash_vote(state, proposal_id, support, caller, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Votes are weighted by the backer's share of the campaign
+fn cast_slash_vote(state: &mut VaultState, proposal_id: u64, support: bool, caller: Principal, now: u64) -> Result<(), String> {
+    if caller == state.creator {
+        return Err("The creator cannot vote on their own slashing".to_string());
+    }
+    
+    let weight = match state.backers.get(&caller) {
+        Some(info) => info.share_percentage,
+        None => return Err("Only backers can vote on slashing".to_string()),
+    };
+    
+    let proposal = state.slash_proposals.iter_mut().find(|p| p.proposal_id == proposal_id)
+        .ok_or_else(|| format!("Slashing proposal {} not found", proposal_id))?;
+    
+    if proposal.status != SlashProposalStatus::Open || now > proposal.challenge_deadline {
+        return Err("Voting period has ended".to_string());
+    }
+    
+    if proposal.voters.contains(&caller) {
+        return Err("Already voted".to_string());
+    }
+    
+    if support {
+        proposal.votes_for += weight;
+        proposal.supporters.push(caller);
+    } else {
+        proposal.votes_against += weight;
+    }
+    proposal.voters.push(caller);
+    
+    Ok(())
+}
+
+#[update]
+fn resolve_slashing_by_dao(proposal_id: u64, approve: bool) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            record_dao_decision(state, proposal_id, approve, caller)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn record_dao_decision(state: &mut VaultState, proposal_id: u64, approve: bool, caller: Principal) -> Result<(), String> {
+    if !is_governance(state, caller) {
+        return Err("Only governance can resolve slashing proposals".to_string());
+    }
+    
+    let proposal = state.slash_proposals.iter_mut().find(|p| p.proposal_id == proposal_id)
+        .ok_or_else(|| format!("Slashing proposal {} not found", proposal_id))?;
+    
+    if proposal.status != SlashProposalStatus::Open {
+        return Err(format!("Slashing proposal is not open. Current status: {:?}", proposal.status));
+    }
+    
+    proposal.dao_decision = Some(approve);
+    Ok(())
+}
+
+// Tallies a proposal once its challenge period is over and executes it if approved
+#[update]
+fn finalize_slashing_proposal(proposal_id: u64) -> Result<SlashProposalStatus, String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            tally_slash_proposal(state, proposal_id, caller, canister_self(), now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn tally_slash_proposal(
+    state: &mut VaultState,
+    proposal_id: u64,
+    caller: Principal,
+    vault: Principal,
+    now: u64,
+) -> Result<SlashProposalStatus, String> {
+    let proposal = state.slash_proposals.iter()
+        .find(|p| p.proposal_id == proposal_id)
+        .cloned()
+        .ok_or_else(|| format!("Slashing proposal {} not found", proposal_id))?;
+    
+    if proposal.status != SlashProposalStatus::Open {
+        return Err(format!("Slashing proposal is not open. Current status: {:?}", proposal.status));
+    }
+    
+    if now <= proposal.challenge_deadline {
+        return Err("Challenge period still active".to_string());
+    }
+    
+    // A DAO decision is binding; otherwise backers must reach the required share of votes
+    let total_weight: f64 = state.backers.values().map(|b| b.share_percentage).sum();
+    let approved = match proposal.dao_decision {
+        Some(decision) => decision,
+        None => total_weight > 0.0
+            && proposal.votes_for * 100.0 / total_weight
+                >= proposal.votes_required as f64,
+    };
+    
+    let slash_id = if approved {
+        Some(execute_slash(state, &proposal, vault, now))
+    } else {
+        None
+    };
+    let status = if approved {
+        SlashProposalStatus::Executed
+    } else {
+        SlashProposalStatus::Rejected
+    };
+    
+    if let Some(p) = state.slash_proposals.iter_mut().find(|p| p.proposal_id == proposal_id) {
+        p.status = status.clone();
+        p.resolved_at = Some(now);
+        p.slash_id = slash_id;
+    }
+    
+    record_event_at(caller, VaultEventKind::SlashResolved {
+        proposal_id,
+        status: status.clone(),
+        slash_id,
+    }, now);
+    
+    push_creator_notice(
+        state,
+        format!("Slashing proposal {} finalized: {:?}", proposal_id, status),
+        Some(proposal_id),
+        now,
+    );
+    
+    Ok(status)
+}
+
+fn execute_slash(state: &mut VaultState, proposal: &SlashProposal, vault: Principal, now: u64) -> u64 {
+    // Redirect a percentage of the creator's remaining revenue share to backers
+    let remaining = 100 - state.creator_slash_percentage as u64;
+    let additional = remaining * proposal.slash_percentage as u64 / 100;
+    state.creator_slash_percentage += additional as u8;
+    
+    // Revenue already allocated to the creator but not yet withdrawn moves to backers now,
+    // future revenue is diverted when it is allocated
+    let unwithdrawn = state.creator_revenue_total.saturating_sub(state.creator_withdrawn);
+    let diverted = unwithdrawn * additional / 100;
+    state.creator_revenue_total -= diverted;
+    state.investor_revenue_total += diverted;
+    credit_and_record(
+        state,
+        diverted,
+        false,
+        CreditSource::SlashDiversion { proposal_id: proposal.proposal_id },
+        vault,
+        now,
+    );
+    
+    let mut slash_amount = diverted;
+    
+    // The locked creator bond is slashed by the same percentage straight into the insurance pool
+    if let Some(bond) = state.creator_bond.as_mut().filter(|b| b.released_at.is_none()) {
+        let bond_slash = (bond.amount - bond.slashed_amount) * proposal.slash_percentage as u64 / 100;
+        bond.slashed_amount += bond_slash;
+        state.insurance_pool_balance += bond_slash;
+        slash_amount += bond_slash;
+    }
+    
+    let mut approved_by: Vec<Principal> = state.dao_canister
+        .filter(|_| proposal.dao_decision == Some(true))
+        .into_iter()
+        .collect();
+    approved_by.extend(proposal.supporters.iter().cloned());
+    
+    // Record slash event
+    let slash_event = SlashEvent {
+        proposal_id: proposal.proposal_id,
+        creator: proposal.creator,
+        campaign_id: state.campaign_id,
+        reason: proposal.reason.clone(),
+        evidence: proposal.evidence.clone(),
+        slash_percentage: additional as u8,
+        amount_slashed: slash_amount,
+        beneficiaries: state.backers.keys().cloned().collect(), // Distribute to all backers
+        executed_at: now,
+        approved_by,
+    };
+    
+    state.slashed_creators.push(slash_event);
+    
+    let slash_id = state.slashed_creators.len() as u64 - 1;
+    push_insurance_trigger(state, InsuranceTrigger::SlashExecuted { slash_id }, vault, now);
+    
+    slash_id
+}
+
+// Creator revenue share
+
+fn creator_statement(state: &VaultState, now: u64) -> CreatorStatement {
+    let vesting_period = (state.creator_vesting_days * NANOS_PER_DAY) as u128;
+    
+    let mut allocated: u128 = 0;
+    let mut vested: u128 = 0;
+    for report in &state.revenue_history {
+        let share = report.creator_amount as u128;
+        let elapsed = now.saturating_sub(report.timestamp) as u128;
+        
+        allocated += share;
+        vested += if vesting_period == 0 || elapsed >= vesting_period {
+            share
+        } else {
+            share * elapsed / vesting_period
+        };
+    }
+    
+    // Slashes move already allocated creator revenue to backers, scale vesting down accordingly
+    let accrued = state.creator_revenue_total;
+    let vested = (vested * accrued as u128).checked_div(allocated).unwrap_or(0) as u64;
+    
+    let withdrawable = match state.creator_payout_mode {
+        CreatorPayoutMode::Vesting => vested,
+        CreatorPayoutMode::Stream => accrued,
+    }.saturating_sub(state.creator_withdrawn);
+    
+    // Hold back the creator share while a slashing review is pending
+    let pending_slash_proposals = state.slash_proposals.iter()
+        .filter(|p| p.status == SlashProposalStatus::Open)
+        .count() as u64;
+    let (withheld, withdrawable) = if pending_slash_proposals > 0 {
+        (withdrawable, 0)
+    } else {
+        (0, withdrawable)
+    };
+    
+    // Only deposited revenue that backers are not owed can be paid out
+    let available = withdrawable.min(creator_revenue_available(state));
+    
+    CreatorStatement {
+        accrued,
+        vested,
+        withdrawn: state.creator_withdrawn,
+        withheld,
+        available,
+        awaiting_deposit: withdrawable - available,
+        slash_percentage: state.creator_slash_percentage,
+        pending_slash_proposals,
+    }
+}
+
+#[update]
+async fn withdraw_creator_share() -> Result<u64, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::operation(GuardedOperation::CreatorWithdrawal)?;
+    let now = time();
+    
+    // Reserve the amount before any await so a concurrent call cannot withdraw it again
+    let (amount, mode, vesting_days, payout_id) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can withdraw the creator share".to_string());
+            }
+            
+            ensure_not_paused(state)?;
+            
+            let statement = creator_statement(state, now);
+            if statement.available == 0 {
+                return if statement.withheld > 0 {
+                    Err("Creator share is withheld while a slashing review is pending".to_string())
+                } else if statement.awaiting_deposit > 0 {
+                    Err("Creator share is waiting for revenue to be deposited".to_string())
+                } else {
+                    Err("No creator share available to withdraw".to_string())
+                };
+            }
+            
+            let amount = statement.available;
+            state.creator_withdrawn += amount;
+            revenue_fund(state, None).disbursed += amount;
+            
+            // Vesting mode pays the creator directly
+            let mode = state.creator_payout_mode.clone();
+            let payout_id = if mode == CreatorPayoutMode::Vesting {
+                Some(book_payout(state, PayoutKind::CreatorShare, None, caller, amount, now, now))
+            } else {
+                None
+            };
+            Ok((amount, mode, state.creator_vesting_days, payout_id))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    if let Some(payout_id) = payout_id {
+        return settle_payout(payout_id).await.map(|_| amount);
+    }
+    
+    let result = match (get_stream_canister(), get_ledger_canister()) {
+        (Some(stream_canister), Some(ledger)) => {
+            create_creator_stream(stream_canister, ledger, caller, amount, vesting_days).await
+        },
+        (None, _) => Err("Stream canister not configured".to_string()),
+        (_, None) => Err("Ledger canister not configured".to_string()),
+    };
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            match &result {
+                Ok(reference) => {
+                    let withdrawal_id = state.creator_withdrawals.len() as u64;
+                    state.creator_withdrawals.push(CreatorWithdrawal {
+                        withdrawal_id,
+                        amount,
+                        mode: mode.clone(),
+                        reference: *reference,
+                        withdrawn_at: now,
+                    });
+                    record_event(caller, VaultEventKind::CreatorWithdrawal {
+                        amount,
+                        mode,
+                        reference: *reference,
+                    });
+                },
+                Err(_) => {
+                    state.creator_withdrawn -= amount;
+                    revenue_fund(state, None).disbursed -= amount;
+                },
+            }
+        }
+    });
+    
+    result.map(|_| amount)
+}
+
+// Funds a vesting stream of the creator's share from the vault's base ledger. ipx-stream binds
+// the stream to this vault and its campaign, so the vault must be one of its authorized funders
+async fn create_creator_stream(
+    stream_canister: Principal,
+    ledger: Principal,
+    creator: Principal,
+    amount: u64,
+    vesting_days: u64,
+) -> Result<u64, String> {
+    token_approve(ledger, stream_canister, amount).await?;
+    
+    let call_result: CallResult<(Result<u64, String>,)> = call(
+        stream_canister,
+        "create_stream",
+        (creator, amount, vesting_days.max(1) * 24 * 60 * 60, StreamType::Linear, ledger),
+    ).await;
+    
+    match call_result {
+        Ok((Ok(stream_id),)) => Ok(stream_id),
+        Ok((Err(e),)) => Err(e),
+        Err(e) => Err(format!("Failed to create stream: {:?}", e)),
+    }
+}
+
+#[update]
+fn set_creator_payout_mode(mode: CreatorPayoutMode, vesting_days: Option<u64>) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can change the payout mode".to_string());
+            }
+            
+            // Switching modes would let unvested amounts be withdrawn early
+            if state.creator_withdrawn > 0 && state.creator_payout_mode != mode {
+                return Err("Payout mode cannot change after the first withdrawal".to_string());
+            }
+            
+            if let Some(days) = vesting_days {
+                if days < state.creator_vesting_days && state.creator_withdrawn > 0 {
+                    return Err("Vesting period cannot be shortened after the first withdrawal".to_string());
+                }
+                state.creator_vesting_days = days;
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "creator_payout_mode".to_string(),
+                value: format!("{:?}, {} vesting days", mode, state.creator_vesting_days),
+            });
+            
+            state.creator_payout_mode = mode;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[query]
+fn get_creator_statement() -> Option<CreatorStatement> {
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| creator_statement(s, now))
+    })
+}
+
+#[query]
+fn get_creator_withdrawals() -> Vec<CreatorWithdrawal> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.creator_withdrawals.clone()).unwrap_or_default()
+    })
+}
+
+// Vault activation and creator bond
+
+#[update]
+async fn activate_vault(bond_amount: u64, bond_lock_days: u64) -> Result<(), String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::operation(GuardedOperation::Activation)?;
+    let now = time();
+    
+    // Mark the vault active before the deposit so it cannot be activated twice
+    let lock_end = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can activate the vault".to_string());
+            }
+            
+            let lock_end = bond_lock_end(now, bond_lock_days)?;
+            
+            if state.activated_at.is_some() {
+                return Err("Vault is already active".to_string());
+            }
+            
+            if let Some(RevenueShareTerm::EndDate { timestamp }) = state.revenue_share_term {
+                if timestamp <= now {
+                    return Err("Revenue share term has already ended".to_string());
+                }
+            }
+            
+            state.activated_at = Some(now);
+            Ok(lock_end)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    if bond_amount == 0 {
+        record_event(caller, VaultEventKind::VaultActivated { bond_amount: 0, bond_block_index: None });
+        return Ok(());
+    }
+    
+    let result = ledger_transfer_from(caller, bond_amount, now).await;
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            match &result {
+                Ok(block_index) => {
+                    // The bond stays locked for at least the revenue share term
+                    let locked_until = cmp::max(lock_end, term_end(state).unwrap_or(0));
+                    
+                    state.creator_bond = Some(CreatorBond {
+                        amount: bond_amount,
+                        deposited_at: now,
+                        locked_until,
+                        slashed_amount: 0,
+                        deposit_block_index: *block_index,
+                        released_at: None,
+                        release_block_index: None,
+                    });
+                    record_event(caller, VaultEventKind::VaultActivated {
+                        bond_amount,
+                        bond_block_index: Some(*block_index),
+                    });
+                },
+                Err(_) => state.activated_at = None,
+            }
+        }
+    });
+    
+    result.map(|_| ())
+}
+
+// Bounded so the lock cannot wrap past u64 into the past, which would free the bond at once
+fn bond_lock_end(now: u64, bond_lock_days: u64) -> Result<u64, String> {
+    if bond_lock_days > MAX_BOND_LOCK_DAYS {
+        return Err(format!("Bond lock cannot exceed {} days", MAX_BOND_LOCK_DAYS));
+    }
+    Ok(now + bond_lock_days * NANOS_PER_DAY)
+}
+
+#[update]
+async fn release_creator_bond() -> Result<u64, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::operation(GuardedOperation::BondRelease)?;
+    let now = time();
+    
+    let (amount, payout_id) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can release the bond".to_string());
+            }
+            
+            ensure_not_paused(state)?;
+            
+            // The bond is only returned when the campaign completed cleanly
+            if state.releases_paused
+                || state.insurance_triggers.iter().any(|t| t.trigger == InsuranceTrigger::CampaignFailure)
+            {
+                return Err("Bond cannot be released for a failed campaign".to_string());
+            }
+            
+            if state.slash_proposals.iter().any(|p| p.status == SlashProposalStatus::Open) {
+                return Err("Bond cannot be released while a slashing review is pending".to_string());
+            }
+            
+            let bond = state.creator_bond.as_mut()
+                .ok_or_else(|| "No creator bond posted".to_string())?;
+            
+            if bond.released_at.is_some() {
+                return Err("Bond already released".to_string());
+            }
+            
+            if now < bond.locked_until {
+                return Err(format!("Bond is locked until {}", bond.locked_until));
+            }
+            
+            bond.released_at = Some(now);
+            let amount = bond.amount - bond.slashed_amount;
+            
+            // A fully slashed bond has nothing to transfer
+            if amount == 0 {
+                record_event(caller, VaultEventKind::BondReleased { amount, block_index: None });
+                return Ok((amount, None));
+            }
+            Ok((amount, Some(book_payout(state, PayoutKind::BondRelease, None, caller, amount, now, now))))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    match payout_id {
+        Some(payout_id) => settle_payout(payout_id).await.map(|_| amount),
+        None => Ok(amount),
+    }
+}
+
+#[query]
+fn get_creator_bond() -> Option<CreatorBond> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.creator_bond.clone())
+    })
+}
+
+// Ledger reconciliation and invariants
+
+fn ensure_not_paused(state: &VaultState) -> Result<(), String> {
+    if state.paused {
+        return Err(format!(
+            "Vault is paused: {}",
+            state.pause_reason.as_deref().unwrap_or("no reason given")
+        ));
+    }
+    Ok(())
+}
+
+fn halt_vault(state: &mut VaultState, reason: String, caller: Principal) {
+    state.paused = true;
+    state.pause_reason = Some(reason.clone());
+    record_event(caller, VaultEventKind::VaultPaused { operations: None, reason });
+}
+
+// Funds the books say the vault holds for backers, the insurance pool and the bond
+fn expected_ledger_balance(state: &VaultState) -> (u64, u64, u64) {
+    let reserved_claims: u64 = state.insurance_claims.iter()
+        .filter(|c| matches!(c.status, ClaimStatus::Approved))
+        .map(|c| c.amount)
+        .sum();
+    let bond_held = state.creator_bond.as_ref()
+        .filter(|b| b.released_at.is_none())
+        .map_or(0, |b| b.amount - b.slashed_amount);
+    
+    let pending_investments: u64 = state.pending_investments.iter().map(|p| p.amount).sum();
+    
+    // Capital raised in other tokens is booked in base units but held on those ledgers
+    let token_capital_value: u64 = state.token_holdings.iter().map(|h| h.capital_value).sum();
+    
+    // Deposited revenue less what was disbursed: creator withdrawals, distribution streams and reinvestment
+    let revenue_held = revenue_available(state, None);
+    
+    let expected = (state.current_funding + state.insurance_pool_balance + state.pending_premiums
+        + pending_investments + reserved_claims + bond_held + revenue_held)
+        .saturating_sub(state.total_paid_out + state.total_refunded + token_capital_value);
+    
+    (expected, reserved_claims, bond_held)
+}
+
+fn check_invariants(state: &VaultState) -> Vec<Discrepancy> {
+    let mut discrepancies = Vec::new();
+    
+    let sum_of_positions: u64 = state.backers.values().map(|b| b.principal).sum();
+    if sum_of_positions != state.current_funding {
+        discrepancies.push(Discrepancy::PositionsMismatch {
+            sum_of_positions,
+            current_funding: state.current_funding,
+        });
+    }
+    
+    for (backer, info) in &state.backers {
+        if info.total_claimed > info.revenue_entitlement + info.sale_proceeds {
+            discrepancies.push(Discrepancy::ClaimsExceedEntitlement {
+                backer: *backer,
+                claimed: info.total_claimed,
+                entitlement: info.revenue_entitlement + info.sale_proceeds,
+            });
+        }
+    }
+    
+    discrepancies
+}
+
+async fn ledger_balance() -> Result<u64, String> {
+    let ledger = get_ledger_canister().ok_or_else(|| "Ledger canister not configured".to_string())?;
+    token_balance(ledger).await
+}
+
+async fn token_balance(ledger: Principal) -> Result<u64, String> {
+    let result: CallResult<(Nat,)> = call(
+        ledger,
+        "icrc1_balance_of",
+        (Account { owner: canister_self(), subaccount: None },),
+    ).await;
+    
+    match result {
+        Ok((balance,)) => u64::try_from(balance.0)
+            .map_err(|_| "Ledger balance does not fit in u64".to_string()),
+        Err(e) => Err(format!("Failed to call ledger: {:?}", e)),
+    }
+}
+
+#[update]
+async fn reconcile(pause_on_discrepancy: bool) -> Result<ReconciliationReport, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::operation(GuardedOperation::Reconcile)?;
+    
+    let token_ledgers = VAULT_STATE.with(|state_ref| {
+        let state_opt = state_ref.borrow();
+        if let Some(ref state) = *state_opt {
+            // Anyone can reconcile, but only the guardian or governance may pause on the result
+            if pause_on_discrepancy && state.guardian != Some(caller) && !is_governance(state, caller) {
+                return Err("Only the guardian or governance can pause the vault".to_string());
+            }
+            
+            let mut ledgers: Vec<Principal> = state.token_holdings.iter().map(|h| h.ledger)
+                .chain(state.revenue_funds.iter().filter_map(|f| f.token))
+                .collect();
+            ledgers.sort();
+            ledgers.dedup();
+            Ok(ledgers)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    let balance = ledger_balance().await;
+    let mut token_balances = Vec::new();
+    for ledger in token_ledgers {
+        token_balances.push((ledger, token_balance(ledger).await));
+    }
+    
+    // Read the books after the await so they match the balance just fetched
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            let (expected_balance, reserved_claims, bond_held) = expected_ledger_balance(state);
+            let mut discrepancies = check_invariants(state);
+            
+            let ledger_balance = match balance {
+                Ok(actual) => {
+                    if actual < expected_balance {
+                        discrepancies.push(Discrepancy::LedgerShortfall { expected: expected_balance, actual });
+                    }
+                    Some(actual)
+                },
+                Err(error) => {
+                    discrepancies.push(Discrepancy::LedgerUnavailable { error });
+                    None
+                },
+            };
+            
+            // Other tokens only have to cover the capital and undisbursed revenue held in them
+            for (ledger, balance) in token_balances {
+                let expected = state.token_holdings.iter()
+                    .find(|h| h.ledger == ledger)
+                    .map_or(0, |h| h.capital)
+                    + revenue_available(state, Some(ledger));
+                match balance {
+                    Ok(actual) if actual < expected => {
+                        discrepancies.push(Discrepancy::TokenShortfall { ledger, expected, actual });
+                    },
+                    Ok(_) => {},
+                    Err(error) => discrepancies.push(Discrepancy::LedgerUnavailable { error }),
+                }
+            }
+            
+            // An unreachable ledger is reported but does not count as a broken invariant
+            let broken = discrepancies.iter().any(|d| !matches!(d, Discrepancy::LedgerUnavailable { .. }));
+            if broken && pause_on_discrepancy && !state.paused {
+                halt_vault(state, format!("Reconciliation failed: {:?}", discrepancies), caller);
+            }
+            
+            let report = ReconciliationReport {
+                checked_at: time(),
+                current_funding: state.current_funding,
+                insurance_pool_balance: state.insurance_pool_balance,
+                reserved_claims,
+                bond_held,
+                revenue_held: revenue_available(state, None),
+                total_paid_out: state.total_paid_out,
+                total_refunded: state.total_refunded,
+                expected_balance,
+                ledger_balance,
+                discrepancies,
+                paused: state.paused,
+            };
+            
+            state.last_reconciliation = Some(report.clone());
+            Ok(report)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[query]
+fn check_vault_invariants() -> Vec<Discrepancy> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map_or_else(Vec::new, check_invariants)
+    })
+}
+
+#[query]
+fn get_last_reconciliation() -> Option<ReconciliationReport> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.last_reconciliation.clone())
+    })
+}
+
+// Emergency pause and guardian controls
+
+fn ensure_operation_enabled(state: &VaultState, operation: VaultOperation) -> Result<(), VaultError> {
+    if state.paused {
+        return Err(VaultError::VaultPaused {
+            reason: state.pause_reason.clone().unwrap_or_default(),
+        });
+    }
+    
+    match state.circuit_breakers.iter().find(|b| b.operation == operation) {
+        Some(breaker) => Err(VaultError::OperationPaused {
+            operation,
+            reason: breaker.reason.clone(),
+            paused_at: breaker.tripped_at,
+        }),
+        None => Ok(()),
+    }
+}
+
+#[update]
+fn set_guardian(guardian: Option<Principal>) -> Result<(), String> {
+    let caller = msg_caller();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if !is_governance(state, caller) {
+                return Err("Only governance can set the guardian".to_string());
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "guardian".to_string(),
+                value: format!("{:?}", guardian),
+            });
+            
+            state.guardian = guardian;
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+// Pauses the given operations, or the whole vault when `operations` is None
+#[update]
+async fn pause_vault(operations: Option<Vec<VaultOperation>>, reason: String) -> Result<(), String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    // Stream claims are paid by ipx-stream, so pausing them trips its breaker for this vault
+    let freeze_streams = operations.as_ref().is_none_or(|ops| ops.contains(&VaultOperation::StreamTransfers));
+    let stream_reason = reason.clone();
+    
+    let stream_canister = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.guardian != Some(caller) && !is_governance(state, caller) {
+                return Err("Only the guardian or governance can pause the vault".to_string());
+            }
+            
+            if reason.is_empty() {
+                return Err("A reason is required to pause the vault".to_string());
+            }
+            
+            match operations {
+                None => halt_vault(state, reason, caller),
+                Some(operations) => {
+                    if operations.is_empty() {
+                        return Err("No operations to pause".to_string());
+                    }
+                    
+                    for operation in &operations {
+                        if !state.circuit_breakers.iter().any(|b| b.operation == *operation) {
+                            state.circuit_breakers.push(CircuitBreaker {
+                                operation: operation.clone(),
+                                reason: reason.clone(),
+                                tripped_by: caller,
+                                tripped_at: now,
+                            });
+                        }
+                    }
+                    
+                    record_event(caller, VaultEventKind::VaultPaused { operations: Some(operations), reason });
+                },
+            }
+            
+            Ok(state.stream_canister.filter(|_| freeze_streams))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    match stream_canister {
+        Some(stream_canister) => set_stream_claims_frozen(stream_canister, true, stream_reason).await,
+        None => Ok(()),
+    }
+}
+
+// Lifts a pause; only the DAO can do this, so a compromised guardian cannot undo its own pause
+#[update]
+async fn unpause_vault(operations: Option<Vec<VaultOperation>>) -> Result<(), String> {
+    let caller = msg_caller();
+    let thaw_streams = operations.as_ref().is_none_or(|ops| ops.contains(&VaultOperation::StreamTransfers));
+    
+    let stream_canister = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if !is_governance(state, caller) {
+                return Err("Only governance can unpause the vault".to_string());
+            }
+            
+            match operations {
+                None => {
+                    state.paused = false;
+                    state.pause_reason = None;
+                    state.circuit_breakers.clear();
+                },
+                Some(ref operations) => {
+                    state.circuit_breakers.retain(|b| !operations.contains(&b.operation));
+                },
+            }
+            
+            record_event(caller, VaultEventKind::VaultUnpaused { operations });
+            
+            // Claims stay frozen while the whole vault or the stream breaker is still paused
+            let streams_paused = state.paused
+                || state.circuit_breakers.iter().any(|b| b.operation == VaultOperation::StreamTransfers);
+            Ok(state.stream_canister.filter(|_| thaw_streams && !streams_paused))
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    match stream_canister {
+        Some(stream_canister) => set_stream_claims_frozen(stream_canister, false, String::new()).await,
+        None => Ok(()),
+    }
+}
+
+async fn set_stream_claims_frozen(stream_canister: Principal, frozen: bool, reason: String) -> Result<(), String> {
+    let result: CallResult<(Result<(), String>,)> = call(
+        stream_canister,
+        "set_claims_frozen",
+        (canister_self(), frozen, reason),
+    ).await;
+    
+    match result {
+        Ok((Ok(()),)) => Ok(()),
+        Ok((Err(e),)) => Err(format!("Vault updated, but the stream claims breaker was not: {}", e)),
+        Err(e) => Err(format!("Vault updated, but the stream claims breaker was not: {:?}", e)),
+    }
+}
+
+#[query]
+fn get_circuit_breakers() -> Vec<CircuitBreaker> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.circuit_breakers.clone()).unwrap_or_default()
+    })
+}
+
+#[query]
+fn get_guardian() -> Option<Principal> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.guardian)
+    })
+}
+
+// Revenue share term and settlement
+
+fn term_end(state: &VaultState) -> Option<u64> {
+    match state.revenue_share_term {
+        Some(RevenueShareTerm::Duration { days }) => state.activated_at.map(|at| at + days * NANOS_PER_DAY),
+        Some(RevenueShareTerm::EndDate { timestamp }) => Some(timestamp),
+        None => None,
+    }
+}
+
+#[update]
+fn set_revenue_share_term(term: RevenueShareTerm, leftover_rule: InsuranceLeftoverRule) -> Result<(), String> {
+    let caller = msg_caller();
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can set the revenue share term".to_string());
+            }
+            
+            // Backers invest under these terms, so they are fixed once the vault is active
+            if state.activated_at.is_some() {
+                return Err("Revenue share term cannot change after activation".to_string());
+            }
+            
+            match term {
+                RevenueShareTerm::Duration { days: 0 } => {
+                    return Err("Revenue share term must be at least one day".to_string());
+                },
+                RevenueShareTerm::EndDate { timestamp } if timestamp <= now => {
+                    return Err("Revenue share term must end in the future".to_string());
+                },
+                _ => {},
+            }
+            
+            if leftover_rule == InsuranceLeftoverRule::TransferToDao && state.dao_canister.is_none() {
+                return Err("DAO canister not configured".to_string());
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "revenue_share_term".to_string(),
+                value: format!("{:?}, leftover: {:?}", term, leftover_rule),
+            });
+            
+            state.revenue_share_term = Some(term);
+            state.insurance_leftover_rule = leftover_rule;
+            
+            Ok(())
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+#[update]
+async fn settle_vault() -> Result<VaultSettlement, String> {
+    let caller = msg_caller();
+    let _guard = CallGuard::operation(GuardedOperation::Settlement)?;
+    let now = time();
+    
+    // Move to Settling and release the insurance pool before any await
+    let (end, leftover, leftover_rule, discarded, leftover_payout) = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            begin_settlement(state, caller, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })?;
+    
+    let mut leftover_block_index = None;
+    if let Some(payout_id) = leftover_payout {
+        match send_payout(payout_id).await {
+            TransferOutcome::Completed(block_index) => leftover_block_index = Some(block_index),
+            // The leftover stays booked to the DAO and is paid when the payout is retried
+            TransferOutcome::Unknown(_) => {},
+            TransferOutcome::Rejected(e) => {
+                VAULT_STATE.with(|state_ref| {
+                    let mut state_opt = state_ref.borrow_mut();
+                    if let Some(ref mut state) = *state_opt {
+                        state.vault_status = VaultStatus::Active;
+                    }
+                });
+                return Err(e);
+            },
+        }
+    }
+    
+    // The final distribution and token retirement are best effort: entitlements
+    // remain claimable through distribute_payouts after the vault closes
+    let (final_distribution, distribution_error) = match run_distribution(DistributionTrigger::Settlement, caller).await {
+        Ok(payouts) => (payouts, None),
+        Err(e) => (Vec::new(), Some(format!("{:?}", e))),
+    };
+    
+    let (tokens_retired, retirement_error) = match get_nft_registry_canister() {
+        Some(nft_registry) => {
+            let result: CallResult<(Result<u64, String>,)> = call(
+                nft_registry,
+                "retire_campaign_tokens",
+                (),
+            ).await;
+            
+            match result {
+                Ok((Ok(retired),)) => (Some(retired), None),
+                Ok((Err(e),)) => (None, Some(e)),
+                Err(e) => (None, Some(format!("Failed to call NFT registry: {:?}", e))),
+            }
+        },
+        None => (None, Some("NFT registry not configured".to_string())),
+    };
+    
+    let settlement = VaultSettlement {
+        term_end: end,
+        settled_at: now,
+        settled_by: caller,
+        discarded_pending_reports: discarded,
+        insurance_leftover: leftover,
+        leftover_rule,
+        leftover_block_index,
+        final_distribution,
+        distribution_error,
+        tokens_retired,
+        retirement_error,
+    };
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            state.vault_status = VaultStatus::Closed;
+            state.settlement = Some(settlement.clone());
+        }
+    });
+    
+    record_event(caller, VaultEventKind::VaultSettled {
+        insurance_leftover: leftover,
+        leftover_rule: settlement.leftover_rule.clone(),
+    });
+    
+    ic_cdk::println!("Vault settled at {}: {} insurance leftover", now, leftover);
+    
+    Ok(settlement)
+}
+
+// Checks that nothing is left open, then closes the books and books the insurance leftover
+fn begin_settlement(
+    state: &mut VaultState,
+    caller: Principal,
+    now: u64,
+) -> Result<(u64, u64, InsuranceLeftoverRule, u64, Option<u64>), String> {
+    if state.vault_status != VaultStatus::Active {
+        return Err(format!("Vault is already {:?}", state.vault_status));
+    }
+    
+    ensure_not_paused(state)?;
+    
+    let end = term_end(state).ok_or_else(|| "Vault has no revenue share term".to_string())?;
+    if now < end {
+        return Err(format!("Revenue share term ends at {}", end));
+    }
+    
+    if state.slash_proposals.iter().any(|p| p.status == SlashProposalStatus::Open) {
+        return Err("Cannot settle while a slashing review is pending".to_string());
+    }
+    
+    if !state.pending_investments.is_empty() {
+        return Err("Cannot settle while investments are in their cooling-off window".to_string());
+    }
+    
+    if !state.pending_payouts.is_empty() {
+        return Err("Cannot settle while payouts are pending, retry them first".to_string());
+    }
+    
+    let open_claims = state.insurance_claims.iter().any(|c| match c.status {
+        ClaimStatus::Pending | ClaimStatus::Appealed => true,
+        ClaimStatus::Rejected => c.appeal_deadline.is_some_and(|d| now < d),
+        _ => false,
+    });
+    if open_claims {
+        return Err("Cannot settle while insurance claims are undecided".to_string());
+    }
+    
+    let leftover_rule = state.insurance_leftover_rule.clone();
+    let dao = state.dao_canister;
+    if leftover_rule == InsuranceLeftoverRule::TransferToDao && dao.is_none() {
+        return Err("DAO canister not configured".to_string());
+    }
+    
+    let discarded = state.pending_revenue_reports.len() as u64;
+    state.pending_revenue_reports.clear();
+    
+    // Fees held in other tokens stay booked there, only the base pool is handed on
+    let leftover = insurance_pool_for(state, None);
+    state.insurance_pool_balance -= leftover;
+    if leftover_rule == InsuranceLeftoverRule::ReturnToBackers {
+        // Already on the base ledger, so it is streamed like deposited revenue
+        revenue_fund(state, None).deposited += leftover;
+        credit_and_record(state, leftover, false, CreditSource::InsuranceLeftover, caller, now);
+    }
+    
+    state.vault_status = VaultStatus::Settling;
+    
+    let leftover_payout = match (&leftover_rule, dao) {
+        (InsuranceLeftoverRule::TransferToDao, Some(dao)) if leftover > 0 => {
+            Some(book_payout(state, PayoutKind::InsuranceLeftover, None, dao, leftover, now, now))
+        },
+        _ => None,
+    };
+    
+    Ok((end, leftover, leftover_rule, discarded, leftover_payout))
 }
 
 #[query]
-fn get_backer_info(backer: Principal) -> Option<BackerInfo> {
+fn get_vault_status() -> Option<(VaultStatus, Option<u64>)> {
     VAULT_STATE.with(|state_ref| {
-        state_ref.borrow().as_ref().and_then(|s| s.backers.get(&backer).cloned())
+        state_ref.borrow().as_ref().map(|s| (s.vault_status.clone(), term_end(s)))
     })
 }
 
 #[query]
-fn get_funding_progress() -> (u64, u64, f64) {
+fn get_settlement() -> Option<VaultSettlement> {
     VAULT_STATE.with(|state_ref| {
-        if let Some(ref state) = *state_ref.borrow() {
-            let percentage = (state.current_funding as f64 / state.funding_goal as f64) * 100.0;
-            (state.current_funding, state.funding_goal, percentage)
-        } else {
-            (0, 0, 0.0)
-        }
+        state_ref.borrow().as_ref().and_then(|s| s.settlement.clone())
     })
 }
 
-// Insurance-related functions
+// Milestone-gated capital release
 
-#[query]
-fn get_insurance_pool_info() -> (u64, u8, u8) {
+// Returns the sum of the tranches, which must fit within the funding goal
+fn validate_milestones(milestones: &[MilestoneInput], funding_goal: u64, now: u64) -> Result<u64, String> {
+    if milestones.is_empty() {
+        return Err("At least one milestone is required".to_string());
+    }
+    
+    let total_tranches = milestones.iter()
+        .try_fold(0u64, |total, m| total.checked_add(m.tranche_amount))
+        .ok_or_else(|| "Milestone tranches overflow".to_string())?;
+    if total_tranches > funding_goal {
+        return Err("Milestone tranches exceed the funding goal".to_string());
+    }
+    
+    let mut previous_due_date = now;
+    for milestone in milestones {
+        if milestone.tranche_amount == 0 {
+            return Err("Milestone tranche amount must be greater than 0".to_string());
+        }
+        if milestone.due_date <= previous_due_date {
+            return Err("Milestone due dates must be in the future and in ascending order".to_string());
+        }
+        previous_due_date = milestone.due_date;
+    }
+    
+    Ok(total_tranches)
+}
+
+#[update]
+fn define_milestones(milestones: Vec<MilestoneInput>) -> Result<(), String> {
+    let caller = msg_caller();
+    let now = time();
+    
     VAULT_STATE.with(|state_ref| {
-        if let Some(ref state) = *state_ref.borrow() {
-            (
-                state.insurance_pool_balance,
-                state.insurance_fee_percentage,
-                state.insurance_coverage_ratio
-            )
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.creator != caller {
+                return Err("Only the creator can define milestones".to_string());
+            }
+            
+            // Milestones are a commitment to backers and cannot be rewritten
+            if !state.milestones.is_empty() {
+                return Err("Milestones have already been defined".to_string());
+            }
+            
+            let total_tranches = validate_milestones(&milestones, state.funding_goal, now)?;
+            
+            state.milestones = milestones.into_iter()
+                .enumerate()
+                .map(|(index, input)| Milestone {
+                    milestone_id: index as u64,
+                    description: input.description,
+                    tranche_amount: input.tranche_amount,
+                    due_date: input.due_date,
+                    status: MilestoneStatus::Pending,
+                    voting_deadline: None,
+                    votes_for: 0.0,
+                    votes_against: 0.0,
+                    voters: Vec::new(),
+                    resolved_at: None,
+                    release_block_index: None,
+                    token_releases: Vec::new(),
+                })
+                .collect();
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "milestones".to_string(),
+                value: format!("{} milestones, {} in tranches", state.milestones.len(), total_tranches),
+            });
+            
+            Ok(())
         } else {
-            (0, 0, 0)
+            Err("Vault not initialized".to_string())
         }
     })
 }
 
 #[update]
-fn file_insurance_claim(amount: u64, reason: String, evidence: Vec<String>) -> Result<u64, String> {
+fn request_milestone_release(milestone_id: u64) -> Result<(), String> {
     let caller = msg_caller();
+    let now = time();
     
     VAULT_STATE.with(|state_ref| {
         let mut state_opt = state_ref.borrow_mut();
         if let Some(ref mut state) = *state_opt {
-            // Check if caller is a backer
-            if let Some(backer_info) = state.backers.get(&caller) {
-                // Calculate max claimable amount (coverage ratio * investment)
-                let max_claimable = (backer_info.amount_invested * state.insurance_coverage_ratio as u64) / 100;
-                
-                if amount > max_claimable {
-                    return Err(format!("Claim exceeds maximum coverage of {}", max_claimable));
+            if state.creator != caller {
+                return Err("Only the creator can request a milestone release".to_string());
+            }
+            
+            check_milestone_deadlines(state, now);
+            
+            if state.releases_paused {
+                return Err("Milestone releases are paused".to_string());
+            }
+            
+            // Milestones are released strictly in order
+            if state.milestones.iter()
+                .take_while(|m| m.milestone_id < milestone_id)
+                .any(|m| m.status != MilestoneStatus::Released)
+            {
+                return Err("Earlier milestones must be released first".to_string());
+            }
+            
+            let remaining_escrow = state.current_funding - state.capital_released;
+            
+            if let Some(milestone) = state.milestones.iter_mut().find(|m| m.milestone_id == milestone_id) {
+                if milestone.status != MilestoneStatus::Pending {
+                    return Err(format!("Milestone is not pending. Current status: {:?}", milestone.status));
                 }
                 
-                // Check if there's enough in the insurance pool
-                if amount > state.insurance_pool_balance {
-                    return Err("Insufficient funds in insurance pool".to_string());
+                if milestone.tranche_amount > remaining_escrow {
+                    return Err("Insufficient escrow for this tranche".to_string());
                 }
                 
-                // Create claim
-                let claim_id = state.insurance_claims.len() as u64;
-                let claim = InsuranceClaim {
-                    claim_id,
-                    claimer: caller,
-                    amount,
-                    reason,
-                    evidence,
-                    status: ClaimStatus::Pending,
-                    filed_at: time(),
-                    resolved_at: None,
-                    approver: None,
-                };
-                
-                state.insurance_claims.push(claim);
-                
-                Ok(claim_id)
+                milestone.status = MilestoneStatus::Voting;
+                milestone.voting_deadline = Some(now + MILESTONE_VOTING_PERIOD_DAYS * NANOS_PER_DAY);
+                Ok(())
             } else {
-                Err("Only backers can file insurance claims".to_string())
+                Err(format!("Milestone {} not found", milestone_id))
             }
         } else {
             Err("Vault not initialized".to_string())
@@ -507,93 +6037,456 @@ fn file_insurance_claim(amount: u64, reason: String, evidence: Vec<String>) -> R
 }
 
 #[update]
-fn process_insurance_claim(claim_id: u64, approve: bool, notes: String) -> Result<(), String> {
+fn vote_on_milestone(milestone_id: u64, approve: bool) -> Result<(), String> {
     let caller = msg_caller();
+    let now = time();
     
     VAULT_STATE.with(|state_ref| {
         let mut state_opt = state_ref.borrow_mut();
         if let Some(ref mut state) = *state_opt {
-            // Check if caller is creator or has governance rights
-            if state.creator != caller {
-                return Err("Only creator or governance can process claims".to_string());
+            cast_milestone_vote(state, milestone_id, approve, caller, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn cast_milestone_vote(state: &mut VaultState, milestone_id: u64, approve: bool, caller: Principal, now: u64) -> Result<(), String> {
+    let weight = match state.backers.get(&caller) {
+        Some(info) => info.share_percentage,
+        None => return Err("Only backers can vote on milestones".to_string()),
+    };
+    
+    let milestone = state.milestones.iter_mut().find(|m| m.milestone_id == milestone_id)
+        .ok_or_else(|| format!("Milestone {} not found", milestone_id))?;
+    
+    if milestone.status != MilestoneStatus::Voting
+        || milestone.voting_deadline.is_none_or(|deadline| now > deadline)
+    {
+        return Err("Milestone is not open for voting".to_string());
+    }
+    
+    if milestone.voters.contains(&caller) {
+        return Err("Already voted".to_string());
+    }
+    
+    if approve {
+        milestone.votes_for += weight;
+    } else {
+        milestone.votes_against += weight;
+    }
+    milestone.voters.push(caller);
+    
+    Ok(())
+}
+
+// Tallies the vote once the voting period is over
+#[update]
+fn finalize_milestone(milestone_id: u64) -> Result<MilestoneStatus, String> {
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            tally_milestone(state, milestone_id, now)
+        } else {
+            Err("Vault not initialized".to_string())
+        }
+    })
+}
+
+fn tally_milestone(state: &mut VaultState, milestone_id: u64, now: u64) -> Result<MilestoneStatus, String> {
+    check_milestone_deadlines(state, now);
+    
+    let total_weight: f64 = state.backers.values().map(|b| b.share_percentage).sum();
+    let milestone = state.milestones.iter_mut()
+        .find(|m| m.milestone_id == milestone_id)
+        .ok_or_else(|| format!("Milestone {} not found", milestone_id))?;
+    
+    if milestone.status != MilestoneStatus::Voting {
+        return Ok(milestone.status.clone());
+    }
+    
+    if milestone.voting_deadline.is_some_and(|deadline| now <= deadline) {
+        return Err("Voting period still active".to_string());
+    }
+    
+    // Without a quorum neither side decides: the milestone goes back to pending and can be
+    // put to a new vote before its due date
+    let turnout = milestone.votes_for + milestone.votes_against;
+    if total_weight <= 0.0 || turnout * 100.0 < total_weight * MILESTONE_QUORUM_PERCENTAGE {
+        milestone.status = MilestoneStatus::Pending;
+        milestone.voting_deadline = None;
+        milestone.votes_for = 0.0;
+        milestone.votes_against = 0.0;
+        milestone.voters.clear();
+        let message = format!("Milestone {} vote did not reach the {}% quorum", milestone_id, MILESTONE_QUORUM_PERCENTAGE);
+        push_creator_notice(state, message, None, now);
+        return Ok(MilestoneStatus::Pending);
+    }
+    
+    milestone.resolved_at = Some(now);
+    milestone.status = if milestone.votes_for > milestone.votes_against {
+        MilestoneStatus::Approved
+    } else {
+        MilestoneStatus::Rejected
+    };
+    let status = milestone.status.clone();
+    
+    if status == MilestoneStatus::Rejected {
+        pause_milestone_releases(state, now);
+    }
+    
+    Ok(status)
+}
+
+// Pays an approved tranche to the creator. Capital raised in several tokens is released in
+// parts, one per token weighted by the positions held in it; each call pays the next part.
+#[update]
+async fn release_milestone_tranche(milestone_id: u64) -> Result<u64, String> {
+    let _guard = CallGuard::operation(GuardedOperation::MilestoneRelease)?;
+    
+    // Book the part as released before the transfer so it cannot be paid twice
+    let payout_id = VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            if state.releases_paused {
+                return Err("Milestone releases are paused".to_string());
             }
             
-            // Find the claim
-            if let Some(claim) = state.insurance_claims.iter_mut().find(|c| c.claim_id == claim_id) {
-                if matches!(claim.status, ClaimStatus::Pending) {
-                    if approve {
-                        // Make sure we have enough in the pool
-                        if claim.amount > state.insurance_pool_balance {
-                            return Err("Insufficient funds in insurance pool".to_string());
-                        }
-                        
-                        // Update claim status
-                        claim.status = ClaimStatus::Approved;
-                        claim.resolved_at = Some(time());
-                        claim.approver = Some(caller);
-                        
-                        // Reduce the insurance pool
-                        state.insurance_pool_balance -= claim.amount;
-                                                
-                    } else {
-                        // Reject the claim
-                        claim.status = ClaimStatus::Rejected;
-                        claim.resolved_at = Some(time());
-                        claim.approver = Some(caller);
-                    }
-                    
-                    Ok(())
-                } else {
-                    Err(format!("Claim is not pending. Current status: {:?}", claim.status))
-                }
-            } else {
-                Err(format!("Claim with ID {} not found", claim_id))
+            ensure_not_paused(state)?;
+            
+            let index = state.milestones.iter()
+                .position(|m| m.milestone_id == milestone_id)
+                .ok_or_else(|| format!("Milestone {} not found", milestone_id))?;
+            
+            if state.milestones[index].status != MilestoneStatus::Approved {
+                return Err(format!("Milestone is not approved. Current status: {:?}", state.milestones[index].status));
+            }
+            
+            // The part being paid has no block index yet, so it must not be picked again
+            let unsettled = state.pending_payouts.iter().any(|p| {
+                matches!(p.kind, PayoutKind::TrancheRelease { milestone_id: id, .. } if id == milestone_id)
+            });
+            if unsettled {
+                return Err("A payout of this tranche is still pending, retry it with retry_payout".to_string());
             }
+            
+            if state.milestones[index].token_releases.is_empty() {
+                let parts = tranche_parts(state, state.milestones[index].tranche_amount);
+                state.milestones[index].token_releases = parts;
+            }
+            
+            let milestone = &state.milestones[index];
+            let part_index = milestone.token_releases.iter()
+                .position(|r| r.block_index.is_none())
+                .ok_or_else(|| "Every part of the tranche is already released".to_string())?;
+            let part = milestone.token_releases[part_index].clone();
+            
+            state.capital_released += part.value;
+            state.total_paid_out += part.value;
+            adjust_token_capital(state, part.token, -(part.amount as i128), -(part.value as i128));
+            
+            let kind = PayoutKind::TrancheRelease { milestone_id, part_index: part_index as u64, value: part.value };
+            let creator = state.creator;
+            Ok(book_payout(state, kind, part.token, creator, part.amount, milestone_id, time()))
         } else {
             Err("Vault not initialized".to_string())
         }
-    })
+    })?;
+    
+    settle_payout(payout_id).await
 }
 
-// Slashing-related functions
+// Splits a tranche between the tokens capital was raised in, in proportion to the positions in each
+fn tranche_parts(state: &VaultState, tranche_amount: u64) -> Vec<TokenRelease> {
+    let total_principal = total_principal(state);
+    let mut parts = Vec::new();
+    let mut remaining = tranche_amount;
+    
+    if total_principal > 0 {
+        for holding in state.token_holdings.iter().filter(|h| h.capital_value > 0) {
+            let token_principal: u64 = state.backers.values()
+                .filter(|b| b.token == Some(holding.ledger))
+                .map(|b| b.principal)
+                .sum();
+            let value = cmp::min(
+                (tranche_amount as u128 * token_principal as u128 / total_principal as u128) as u64,
+                holding.capital_value,
+            );
+            if value == 0 {
+                continue;
+            }
+            
+            // Converted at the rate the capital was booked at
+            let amount = (value as u128 * holding.capital as u128 / holding.capital_value as u128) as u64;
+            remaining -= value;
+            parts.push(TokenRelease { token: Some(holding.ledger), amount, value, block_index: None });
+        }
+    }
+    
+    if remaining > 0 || parts.is_empty() {
+        parts.push(TokenRelease { token: None, amount: remaining, value: remaining, block_index: None });
+    }
+    parts
+}
+
+// Daily checks that must not wait for someone to call into the vault
+fn run_scheduled_checks() {
+    let now = time();
+    let has_streams = VAULT_STATE.with(|state_ref| {
+        if let Some(ref mut state) = *state_ref.borrow_mut() {
+            check_milestone_deadlines(state, now);
+            state.stream_canister.is_some()
+        } else {
+            false
+        }
+    });
+    
+    check_slashing_conditions();
+    
+    if has_streams {
+        ic_cdk::futures::spawn(async {
+            // Picked up again on the next check if ipx-stream cannot be reached
+            let _ = sync_stream_payouts().await;
+        });
+    }
+}
+
+// Marks overdue milestones as missed, which stops further releases
+fn check_milestone_deadlines(state: &mut VaultState, now: u64) {
+    let mut missed = false;
+    
+    for milestone in state.milestones.iter_mut() {
+        if milestone.status == MilestoneStatus::Pending && now > milestone.due_date {
+            milestone.status = MilestoneStatus::Missed;
+            milestone.resolved_at = Some(now);
+            missed = true;
+        }
+    }
+    
+    if missed {
+        pause_milestone_releases(state, now);
+    }
+}
+
+fn pause_milestone_releases(state: &mut VaultState, now: u64) {
+    if state.releases_paused {
+        return;
+    }
+    
+    state.releases_paused = true;
+    state.refundable_escrow = state.current_funding - state.capital_released;
+    
+    record_event_at(canister_self(), VaultEventKind::SettingsChanged {
+        setting: "releases_paused".to_string(),
+        value: format!("true, {} refundable escrow", state.refundable_escrow),
+    }, now);
+    
+    push_creator_notice(
+        state,
+        format!("Milestone releases paused, {} of escrow will be refunded to backers", state.refundable_escrow),
+        None,
+        now,
+    );
+}
 
 #[update]
-fn propose_slashing(creator: Principal, reason: SlashReason, evidence: Vec<String>) -> Result<u64, String> {
+async fn claim_escrow_refund() -> Result<u64, String> {
     let caller = msg_caller();
+    let now = time();
+    let _guard = CallGuard::principal(GuardedOperation::EscrowRefund, caller)?;
     
-
-    VAULT_STATE.with(|state_ref| {
+    let (amount, payout_id) = VAULT_STATE.with(|state_ref| {
         let mut state_opt = state_ref.borrow_mut();
         if let Some(ref mut state) = *state_opt {
-            // Basic validation
-            if creator != state.creator {
-                return Err("Target is not the creator of this campaign".to_string());
+            if !state.releases_paused {
+                return Err("Escrow refunds are only available once milestone releases are paused".to_string());
             }
             
-         
-            let creator_share = state.total_revenue * (100 - state.revenue_share_percentage) as u64 / 100;
-            let slash_amount = creator_share / 2;
+            ensure_not_paused(state)?;
             
-            // Record slash event
-            let slash_event = SlashEvent {
-                creator,
-                campaign_id: state.campaign_id,
-                reason,
-                amount_slashed: slash_amount,
-                beneficiaries: state.backers.keys().cloned().collect(), // Distribute to all backers
-                executed_at: time(),
-                approved_by: vec![caller], 
-            };
+            // Principal is never reduced, so the split stays the same however many backers already claimed
+            let total_principal = total_principal(state);
+            let refundable_escrow = state.refundable_escrow;
             
-            state.slashed_creators.push(slash_event.clone());
+            let backer = state.backers.get_mut(&caller)
+                .ok_or_else(|| "Only backers can claim escrow refunds".to_string())?;
             
+            if backer.refunded_amount > 0 {
+                return Err("Escrow refund already claimed".to_string());
+            }
             
-            state.insurance_pool_balance += slash_amount;
+            let amount = (refundable_escrow as u128 * backer.principal as u128 / total_principal.max(1) as u128) as u64;
+            if amount == 0 {
+                return Err("No escrow to refund".to_string());
+            }
             
-            Ok(state.slashed_creators.len() as u64 - 1)
+            // A refunded backer has exited: no more votes and no share of future revenue. Revenue
+            // already credited stays claimable.
+            backer.refunded_amount = amount;
+            backer.share_percentage = 0.0;
+            let (token, token_amount) = position_amount(backer, amount);
+            state.total_refunded += amount;
+            adjust_token_capital(state, token, -(token_amount as i128), -(amount as i128));
+            let payout_id = book_payout(state, PayoutKind::EscrowRefund { value: amount }, token, caller, token_amount, now, now);
+            Ok((amount, payout_id))
         } else {
             Err("Vault not initialized".to_string())
         }
+    })?;
+    
+    settle_payout(payout_id).await.map(|_| amount)
+}
+
+#[query]
+fn get_milestones() -> Vec<Milestone> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.milestones.clone()).unwrap_or_default()
+    })
+}
+
+// Automated slashing condition monitoring
+
+fn check_slashing_conditions() {
+    let now = time();
+    
+    VAULT_STATE.with(|state_ref| {
+        let mut state_opt = state_ref.borrow_mut();
+        if let Some(ref mut state) = *state_opt {
+            // Nothing is owed to backers once the term is over
+            if state.vault_status != VaultStatus::Active || term_end(state).is_some_and(|end| now >= end) {
+                return;
+            }
+            
+            let metrics = measure_conditions(state, now);
+            let previous_missed = state.condition_metrics.as_ref().map_or(0, |m| m.missed_reports);
+            let conditions = state.slashing_conditions.clone();
+            
+            if metrics.active_days >= conditions.minimum_active_period_days {
+                let evidence = vec![format!("{:?}", metrics)];
+                let last_report = metrics.last_report_period_end
+                    .unwrap_or(state.activated_at.unwrap_or(state.created_at));
+                
+                if metrics.missed_reports >= conditions.missed_revenue_reports_threshold as u64 {
+                    let breach = ConditionBreach::MissedReports { since: last_report };
+                    if !state.flagged_breaches.contains(&breach)
+                        && !has_open_slash_proposal(state, |r| matches!(r, SlashReason::MissedRevenueReports))
+                    {
+                        state.flagged_breaches.push(breach);
+                        let missed_reports = cmp::min(metrics.missed_reports, u8::MAX as u64) as u8;
+                        push_insurance_trigger(
+                            state,
+                            InsuranceTrigger::MissedRevenueReports { missed_reports },
+                            canister_self(),
+                            now,
+                        );
+                        open_slash_proposal(
+                            state,
+                            SlashReason::MissedRevenueReports,
+                            evidence.clone(),
+                            AUTO_SLASH_PERCENTAGE,
+                            canister_self(),
+                            now,
+                        );
+                    }
+                } else if metrics.missed_reports > previous_missed {
+                    push_creator_notice(
+                        state,
+                        format!(
+                            "Warning: {} revenue report(s) missed, slashing review opens at {}",
+                            metrics.missed_reports, conditions.missed_revenue_reports_threshold
+                        ),
+                        None,
+                        now,
+                    );
+                }
+                
+                let breach = ConditionBreach::RevenueDecline { period_end: last_report };
+                if conditions.revenue_decline_threshold_percentage > 0
+                    && metrics.revenue_decline_percentage >= conditions.revenue_decline_threshold_percentage
+                    && !state.flagged_breaches.contains(&breach)
+                    && !has_open_slash_proposal(state, |r| matches!(r, SlashReason::RevenueDecline))
+                {
+                    state.flagged_breaches.push(breach);
+                    open_slash_proposal(
+                        state,
+                        SlashReason::RevenueDecline,
+                        evidence,
+                        AUTO_SLASH_PERCENTAGE,
+                        canister_self(),
+                        now,
+                    );
+                }
+            }
+            
+            state.condition_metrics = Some(metrics);
+        }
+    });
+}
+
+fn measure_conditions(state: &VaultState, now: u64) -> ConditionMetrics {
+    let interval = state.slashing_conditions.expected_report_interval_days.max(1) * NANOS_PER_DAY;
+    
+    let started_at = state.activated_at.unwrap_or(state.created_at);
+    let last_report_period_end = state.revenue_history.iter().map(|r| r.period_end).max();
+    let reference = last_report_period_end.unwrap_or(started_at);
+    let grace = REPORT_GRACE_PERIOD_DAYS * NANOS_PER_DAY;
+    let missed_reports = now.saturating_sub(reference + grace) / interval;
+    
+    // The windows end at the last report, so a late report is counted as missed, never as a decline
+    let window_end = last_report_period_end.unwrap_or(now);
+    let current_start = window_end.saturating_sub(interval);
+    let previous_start = window_end.saturating_sub(2 * interval);
+    let window_revenue = |from: u64, to: u64| -> u64 {
+        state.revenue_history.iter()
+            .filter(|r| r.period_end > from && r.period_end <= to)
+            .map(|r| r.amount)
+            .sum()
+    };
+    let current_window_revenue = window_revenue(current_start, window_end);
+    let previous_window_revenue = window_revenue(previous_start, current_start);
+    
+    let revenue_decline_percentage = if previous_window_revenue > current_window_revenue {
+        ((previous_window_revenue - current_window_revenue) * 100 / previous_window_revenue) as u8
+    } else {
+        0
+    };
+    
+    ConditionMetrics {
+        evaluated_at: now,
+        active_days: now.saturating_sub(started_at) / NANOS_PER_DAY,
+        last_report_period_end,
+        missed_reports,
+        current_window_revenue,
+        previous_window_revenue,
+        revenue_decline_percentage,
+    }
+}
+
+fn has_open_slash_proposal(state: &VaultState, reason: impl Fn(&SlashReason) -> bool) -> bool {
+    state.slash_proposals.iter()
+        .any(|p| p.status == SlashProposalStatus::Open && reason(&p.reason))
+}
+
+#[query]
+fn get_condition_metrics() -> Option<ConditionMetrics> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().and_then(|s| s.condition_metrics.clone())
+    })
+}
+
+#[query]
+fn get_slash_proposals() -> Vec<SlashProposal> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.slash_proposals.clone()).unwrap_or_default()
+    })
+}
+
+#[query]
+fn get_creator_notices() -> Vec<CreatorNotice> {
+    VAULT_STATE.with(|state_ref| {
+        state_ref.borrow().as_ref().map(|s| s.creator_notices.clone()).unwrap_or_default()
     })
 }
 
@@ -606,6 +6499,7 @@ fn get_slashing_conditions() -> SlashingConditions {
                 revenue_decline_threshold_percentage: 0,
                 minimum_active_period_days: 0,
                 governance_votes_required: 0,
+                expected_report_interval_days: 0,
             },
             |s| s.slashing_conditions.clone()
         )
@@ -615,10 +6509,7 @@ fn get_slashing_conditions() -> SlashingConditions {
 #[query]
 fn get_slash_events() -> Vec<SlashEvent> {
     VAULT_STATE.with(|state_ref| {
-        state_ref.borrow().as_ref().map_or_else(
-            || Vec::new(),
-            |s| s.slashed_creators.clone()
-        )
+        state_ref.borrow().as_ref().map(|s| s.slashed_creators.clone()).unwrap_or_default()
     })
 }
 
@@ -655,14 +6546,36 @@ fn set_canister_refs(
     nft_registry: Option<Principal>,
     stream: Option<Principal>,
     oracle: Option<Principal>,
+    ledger: Option<Principal>,
+    dao: Option<Principal>,
 ) -> Result<(), String> {
     let caller = msg_caller();
     
     VAULT_STATE.with(|state_ref| {
         let mut state_opt = state_ref.borrow_mut();
         if let Some(ref mut state) = *state_opt {
-            if state.creator != caller {
-                return Err("Only creator can set canister references".to_string());
+            // The creator is overseen by these canisters, so only the deployer or governance wires them up
+            if caller != state.deployer && !is_governance(state, caller) {
+                return Err("Only the deployer or governance can set canister references".to_string());
+            }
+            
+            // The creator cannot act as their own oracle, their reports stay unverified
+            if oracle == Some(state.creator) {
+                return Err("Oracle canister cannot be the creator".to_string());
+            }
+            
+            // Governance oversees the creator, so it cannot be swapped out once set
+            if let (Some(current), Some(new_dao)) = (state.dao_canister, dao) {
+                if current != new_dao {
+                    return Err("Governance canister is already set".to_string());
+                }
+            }
+            
+            // Other tokens are valued against the base ledger, so it is fixed once they are accepted
+            if let (Some(current), Some(new_ledger)) = (state.ledger_canister, ledger) {
+                if current != new_ledger && !state.accepted_tokens.is_empty() {
+                    return Err("Base ledger cannot change while other tokens are accepted".to_string());
+                }
             }
             
             if let Some(nft) = nft_registry {
@@ -674,6 +6587,21 @@ fn set_canister_refs(
             if let Some(oracle) = oracle {
                 state.oracle_canister = Some(oracle);
             }
+            if let Some(ledger) = ledger {
+                state.ledger_canister = Some(ledger);
+            }
+            if let Some(dao) = dao {
+                state.dao_canister = Some(dao);
+            }
+            
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "canister_refs".to_string(),
+                value: format!(
+                    "nft_registry: {:?}, stream: {:?}, oracle: {:?}, ledger: {:?}, dao: {:?}",
+                    state.nft_registry_canister, state.stream_canister, state.oracle_canister,
+                    state.ledger_canister, state.dao_canister
+                ),
+            });
             
             Ok(())
         } else {
@@ -682,6 +6610,28 @@ fn set_canister_refs(
     })
 }
 
+// The creator is the one being slashed, so only governance sets the vote threshold. Once the vault is
+// active or has backers, the thresholds that detect a breach are governance-only as well.
+fn check_slashing_conditions_change(state: &VaultState, conditions: &SlashingConditions, governance: bool) -> Result<(), String> {
+    let current = &state.slashing_conditions;
+    
+    if conditions.governance_votes_required != current.governance_votes_required {
+        if !governance {
+            return Err("Only governance can change the votes required to slash".to_string());
+        }
+        if conditions.governance_votes_required == 0 || conditions.governance_votes_required > 100 {
+            return Err("Votes required must be between 1 and 100%".to_string());
+        }
+    }
+    
+    let monitored = state.activated_at.is_some() || !state.backers.is_empty() || !state.pending_investments.is_empty();
+    if monitored && !governance && conditions != current {
+        return Err("Only governance can change the slashing conditions of an active vault".to_string());
+    }
+    
+    Ok(())
+}
+
 #[update]
 fn update_insurance_settings(
     fee_percentage: Option<u8>,
@@ -694,10 +6644,15 @@ fn update_insurance_settings(
         let mut state_opt = state_ref.borrow_mut();
         if let Some(ref mut state) = *state_opt {
             // Only creator or DAO governance can update these settings
-            if state.creator != caller {
+            let governance = is_governance(state, caller);
+            if state.creator != caller && !governance {
                 return Err("Only creator or governance can update insurance settings".to_string());
             }
             
+            if let Some(ref conditions) = slashing_conditions {
+                check_slashing_conditions_change(state, conditions, governance)?;
+            }
+            
             // Update insurance fee percentage if provided
             if let Some(fee) = fee_percentage {
                 if fee > 20 {
@@ -719,6 +6674,14 @@ fn update_insurance_settings(
                 state.slashing_conditions = conditions;
             }
             
+            record_event(caller, VaultEventKind::SettingsChanged {
+                setting: "insurance_settings".to_string(),
+                value: format!(
+                    "fee: {}%, coverage: {}%, {:?}",
+                    state.insurance_fee_percentage, state.insurance_coverage_ratio, state.slashing_conditions
+                ),
+            });
+            
             Ok(())
         } else {
             Err("Vault not initialized".to_string())
@@ -726,4 +6689,1093 @@ fn update_insurance_settings(
     })
 }
 
-ic_cdk::export_candid!();
\ No newline at end of file
+ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn principal(id: u8) -> Principal {
+        Principal::from_slice(&[id])
+    }
+    
+    fn state_with_backer(backer: Principal, revenue_entitlement: u64) -> VaultState {
+        let mut state = default_vault_state(0);
+        state.backers.insert(backer, BackerInfo {
+            amount_invested: 1_000,
+            nft_token_id: None,
+            share_percentage: 0.1,
+            total_claimed: 0,
+            investment_timestamp: 0,
+            refunded_amount: 0,
+            principal: 980,
+            revenue_entitlement,
+            tranche: BackerTranche::Junior,
+            token: None,
+            token_amount: 1_000,
+            token_entitlements: Vec::new(),
+            token_credited_value: 0,
+            auto_reinvest: false,
+            reinvestment_balance: 0,
+            sell_order: 0,
+            sale_proceeds: 0,
+        });
+        state
+    }
+    
+    #[test]
+    fn operation_guard_rejects_overlapping_calls_until_released() {
+        let first = CallGuard::operation(GuardedOperation::Settlement).unwrap();
+        
+        // A second call arrives while the first is suspended at an await
+        assert!(CallGuard::operation(GuardedOperation::Settlement).is_err());
+        // Other operations are not blocked
+        assert!(CallGuard::operation(GuardedOperation::Reconcile).is_ok());
+        
+        drop(first);
+        assert!(CallGuard::operation(GuardedOperation::Settlement).is_ok());
+    }
+    
+    #[test]
+    fn principal_guard_only_blocks_the_same_principal() {
+        let alice = CallGuard::principal(GuardedOperation::EscrowRefund, principal(1)).unwrap();
+        
+        assert!(CallGuard::principal(GuardedOperation::EscrowRefund, principal(1)).is_err());
+        assert!(CallGuard::principal(GuardedOperation::EscrowRefund, principal(2)).is_ok());
+        assert!(CallGuard::principal(GuardedOperation::CancelInvestment, principal(1)).is_ok());
+        
+        drop(alice);
+        assert!(CallGuard::principal(GuardedOperation::EscrowRefund, principal(1)).is_ok());
+    }
+    
+    #[test]
+    fn interleaved_distributions_do_not_double_pay() {
+        let backer = principal(1);
+        let mut state = state_with_backer(backer, 500);
+        
+        // First round reserves the entitlement, then awaits create_streams
+        let _first_guard = CallGuard::operation(GuardedOperation::Distribution).unwrap();
+        let first = reserve_payouts(&mut state);
+        assert_eq!(first, vec![(backer, 500)]);
+        
+        // An overlapping round is turned away by the guard, and even without it finds nothing left
+        assert!(CallGuard::operation(GuardedOperation::Distribution).is_err());
+        assert!(reserve_payouts(&mut state).is_empty());
+        
+        // New revenue during the await is only the new amount
+        state.backers.get_mut(&backer).unwrap().revenue_entitlement += 200;
+        assert_eq!(reserve_payouts(&mut state), vec![(backer, 200)]);
+    }
+    
+    #[test]
+    fn failed_distribution_releases_its_reservation() {
+        let backer = principal(1);
+        let mut state = state_with_backer(backer, 500);
+        
+        let payouts = reserve_payouts(&mut state);
+        release_payouts(&mut state, &payouts);
+        
+        assert_eq!(state.backers[&backer].total_claimed, 0);
+        assert_eq!(reserve_payouts(&mut state), vec![(backer, 500)]);
+    }
+    
+    #[test]
+    fn interleaved_mints_produce_one_token_per_backer() {
+        let backer = principal(1);
+        let mut state = state_with_backer(backer, 0);
+        state.backers.insert(principal(2), state.backers[&backer].clone());
+        
+        let first = CallGuard::principal(GuardedOperation::Mint, backer).unwrap();
+        assert!(mint_candidate(&state, backer).is_ok());
+        
+        // Same backer is blocked while the registry call is outstanding, another backer is not
+        assert!(CallGuard::principal(GuardedOperation::Mint, backer).is_err());
+        assert!(CallGuard::principal(GuardedOperation::Mint, principal(2)).is_ok());
+        
+        // First call commits its token and releases the guard
+        state.backers.get_mut(&backer).unwrap().nft_token_id = Some(7);
+        drop(first);
+        
+        let _retry = CallGuard::principal(GuardedOperation::Mint, backer).unwrap();
+        assert!(mint_candidate(&state, backer).is_err());
+    }
+    
+    fn waterfall(post_recoupment_share_percentage: u8, return_cap_percentage: Option<u64>) -> DistributionWaterfall {
+        DistributionWaterfall {
+            recoupment_enabled: true,
+            post_recoupment_share_percentage,
+            return_cap_percentage,
+            senior_tranche_enabled: false,
+        }
+    }
+    
+    #[test]
+    fn waterfall_recoups_principal_before_sharing_revenue() {
+        let mut state = state_with_backer(principal(1), 0);
+        state.distribution_waterfall = Some(waterfall(50, None));
+        
+        // 980 recoups the principal, the other 20 is split at the post-recoupment share
+        let (investor, creator, phase) = allocate_revenue(&state, 1_000);
+        assert_eq!((investor, creator, phase), (990, 10, WaterfallPhase::Recoupment));
+        
+        state.investor_revenue_total = 980;
+        assert_eq!(allocate_revenue(&state, 100), (50, 50, WaterfallPhase::PostRecoupment));
+    }
+    
+    #[test]
+    fn waterfall_stops_backer_returns_at_the_cap() {
+        let mut state = state_with_backer(principal(1), 0);
+        state.distribution_waterfall = Some(waterfall(50, Some(200)));
+        state.investor_revenue_total = 1_950;
+        
+        // 10 is left under the 1960 cap: 20 of the revenue fills it at 50%, the creator keeps the rest
+        let (investor, creator, phase) = allocate_revenue(&state, 100);
+        assert_eq!((investor, creator, phase), (10, 90, WaterfallPhase::PostRecoupment));
+        
+        state.investor_revenue_total = 1_960;
+        assert_eq!(allocate_revenue(&state, 100), (0, 100, WaterfallPhase::Capped));
+    }
+    
+    #[test]
+    fn waterfall_rounding_never_loses_or_exceeds_revenue() {
+        let mut state = state_with_backer(principal(1), 0);
+        state.distribution_waterfall = Some(waterfall(30, Some(150)));
+        let cap = return_cap(&state).unwrap();
+        
+        for amount in [1, 2, 3, 7, 99, 101, 977, 1_001, 12_345] {
+            for investor_total in [0, 979, 980, 1_463, 1_469] {
+                state.investor_revenue_total = investor_total;
+                let (investor, creator, _) = allocate_revenue(&state, amount);
+                
+                assert_eq!(investor + creator, amount, "amount {} at {}", amount, investor_total);
+                assert!(investor_total + investor <= cap, "amount {} at {}", amount, investor_total);
+            }
+        }
+        
+        // Without a waterfall the backer share rounds down and the remainder is the creator's
+        state.distribution_waterfall = None;
+        assert_eq!(allocate_revenue(&state, 999), (99, 900, WaterfallPhase::FlatShare));
+    }
+    
+    #[test]
+    fn pro_rata_credits_leave_less_than_one_unit_per_backer() {
+        let mut state = state_with_backer(principal(1), 0);
+        state.backers.insert(principal(2), state.backers[&principal(1)].clone());
+        state.backers.insert(principal(3), state.backers[&principal(1)].clone());
+        
+        credit_backers(&mut state, 100, false);
+        
+        let credited: Vec<u64> = (1..=3).map(|id| state.backers[&principal(id)].revenue_entitlement).collect();
+        assert_eq!(credited, vec![33, 33, 33]);
+        let remainder = 100 - credited.iter().sum::<u64>();
+        assert!(remainder < 3);
+    }
+    
+    #[test]
+    fn senior_backers_recoup_before_juniors() {
+        let mut state = state_with_backer(principal(1), 0);
+        state.backers.insert(principal(2), state.backers[&principal(1)].clone());
+        state.backers.get_mut(&principal(1)).unwrap().tranche = BackerTranche::Senior;
+        
+        // The senior backer is owed 980, the 20 left over goes to the junior backer
+        credit_backers(&mut state, 1_000, true);
+        assert_eq!(state.backers[&principal(1)].revenue_entitlement, 980);
+        assert_eq!(state.backers[&principal(2)].revenue_entitlement, 20);
+    }
+    
+    fn settled_claim(claim_id: u64, recipient: Principal, ledger: Principal, amount: u64) -> SettledClaim {
+        SettledClaim { claim_id, stream_id: 1, recipient, ledger, amount, block_index: 100 + claim_id, settled_at: 0 }
+    }
+    
+    #[test]
+    fn settled_stream_claims_are_booked_as_payouts_on_the_statement() {
+        let backer = principal(1);
+        let (base_ledger, other_ledger, stream_canister) = (principal(8), principal(7), principal(6));
+        let mut state = state_with_backer(backer, 0);
+        state.ledger_canister = Some(base_ledger);
+        
+        book_stream_payouts(&mut state, stream_canister, &[
+            settled_claim(1, backer, base_ledger, 300),
+            settled_claim(2, principal(2), base_ledger, 50),
+        ], 10);
+        book_stream_payouts(&mut state, stream_canister, &[settled_claim(3, backer, other_ledger, 40)], 20);
+        assert_eq!(state.stream_payouts_booked, 3);
+        
+        let kinds: Vec<VaultEventKind> = EVENT_LOG.with(|log| log.borrow().iter().map(|(_, e)| e.kind).collect());
+        assert!(matches!(kinds[2], VaultEventKind::TokenStreamPayout { ledger, amount: 40, .. } if ledger == other_ledger));
+        
+        // Only the backer's own base-token payouts count towards its stream payouts
+        let statement = build_backer_statement(backer, 0, 100, 100);
+        assert_eq!(statement.stream_payouts, 300);
+        assert_eq!(statement.lines.len(), 1);
+        assert_eq!(statement.lines[0].ledger_block_index, Some(101));
+    }
+    
+    #[test]
+    fn creator_cannot_loosen_breach_detection_once_the_vault_is_active() {
+        let mut state = default_vault_state(0);
+        let loosened = SlashingConditions {
+            missed_revenue_reports_threshold: 255,
+            revenue_decline_threshold_percentage: 0,
+            minimum_active_period_days: u64::MAX,
+            ..state.slashing_conditions.clone()
+        };
+        
+        // Setting up the vault before anyone relies on it
+        assert!(check_slashing_conditions_change(&state, &loosened, false).is_ok());
+        
+        state.activated_at = Some(1);
+        assert!(check_slashing_conditions_change(&state, &loosened, false).is_err());
+        let longer_interval = SlashingConditions { expected_report_interval_days: 365, ..state.slashing_conditions.clone() };
+        assert!(check_slashing_conditions_change(&state, &longer_interval, false).is_err());
+        assert!(check_slashing_conditions_change(&state, &loosened, true).is_ok());
+        
+        // Backers without activation count as well
+        let state = state_with_backer(principal(1), 0);
+        assert!(check_slashing_conditions_change(&state, &loosened, false).is_err());
+        assert!(check_slashing_conditions_change(&state, &state.slashing_conditions, false).is_ok());
+        
+        // The vote threshold is governance-only even before activation
+        let fewer_votes = SlashingConditions { governance_votes_required: 10, ..default_vault_state(0).slashing_conditions };
+        assert!(check_slashing_conditions_change(&default_vault_state(0), &fewer_votes, false).is_err());
+    }
+    
+    #[test]
+    fn bond_lock_is_bounded_instead_of_wrapping() {
+        let now = 1_700_000_000 * 1_000_000_000;
+        
+        assert_eq!(bond_lock_end(now, 30), Ok(now + 30 * NANOS_PER_DAY));
+        assert_eq!(bond_lock_end(now, MAX_BOND_LOCK_DAYS), Ok(now + MAX_BOND_LOCK_DAYS * NANOS_PER_DAY));
+        // u64::MAX / NANOS_PER_DAY + 1 days would wrap to a lock far in the past
+        assert!(bond_lock_end(now, u64::MAX / NANOS_PER_DAY + 1).is_err());
+        assert!(bond_lock_end(now, MAX_BOND_LOCK_DAYS + 1).is_err());
+    }
+    
+    fn milestone_input(tranche_amount: u64, due_date: u64) -> MilestoneInput {
+        MilestoneInput { description: "Milestone".to_string(), tranche_amount, due_date }
+    }
+    
+    #[test]
+    fn milestone_tranches_cannot_wrap_past_the_funding_goal() {
+        let valid = [milestone_input(400_000, 10), milestone_input(600_000, 20)];
+        assert_eq!(validate_milestones(&valid, 1_000_000, 0), Ok(1_000_000));
+        
+        // u64::MAX + 2 wraps to 1 without checked addition
+        let wrapping = [milestone_input(u64::MAX, 10), milestone_input(2, 20)];
+        assert!(validate_milestones(&wrapping, 1_000_000, 0).is_err());
+        
+        assert!(validate_milestones(&[milestone_input(1_000_001, 10)], 1_000_000, 0).is_err());
+        assert!(validate_milestones(&[milestone_input(500, 20), milestone_input(500, 10)], 1_000_000, 0).is_err());
+        assert!(validate_milestones(&[], 1_000_000, 0).is_err());
+    }
+    
+    fn filed_claim(claim_id: u64, claimer: Principal, amount: u64) -> InsuranceClaim {
+        InsuranceClaim {
+            claim_id,
+            trigger_id: 0,
+            claimer,
+            amount,
+            reason: "Campaign failed".to_string(),
+            evidence: Vec::new(),
+            status: ClaimStatus::Pending,
+            filed_at: 0,
+            resolved_at: None,
+            approver: None,
+            appeal_deadline: None,
+            appeal_reason: None,
+            resolution_notes: None,
+            paid_at: None,
+            payout_block_index: None,
+        }
+    }
+    
+    #[test]
+    fn adjudication_notes_are_kept_on_the_claim_and_in_the_log() {
+        let (creator, backer) = (principal(5), principal(1));
+        let mut state = state_with_backer(backer, 0);
+        state.creator = creator;
+        state.insurance_claims.push(filed_claim(0, backer, 100));
+        
+        adjudicate_insurance_claim(&mut state, 0, false, "No missed report on record".to_string(), creator, 10).unwrap();
+        
+        assert_eq!(state.insurance_claims[0].resolution_notes.as_deref(), Some("No missed report on record"));
+        let notes = EVENT_LOG.with(|log| match log.borrow().last_key_value().map(|(_, e)| e.kind) {
+            Some(VaultEventKind::InsuranceClaim { notes, status: ClaimStatus::Rejected, .. }) => notes,
+            _ => None,
+        });
+        assert_eq!(notes.as_deref(), Some("No missed report on record"));
+    }
+    
+    #[test]
+    fn insurance_claim_is_filed_rejected_appealed_approved_and_paid() {
+        let (backer, creator, dao) = (principal(1), principal(5), principal(4));
+        let mut state = state_with_backer(backer, 0);
+        state.creator = creator;
+        state.dao_canister = Some(dao);
+        state.insurance_pool_balance = 1_000;
+        
+        // Claims need a recorded trigger and stay within 80% of the 1000 invested
+        assert!(file_claim(&mut state, 0, 500, "Failed".to_string(), Vec::new(), backer, 10).is_err());
+        let trigger_id = push_insurance_trigger(&mut state, InsuranceTrigger::CampaignFailure, dao, 10);
+        assert!(file_claim(&mut state, trigger_id, 801, "Failed".to_string(), Vec::new(), backer, 10).is_err());
+        assert!(file_claim(&mut state, trigger_id, 500, "Failed".to_string(), Vec::new(), principal(2), 10).is_err());
+        let claim_id = file_claim(&mut state, trigger_id, 500, "Failed".to_string(), Vec::new(), backer, 10).unwrap();
+        assert!(file_claim(&mut state, trigger_id, 100, "Again".to_string(), Vec::new(), backer, 10).is_err());
+        
+        // The creator rejects, the backer appeals within the window, only governance decides the appeal
+        adjudicate_insurance_claim(&mut state, claim_id, false, "No failure".to_string(), creator, 20).unwrap();
+        let deadline = 20 + CLAIM_APPEAL_WINDOW_DAYS * NANOS_PER_DAY;
+        assert_eq!(state.insurance_claims[0].appeal_deadline, Some(deadline));
+        assert!(appeal_claim(&mut state, claim_id, "It failed".to_string(), Vec::new(), principal(2), 30).is_err());
+        assert!(appeal_claim(&mut state, claim_id, "It failed".to_string(), Vec::new(), backer, deadline + 1).is_err());
+        appeal_claim(&mut state, claim_id, "It failed".to_string(), vec!["report".to_string()], backer, 30).unwrap();
+        assert!(matches!(state.insurance_claims[0].status, ClaimStatus::Appealed));
+        assert!(adjudicate_insurance_claim(&mut state, claim_id, true, "Approved".to_string(), creator, 40).is_err());
+        adjudicate_insurance_claim(&mut state, claim_id, true, "Approved".to_string(), dao, 40).unwrap();
+        assert_eq!(state.insurance_pool_balance, 500);
+        
+        // A failed transfer releases the claim, the retry lands and records the block
+        assert!(book_claim_payout(&mut state, claim_id, principal(2), 50).is_err());
+        let payout_id = book_claim_payout(&mut state, claim_id, backer, 50).unwrap();
+        assert!(book_claim_payout(&mut state, claim_id, backer, 50).is_err());
+        finish_payout(&mut state, payout_id, None, backer, 60);
+        assert!(matches!(state.insurance_claims[0].status, ClaimStatus::Approved));
+        
+        let payout_id = book_claim_payout(&mut state, claim_id, dao, 70).unwrap();
+        assert_eq!(state.pending_payouts[0].amount, 500);
+        finish_payout(&mut state, payout_id, Some(9), dao, 80);
+        assert!(state.pending_payouts.is_empty());
+        assert!(matches!(state.insurance_claims[0].status, ClaimStatus::Paid));
+        assert_eq!(state.insurance_claims[0].payout_block_index, Some(9));
+    }
+    
+    fn state_with_backers(count: u8) -> VaultState {
+        let mut state = state_with_backer(principal(1), 0);
+        for id in 2..=count {
+            state.backers.insert(principal(id), state.backers[&principal(1)].clone());
+        }
+        state
+    }
+    
+    #[test]
+    fn slash_proposals_pass_on_the_weighted_vote_share() {
+        let (creator, dao) = (principal(5), principal(4));
+        let mut state = state_with_backers(3);
+        state.creator = creator;
+        state.dao_canister = Some(dao);
+        let evidence = vec!["Audit".to_string()];
+        let deadline = SLASH_CHALLENGE_PERIOD_DAYS * NANOS_PER_DAY;
+        
+        // One of three equal backers is 33%, short of the 51% required
+        let rejected = open_slash_proposal(&mut state, SlashReason::RevenueFraud, evidence.clone(), 20, principal(1), 0);
+        assert!(cast_slash_vote(&mut state, rejected, true, creator, 10).is_err());
+        assert!(cast_slash_vote(&mut state, rejected, true, principal(9), 10).is_err());
+        cast_slash_vote(&mut state, rejected, true, principal(1), 10).unwrap();
+        assert!(cast_slash_vote(&mut state, rejected, true, principal(1), 10).is_err());
+        cast_slash_vote(&mut state, rejected, false, principal(2), 10).unwrap();
+        assert!(cast_slash_vote(&mut state, rejected, true, principal(3), deadline + 1).is_err());
+        assert!(tally_slash_proposal(&mut state, rejected, creator, dao, deadline).is_err());
+        assert_eq!(tally_slash_proposal(&mut state, rejected, creator, dao, deadline + 1), Ok(SlashProposalStatus::Rejected));
+        assert_eq!(state.creator_slash_percentage, 0);
+        
+        // Two of three is 66%
+        let passed = open_slash_proposal(&mut state, SlashReason::RevenueFraud, evidence, 20, principal(1), 0);
+        cast_slash_vote(&mut state, passed, true, principal(1), 10).unwrap();
+        cast_slash_vote(&mut state, passed, true, principal(2), 10).unwrap();
+        assert_eq!(tally_slash_proposal(&mut state, passed, creator, dao, deadline + 1), Ok(SlashProposalStatus::Executed));
+        assert_eq!(state.creator_slash_percentage, 20);
+        assert_eq!(state.slash_proposals[passed as usize].slash_id, Some(0));
+        assert!(matches!(state.insurance_triggers[0].trigger, InsuranceTrigger::SlashExecuted { slash_id: 0 }));
+        assert!(tally_slash_proposal(&mut state, passed, creator, dao, deadline + 2).is_err());
+    }
+    
+    #[test]
+    fn dao_decision_overrides_the_backer_vote() {
+        let (creator, dao) = (principal(5), principal(4));
+        let mut state = state_with_backers(3);
+        state.creator = creator;
+        state.dao_canister = Some(dao);
+        let deadline = SLASH_CHALLENGE_PERIOD_DAYS * NANOS_PER_DAY;
+        
+        // Unanimous backers, vetoed by the DAO
+        let vetoed = open_slash_proposal(&mut state, SlashReason::ProjectAbandonment, vec!["Silence".to_string()], 50, dao, 0);
+        for id in 1..=3 {
+            cast_slash_vote(&mut state, vetoed, true, principal(id), 10).unwrap();
+        }
+        assert!(record_dao_decision(&mut state, vetoed, false, principal(1)).is_err());
+        record_dao_decision(&mut state, vetoed, false, dao).unwrap();
+        assert_eq!(tally_slash_proposal(&mut state, vetoed, dao, dao, deadline + 1), Ok(SlashProposalStatus::Rejected));
+        assert!(record_dao_decision(&mut state, vetoed, true, dao).is_err());
+        
+        // No votes at all, approved by the DAO
+        let approved = open_slash_proposal(&mut state, SlashReason::ProjectAbandonment, vec!["Silence".to_string()], 50, dao, 0);
+        record_dao_decision(&mut state, approved, true, dao).unwrap();
+        assert_eq!(tally_slash_proposal(&mut state, approved, dao, dao, deadline + 1), Ok(SlashProposalStatus::Executed));
+        assert_eq!(state.creator_slash_percentage, 50);
+        assert_eq!(state.slashed_creators[0].approved_by, vec![dao]);
+    }
+    
+    fn verified_report(report_id: u64, amount: u64, period_end: u64) -> RevenueUpdate {
+        RevenueUpdate {
+            report_id,
+            amount,
+            source: "oracle".to_string(),
+            timestamp: period_end,
+            oracle_verification: true,
+            idempotency_key: report_id.to_string(),
+            period_start: period_end.saturating_sub(30 * NANOS_PER_DAY),
+            period_end,
+            reported_by: principal(3),
+            token: None,
+            token_amount: amount,
+            investor_amount: 0,
+            creator_amount: 0,
+            phase: None,
+        }
+    }
+    
+    #[test]
+    fn missed_reports_are_counted_per_interval_after_the_grace_period() {
+        let day = NANOS_PER_DAY;
+        let mut state = default_vault_state(0);
+        state.activated_at = Some(0);
+        
+        // Reports are due every 30 days and count as missed 3 days after that
+        assert_eq!(measure_conditions(&state, 33 * day - 1).missed_reports, 0);
+        assert_eq!(measure_conditions(&state, 33 * day).missed_reports, 1);
+        assert_eq!(measure_conditions(&state, 93 * day).missed_reports, 3);
+        assert_eq!(measure_conditions(&state, 93 * day).active_days, 93);
+        
+        // A report restarts the count from the end of its period
+        state.revenue_history.push(verified_report(0, 1_000, 30 * day));
+        let metrics = measure_conditions(&state, 63 * day - 1);
+        assert_eq!((metrics.missed_reports, metrics.last_report_period_end), (0, Some(30 * day)));
+        assert_eq!(measure_conditions(&state, 63 * day).missed_reports, 1);
+    }
+    
+    #[test]
+    fn revenue_decline_compares_the_last_two_report_intervals() {
+        let day = NANOS_PER_DAY;
+        let mut state = default_vault_state(0);
+        state.revenue_history.push(verified_report(0, 600, 20 * day));
+        state.revenue_history.push(verified_report(1, 400, 30 * day));
+        state.revenue_history.push(verified_report(2, 400, 60 * day));
+        
+        let metrics = measure_conditions(&state, 61 * day);
+        assert_eq!((metrics.previous_window_revenue, metrics.current_window_revenue), (1_000, 400));
+        assert_eq!(metrics.revenue_decline_percentage, 60);
+        
+        // Growth is not a decline
+        state.revenue_history.push(verified_report(3, 2_000, 90 * day));
+        assert_eq!(measure_conditions(&state, 91 * day).revenue_decline_percentage, 0);
+        
+        // Not reporting leaves the windows on the last report, it is counted as missed instead
+        let late = measure_conditions(&state, 200 * day);
+        assert_eq!((late.current_window_revenue, late.revenue_decline_percentage), (2_000, 0));
+        assert_eq!(late.missed_reports, 3);
+    }
+    
+    #[test]
+    fn creator_share_vests_linearly_and_is_held_back_during_a_slash_review() {
+        let day = NANOS_PER_DAY;
+        let mut state = state_with_backer(principal(1), 0);
+        let mut report = verified_report(0, 2_000, 0);
+        report.creator_amount = 1_000;
+        state.revenue_history.push(report);
+        state.creator_revenue_total = 1_000;
+        revenue_fund(&mut state, None).deposited = 10_000;
+        
+        // Half of the 30 day vesting period
+        let statement = creator_statement(&state, 15 * day);
+        assert_eq!((statement.accrued, statement.vested, statement.available), (1_000, 500, 500));
+        assert_eq!(creator_statement(&state, 30 * day).vested, 1_000);
+        
+        // Withdrawals come off what vested, deposits cap what can be paid now
+        state.creator_withdrawn = 200;
+        assert_eq!(creator_statement(&state, 15 * day).available, 300);
+        revenue_fund(&mut state, None).deposited = 250;
+        let statement = creator_statement(&state, 15 * day);
+        assert_eq!((statement.available, statement.awaiting_deposit), (250, 50));
+        
+        // Streamed creators are not vested
+        state.creator_payout_mode = CreatorPayoutMode::Stream;
+        revenue_fund(&mut state, None).deposited = 10_000;
+        assert_eq!(creator_statement(&state, 0).available, 800);
+        
+        open_slash_proposal(&mut state, SlashReason::RevenueFraud, vec!["Audit".to_string()], 50, principal(1), 0);
+        let statement = creator_statement(&state, 0);
+        assert_eq!((statement.withheld, statement.available, statement.pending_slash_proposals), (800, 0, 1));
+    }
+    
+    #[test]
+    fn slashes_compound_on_the_remaining_share_and_scale_vesting_down() {
+        let (dao, day) = (principal(4), NANOS_PER_DAY);
+        let mut state = state_with_backer(principal(1), 0);
+        state.dao_canister = Some(dao);
+        let mut report = verified_report(0, 2_000, 0);
+        report.creator_amount = 1_000;
+        state.revenue_history.push(report);
+        state.creator_revenue_total = 1_000;
+        state.creator_bond = Some(CreatorBond {
+            amount: 1_000,
+            deposited_at: 0,
+            locked_until: u64::MAX,
+            slashed_amount: 0,
+            deposit_block_index: 0,
+            released_at: None,
+            release_block_index: None,
+        });
+        
+        let slash = |state: &mut VaultState| {
+            let proposal_id = open_slash_proposal(state, SlashReason::RevenueFraud, vec!["Audit".to_string()], 50, dao, 0);
+            record_dao_decision(state, proposal_id, true, dao).unwrap();
+            tally_slash_proposal(state, proposal_id, dao, dao, 8 * day).unwrap();
+        };
+        
+        // Half of the unwithdrawn share and of the bond
+        slash(&mut state);
+        assert_eq!((state.creator_slash_percentage, state.creator_revenue_total), (50, 500));
+        assert_eq!(state.backers[&principal(1)].revenue_entitlement, 500);
+        assert_eq!(state.insurance_pool_balance, 500);
+        assert_eq!(state.slashed_creators[0].amount_slashed, 1_000);
+        assert_eq!(creator_statement(&state, 15 * day).vested, 250);
+        
+        // The second 50% applies to the half that is left
+        slash(&mut state);
+        assert_eq!((state.creator_slash_percentage, state.creator_revenue_total), (75, 375));
+        assert_eq!(state.slashed_creators[1].slash_percentage, 25);
+        assert_eq!(creator_statement(&state, 30 * day).vested, 375);
+    }
+    
+    fn voting_milestone(milestone_id: u64, tranche_amount: u64) -> Milestone {
+        Milestone {
+            milestone_id,
+            description: "Milestone".to_string(),
+            tranche_amount,
+            due_date: u64::MAX,
+            status: MilestoneStatus::Voting,
+            voting_deadline: Some(MILESTONE_VOTING_PERIOD_DAYS * NANOS_PER_DAY),
+            votes_for: 0.0,
+            votes_against: 0.0,
+            voters: Vec::new(),
+            resolved_at: None,
+            release_block_index: None,
+            token_releases: Vec::new(),
+        }
+    }
+    
+    #[test]
+    fn milestone_vote_needs_a_quorum_before_it_decides() {
+        let deadline = MILESTONE_VOTING_PERIOD_DAYS * NANOS_PER_DAY;
+        let mut state = state_with_backers(10);
+        state.milestones.push(voting_milestone(0, 1_000));
+        
+        // One of ten equal backers is 10% turnout, below the 20% quorum
+        cast_milestone_vote(&mut state, 0, true, principal(1), 10).unwrap();
+        assert!(cast_milestone_vote(&mut state, 0, true, principal(1), 10).is_err());
+        assert!(cast_milestone_vote(&mut state, 0, true, principal(20), 10).is_err());
+        assert!(tally_milestone(&mut state, 0, deadline).is_err());
+        assert_eq!(tally_milestone(&mut state, 0, deadline + 1), Ok(MilestoneStatus::Pending));
+        assert!(state.milestones[0].voters.is_empty());
+        assert!(cast_milestone_vote(&mut state, 0, true, principal(2), 10).is_err());
+        
+        // Put to a new vote, 20% turnout decides it
+        state.milestones[0] = voting_milestone(0, 1_000);
+        cast_milestone_vote(&mut state, 0, true, principal(1), 10).unwrap();
+        cast_milestone_vote(&mut state, 0, true, principal(2), 10).unwrap();
+        assert!(cast_milestone_vote(&mut state, 0, false, principal(3), deadline + 1).is_err());
+        assert_eq!(tally_milestone(&mut state, 0, deadline + 1), Ok(MilestoneStatus::Approved));
+        assert_eq!(state.milestones[0].resolved_at, Some(deadline + 1));
+        assert!(!state.releases_paused);
+    }
+    
+    #[test]
+    fn tranche_is_split_across_tokens_by_principal() {
+        let ledger = principal(7);
+        let mut state = state_with_backers(2);
+        let token_backer = state.backers.get_mut(&principal(2)).unwrap();
+        token_backer.token = Some(ledger);
+        state.token_holdings.push(TokenHolding { ledger, capital: 50, capital_value: 980, insurance_fee_value: 0 });
+        
+        // Half of the principal was raised in the token, converted at the booked 50 / 980 rate
+        let parts = tranche_parts(&state, 1_000);
+        let split: Vec<(Option<Principal>, u64, u64)> = parts.iter().map(|p| (p.token, p.amount, p.value)).collect();
+        assert_eq!(split, vec![(Some(ledger), 25, 500), (None, 500, 500)]);
+        
+        // A token part never exceeds the capital still held in it
+        state.token_holdings[0].capital_value = 300;
+        state.token_holdings[0].capital = 15;
+        let parts = tranche_parts(&state, 1_000);
+        assert_eq!(parts.iter().map(|p| p.value).collect::<Vec<_>>(), vec![300, 700]);
+        assert_eq!(parts[0].amount, 15);
+        
+        // Without token capital the whole tranche is paid in the base token
+        state.token_holdings.clear();
+        let parts = tranche_parts(&state, 1_000);
+        assert_eq!((parts.len(), parts[0].token, parts[0].value), (1, None, 1_000));
+    }
+    
+    #[test]
+    fn settlement_waits_for_the_term_end_and_every_open_item() {
+        let (dao, day) = (principal(4), NANOS_PER_DAY);
+        let end = 365 * day;
+        let mut state = state_with_backers(2);
+        state.activated_at = Some(0);
+        state.insurance_pool_balance = 500;
+        
+        assert!(begin_settlement(&mut state, dao, end).is_err());
+        state.revenue_share_term = Some(RevenueShareTerm::Duration { days: 365 });
+        assert!(begin_settlement(&mut state, dao, end - 1).is_err());
+        
+        // An open slashing review, an undecided claim or a pending payout each hold settlement back
+        open_slash_proposal(&mut state, SlashReason::RevenueFraud, vec!["Audit".to_string()], 10, dao, 0);
+        assert!(begin_settlement(&mut state, dao, end).is_err());
+        state.slash_proposals[0].status = SlashProposalStatus::Rejected;
+        
+        let mut claim = filed_claim(0, principal(1), 100);
+        claim.status = ClaimStatus::Rejected;
+        claim.appeal_deadline = Some(end + 1);
+        state.insurance_claims.push(claim);
+        assert!(begin_settlement(&mut state, dao, end).is_err());
+        
+        book_payout(&mut state, PayoutKind::BondRelease, None, principal(5), 10, 0, 0);
+        assert!(begin_settlement(&mut state, dao, end + 1).is_err());
+        state.pending_payouts.clear();
+        
+        state.insurance_leftover_rule = InsuranceLeftoverRule::TransferToDao;
+        assert!(begin_settlement(&mut state, dao, end + 1).is_err());
+        
+        // Returned to backers, the leftover is credited pro rata and nothing is booked to the DAO
+        state.insurance_leftover_rule = InsuranceLeftoverRule::ReturnToBackers;
+        let (term_end, leftover, _, _, leftover_payout) = begin_settlement(&mut state, dao, end + 1).unwrap();
+        assert_eq!((term_end, leftover, leftover_payout), (end, 500, None));
+        assert_eq!(state.vault_status, VaultStatus::Settling);
+        assert_eq!(state.backers[&principal(1)].revenue_entitlement, 250);
+        assert!(begin_settlement(&mut state, dao, end + 1).is_err());
+    }
+    
+    #[test]
+    fn settlement_books_the_insurance_leftover_to_the_dao() {
+        let dao = principal(4);
+        let mut state = state_with_backers(1);
+        state.revenue_share_term = Some(RevenueShareTerm::EndDate { timestamp: 100 });
+        state.insurance_leftover_rule = InsuranceLeftoverRule::TransferToDao;
+        state.dao_canister = Some(dao);
+        state.insurance_pool_balance = 500;
+        
+        let (_, leftover, _, _, leftover_payout) = begin_settlement(&mut state, dao, 100).unwrap();
+        assert_eq!((leftover, state.insurance_pool_balance), (500, 0));
+        let payout = &state.pending_payouts[0];
+        assert_eq!((Some(payout.payout_id), payout.to, payout.amount), (leftover_payout, dao, 500));
+        assert_eq!(state.backers[&principal(1)].revenue_entitlement, 0);
+    }
+    
+    #[test]
+    fn event_log_pages_through_every_event_once() {
+        for i in 0..250u64 {
+            let kind = if i % 5 == 0 {
+                VaultEventKind::Refund { backer: principal(1), amount: i, block_index: i }
+            } else {
+                VaultEventKind::SettingsChanged { setting: "setting".to_string(), value: i.to_string() }
+            };
+            record_event_at(principal(9), kind, i);
+        }
+        
+        let page = get_events(0, 500, None);
+        assert_eq!((page.events.len(), page.next_event_id, page.total_events), (100, Some(100), 250));
+        assert_eq!(get_events(0, 0, None).events.len(), 1);
+        
+        let mut seen = Vec::new();
+        let mut start = Some(0);
+        while let Some(event_id) = start {
+            let page = get_events(event_id, 100, None);
+            seen.extend(page.events.iter().map(|e| e.event_id));
+            start = page.next_event_id;
+        }
+        assert_eq!(seen, (0..250).collect::<Vec<u64>>());
+        
+        assert!(get_events(250, 100, None).events.is_empty());
+        assert_eq!(get_event(42).map(|e| e.timestamp), Some(42));
+    }
+    
+    #[test]
+    fn event_filters_match_category_principal_and_time_range() {
+        for i in 0..250u64 {
+            let kind = if i % 5 == 0 {
+                VaultEventKind::Refund { backer: principal(1), amount: i, block_index: i }
+            } else {
+                VaultEventKind::SettingsChanged { setting: "setting".to_string(), value: i.to_string() }
+            };
+            record_event_at(principal(9), kind, i);
+        }
+        
+        let refunds = VaultEventFilter {
+            category: Some(VaultEventCategory::Refund),
+            principal: None,
+            from_timestamp: None,
+            to_timestamp: None,
+        };
+        let page = get_events(0, 100, Some(refunds));
+        assert_eq!((page.events.len(), page.next_event_id), (50, None));
+        
+        // The backer is not the caller of its refunds, it matches as the principal they concern
+        let backer_window = VaultEventFilter {
+            category: None,
+            principal: Some(principal(1)),
+            from_timestamp: Some(100),
+            to_timestamp: Some(200),
+        };
+        let page = get_events(0, 100, Some(backer_window));
+        let timestamps: Vec<u64> = page.events.iter().map(|e| e.timestamp).collect();
+        assert_eq!(timestamps, (100..200).step_by(5).collect::<Vec<u64>>());
+        
+        let caller = VaultEventFilter { category: None, principal: Some(principal(9)), from_timestamp: None, to_timestamp: None };
+        assert_eq!(get_events(0, 100, Some(caller)).next_event_id, Some(100));
+    }
+    
+    #[test]
+    fn expected_ledger_balance_adds_holdings_and_subtracts_outflows() {
+        let mut state = state_with_backers(2);
+        state.current_funding = 1_960;
+        state.insurance_pool_balance = 40;
+        let mut claim = filed_claim(0, principal(1), 100);
+        claim.status = ClaimStatus::Approved;
+        state.insurance_claims.push(claim);
+        state.insurance_claims.push(filed_claim(1, principal(2), 70));
+        state.creator_bond = Some(CreatorBond {
+            amount: 500,
+            deposited_at: 0,
+            locked_until: 0,
+            slashed_amount: 100,
+            deposit_block_index: 0,
+            released_at: None,
+            release_block_index: None,
+        });
+        let fund = revenue_fund(&mut state, None);
+        fund.deposited = 1_000;
+        fund.disbursed = 300;
+        state.total_paid_out = 200;
+        state.total_refunded = 100;
+        state.token_holdings.push(TokenHolding { ledger: principal(7), capital: 5, capital_value: 500, insurance_fee_value: 0 });
+        
+        // 1960 + 40 + 100 approved + 400 bond + 700 revenue, less 200 paid, 100 refunded and 500 held in the token
+        assert_eq!(expected_ledger_balance(&state), (2_400, 100, 400));
+        
+        // A released bond has left the vault
+        state.creator_bond.as_mut().unwrap().released_at = Some(1);
+        assert_eq!(expected_ledger_balance(&state).0, 2_000);
+        assert!(check_invariants(&state).is_empty());
+    }
+    
+    #[test]
+    fn invariants_flag_mismatched_positions_and_overclaims() {
+        let mut state = state_with_backers(2);
+        state.current_funding = 2_000;
+        state.backers.get_mut(&principal(2)).unwrap().total_claimed = 10;
+        
+        let discrepancies = check_invariants(&state);
+        assert_eq!(discrepancies.len(), 2);
+        assert!(matches!(
+            discrepancies[0],
+            Discrepancy::PositionsMismatch { sum_of_positions: 1_960, current_funding: 2_000 }
+        ));
+        assert!(matches!(
+            discrepancies[1],
+            Discrepancy::ClaimsExceedEntitlement { claimed: 10, entitlement: 0, .. }
+        ));
+    }
+    
+    #[test]
+    fn backer_statement_totals_only_the_backers_events_in_range() {
+        let (backer, other) = (principal(1), principal(2));
+        record_event_at(backer, VaultEventKind::Investment { backer, amount: 1_000, insurance_fee: 20, share_percentage: 0.1 }, 5);
+        record_event_at(other, VaultEventKind::Investment { backer: other, amount: 500, insurance_fee: 10, share_percentage: 0.05 }, 6);
+        record_event_at(principal(3), VaultEventKind::EntitlementsCredited {
+            source: CreditSource::Revenue { report_id: 0 },
+            credits: vec![(backer, 70), (other, 30)],
+        }, 10);
+        record_event_at(backer, VaultEventKind::InsuranceClaim {
+            claim_id: 0,
+            claimer: backer,
+            amount: 400,
+            status: ClaimStatus::Approved,
+            block_index: None,
+            notes: None,
+        }, 12);
+        record_event_at(backer, VaultEventKind::InsuranceClaim {
+            claim_id: 0,
+            claimer: backer,
+            amount: 400,
+            status: ClaimStatus::Paid,
+            block_index: Some(77),
+            notes: None,
+        }, 15);
+        record_event_at(principal(9), VaultEventKind::Refund { backer, amount: 300, block_index: 78 }, 20);
+        
+        let statement = build_backer_statement(backer, 0, 20, 30);
+        assert_eq!((statement.invested, statement.insurance_fees, statement.entitlements_accrued), (1_000, 20, 70));
+        assert_eq!((statement.insurance_payouts, statement.refunds), (400, 0));
+        let lines: Vec<(u64, u64, Option<u64>)> = statement.lines.iter()
+            .map(|l| (l.event_id, l.amount, l.ledger_block_index))
+            .collect();
+        assert_eq!(lines, vec![(0, 1_000, None), (2, 70, None), (4, 400, Some(77))]);
+        
+        // The range start is inclusive, its end exclusive
+        let later = build_backer_statement(backer, 15, 21, 30);
+        assert_eq!((later.insurance_payouts, later.refunds, later.invested), (400, 300, 0));
+    }
+    
+    #[test]
+    fn statement_csv_lists_lines_then_totals() {
+        let backer = principal(1);
+        record_event_at(backer, VaultEventKind::Investment { backer, amount: 1_000, insurance_fee: 20, share_percentage: 0.1 }, 5);
+        record_event_at(principal(9), VaultEventKind::Refund { backer, amount: 300, block_index: 78 }, 20);
+        
+        let csv = statement_csv(&build_backer_statement(backer, 0, 100, 100));
+        let rows: Vec<&str> = csv.lines().collect();
+        assert_eq!(rows[0], "event_id,timestamp,type,amount,ledger_block_index");
+        assert_eq!(rows[1], "0,5,Investment,1000,");
+        assert_eq!(rows[2], "1,20,Refund,300,78");
+        assert_eq!(rows[3], ",,TotalInvested,1000,");
+        assert_eq!(rows[4], ",,TotalInsuranceFees,20,");
+        assert_eq!(rows.last(), Some(&",,TotalRefunds,300,"));
+        assert_eq!(rows.len(), 9);
+    }
+    
+    fn pending_investment(investment_id: u64, backer: Principal, amount: u64, confirms_at: u64) -> PendingInvestment {
+        PendingInvestment {
+            investment_id,
+            backer,
+            amount,
+            insurance_fee: amount / 50,
+            insured_by_pool: false,
+            token: None,
+            token_amount: amount,
+            invested_at: 0,
+            confirms_at,
+        }
+    }
+    
+    #[test]
+    fn investment_can_be_cancelled_until_its_cooling_off_window_ends() {
+        let (backer, window_end) = (principal(1), 48 * NANOS_PER_HOUR);
+        let mut state = default_vault_state(0);
+        state.pending_investments.push(pending_investment(1, backer, 1_000, window_end));
+        state.pending_funding = 980;
+        
+        assert!(book_investment_refund(&mut state, 1, principal(2), 10).is_err());
+        assert!(book_investment_refund(&mut state, 1, backer, window_end).is_err());
+        
+        // Refunded in full, insurance fee included, and no longer holding its place against the goal
+        let payout_id = book_investment_refund(&mut state, 1, backer, 10).unwrap();
+        assert_eq!((state.pending_investments.len(), state.pending_funding), (0, 0));
+        assert_eq!((state.pending_payouts[0].to, state.pending_payouts[0].amount), (backer, 1_000));
+        assert!(book_investment_refund(&mut state, 1, backer, 10).is_err());
+        
+        // A refund that did not go through puts the investment back
+        finish_payout(&mut state, payout_id, None, backer, 20);
+        assert_eq!((state.pending_investments.len(), state.pending_funding), (1, 980));
+        
+        let payout_id = book_investment_refund(&mut state, 1, backer, 30).unwrap();
+        finish_payout(&mut state, payout_id, Some(5), backer, 40);
+        assert!(state.pending_investments.is_empty() && state.pending_payouts.is_empty());
+        assert!(state.backers.is_empty());
+    }
+    
+    #[test]
+    fn matured_investments_are_confirmed_into_positions() {
+        let (backer, window_end) = (principal(1), 48 * NANOS_PER_HOUR);
+        let mut state = default_vault_state(0);
+        state.pending_investments.push(pending_investment(1, backer, 1_000, window_end));
+        state.pending_investments.push(pending_investment(2, backer, 500, 2 * window_end));
+        state.pending_funding = 980 + 490;
+        
+        assert_eq!(confirm_matured(&mut state, window_end - 1, principal(9)), 0);
+        assert_eq!(confirm_matured(&mut state, window_end, principal(9)), 1);
+        assert_eq!((state.pending_funding, state.current_funding, state.insurance_pool_balance), (490, 980, 20));
+        let info = &state.backers[&backer];
+        assert_eq!((info.amount_invested, info.principal), (1_000, 980));
+        
+        // A repeat investment adds to the position
+        assert_eq!(confirm_matured(&mut state, 2 * window_end, principal(9)), 1);
+        assert_eq!((state.backers[&backer].principal, state.pending_funding), (1_470, 0));
+    }
+    
+    fn policy() -> EligibilityPolicy {
+        EligibilityPolicy {
+            min_investment: None,
+            max_investment_per_backer: None,
+            max_share_percentage: None,
+            allowlist: None,
+            attestation: None,
+        }
+    }
+    
+    #[test]
+    fn eligibility_applies_allowlist_minimum_and_caps_including_pending_investments() {
+        let backer = principal(1);
+        let mut state = state_with_backer(backer, 0);
+        state.funding_goal = 10_000;
+        assert!(check_eligibility(&state, principal(2), 1, 1, 0).is_ok());
+        
+        state.eligibility_policy = Some(EligibilityPolicy { allowlist: Some(vec![backer]), min_investment: Some(100), ..policy() });
+        assert!(matches!(check_eligibility(&state, principal(2), 500, 490, 0), Err(EligibilityRejection::NotAllowlisted)));
+        assert!(matches!(check_eligibility(&state, backer, 99, 97, 0), Err(EligibilityRejection::BelowMinimum { minimum: 100, amount: 99 })));
+        assert!(check_eligibility(&state, backer, 100, 98, 0).is_ok());
+        
+        // 1000 invested and 500 still cooling off count against the 2000 maximum
+        state.pending_investments.push(pending_investment(1, backer, 500, 10));
+        state.eligibility_policy = Some(EligibilityPolicy { max_investment_per_backer: Some(2_000), ..policy() });
+        assert!(check_eligibility(&state, backer, 500, 490, 0).is_ok());
+        assert!(matches!(
+            check_eligibility(&state, backer, 501, 491, 0),
+            Err(EligibilityRejection::AboveBackerMaximum { maximum: 2_000, already_invested: 1_500, amount: 501 })
+        ));
+        
+        // 980 held and 490 pending leave 30 under a 15% share of 10000
+        state.eligibility_policy = Some(EligibilityPolicy { max_share_percentage: Some(15), ..policy() });
+        assert!(check_eligibility(&state, backer, 40, 30, 0).is_ok());
+        assert!(matches!(
+            check_eligibility(&state, backer, 40, 31, 0),
+            Err(EligibilityRejection::ConcentrationCapExceeded { max_share_percentage: 15, .. })
+        ));
+    }
+    
+    #[test]
+    fn eligibility_requires_a_current_attestation_from_an_allowed_jurisdiction() {
+        let (backer, issuer) = (principal(1), principal(6));
+        let mut state = default_vault_state(0);
+        state.eligibility_policy = Some(EligibilityPolicy {
+            attestation: Some(AttestationRequirement {
+                issuer,
+                credential_type: "kyc".to_string(),
+                allowed_jurisdictions: vec!["CH".to_string(), "DE".to_string()],
+            }),
+            ..policy()
+        });
+        let attestation = |issuer: Principal, jurisdiction: &str| VerifiedAttestation {
+            issuer,
+            credential_type: "kyc".to_string(),
+            jurisdiction: jurisdiction.to_string(),
+            expires_at: 100,
+            verified_at: 0,
+        };
+        
+        assert!(matches!(check_eligibility(&state, backer, 10, 10, 0), Err(EligibilityRejection::AttestationMissing { .. })));
+        state.backer_attestations.insert(backer, attestation(principal(7), "CH"));
+        assert!(matches!(check_eligibility(&state, backer, 10, 10, 0), Err(EligibilityRejection::AttestationMissing { .. })));
+        
+        state.backer_attestations.insert(backer, attestation(issuer, "US"));
+        assert!(matches!(check_eligibility(&state, backer, 10, 10, 0), Err(EligibilityRejection::JurisdictionNotAllowed { .. })));
+        
+        state.backer_attestations.insert(backer, attestation(issuer, "CH"));
+        assert!(check_eligibility(&state, backer, 10, 10, 99).is_ok());
+        assert!(matches!(
+            check_eligibility(&state, backer, 10, 10, 100),
+            Err(EligibilityRejection::AttestationExpired { expired_at: 100 })
+        ));
+    }
+    
+    fn token_state(base_decimals: u8, token_decimals: u8) -> VaultState {
+        let mut state = default_vault_state(0);
+        state.ledger_canister = Some(principal(8));
+        state.accepted_tokens = vec![
+            AcceptedToken { ledger: principal(8), symbol: "USDC".to_string(), decimals: base_decimals },
+            AcceptedToken { ledger: principal(7), symbol: "BTC".to_string(), decimals: token_decimals },
+        ];
+        state
+    }
+    
+    fn rate(rate: u64, rate_decimals: u32, fetched_at: u64) -> TokenRate {
+        TokenRate { ledger: principal(7), rate, rate_decimals, fetched_at }
+    }
+    
+    #[test]
+    fn token_amounts_convert_to_base_value_across_decimals() {
+        // One BTC (8 decimals) at 60000 USDC (6 decimals), quoted with 9 rate decimals
+        let state = token_state(6, 8);
+        let btc_rate = rate(60_000 * 1_000_000_000, 9, 0);
+        assert_eq!(to_base_value(&state, principal(7), 100_000_000, &btc_rate), Ok(60_000 * 1_000_000));
+        assert_eq!(to_base_value(&state, principal(7), 1_000, &btc_rate), Ok(600_000));
+        // Below one base unit rounds down
+        assert_eq!(to_base_value(&state, principal(7), 1, &btc_rate), Ok(600));
+        
+        // A base token with more decimals scales up, and overflow is an error rather than a wrap
+        let state = token_state(18, 0);
+        assert_eq!(to_base_value(&state, principal(7), 5, &rate(2, 0, 0)), Ok(10_000_000_000_000_000_000));
+        assert!(to_base_value(&state, principal(7), 10, &rate(2, 0, 0)).is_err());
+        
+        assert!(to_base_value(&state, principal(8), 5, &rate(2, 0, 0)).is_err());
+        assert!(to_base_value(&state, principal(6), 5, &rate(2, 0, 0)).is_err());
+        let mut unconfigured = token_state(6, 8);
+        unconfigured.ledger_canister = None;
+        assert!(to_base_value(&unconfigured, principal(7), 5, &btc_rate).is_err());
+    }
+    
+    #[test]
+    fn cached_rates_expire_and_positions_convert_back_at_the_invested_rate() {
+        let mut state = token_state(6, 8);
+        state.token_rates.push(rate(60_000, 0, 0));
+        assert!(cached_rate(&state, principal(7), 60, 60 * 1_000_000_000).is_some());
+        assert!(cached_rate(&state, principal(7), 60, 60 * 1_000_000_000 + 1).is_none());
+        assert!(cached_rate(&state, principal(6), 60, 0).is_none());
+        
+        // 1000 base units were invested as 50 token units
+        let mut info = state_with_backer(principal(1), 0).backers[&principal(1)].clone();
+        assert_eq!(position_amount(&info, 500), (None, 500));
+        info.token = Some(principal(7));
+        info.token_amount = 50;
+        assert_eq!(position_amount(&info, 500), (Some(principal(7)), 25));
+        assert_eq!(position_amount(&info, 10), (Some(principal(7)), 0));
+    }
+    
+    #[test]
+    fn reinvested_revenue_buys_new_shares_while_the_goal_is_open() {
+        let mut state = state_with_backers(2);
+        state.funding_goal = 10_000;
+        state.current_funding = 1_960;
+        revenue_fund(&mut state, None).deposited = 1_000;
+        for id in 1..=2 {
+            let info = state.backers.get_mut(&principal(id)).unwrap();
+            info.revenue_entitlement = 300;
+            info.auto_reinvest = true;
+        }
+        // Positions held in another token are paid in that token, never reinvested
+        state.backers.get_mut(&principal(2)).unwrap().token = Some(principal(7));
+        
+        reinvest_entitlements(&mut state, principal(9), 10);
+        
+        // 300 at par, 2% of it to the insurance pool
+        let info = &state.backers[&principal(1)];
+        assert_eq!((info.total_claimed, info.reinvestment_balance, info.principal, info.amount_invested), (300, 0, 1_274, 1_300));
+        assert_eq!((state.current_funding, state.reinvestment_pool, state.insurance_pool_balance), (2_254, 0, 6));
+        assert_eq!(revenue_available(&state, None), 700);
+        assert_eq!(state.backers[&principal(2)].total_claimed, 0);
+    }
+    
+    #[test]
+    fn reinvestment_buys_sell_orders_once_the_goal_is_met_and_pools_the_rest() {
+        let mut state = state_with_backers(2);
+        state.funding_goal = 1_960;
+        state.current_funding = 1_960;
+        revenue_fund(&mut state, None).deposited = 1_000;
+        let buyer = state.backers.get_mut(&principal(1)).unwrap();
+        buyer.revenue_entitlement = 300;
+        buyer.auto_reinvest = true;
+        state.backers.get_mut(&principal(2)).unwrap().sell_order = 200;
+        
+        reinvest_entitlements(&mut state, principal(9), 10);
+        
+        let (buyer, seller) = (&state.backers[&principal(1)], &state.backers[&principal(2)]);
+        assert_eq!((buyer.principal, buyer.reinvestment_balance), (1_180, 100));
+        assert_eq!((seller.principal, seller.sell_order, seller.sale_proceeds), (780, 0, 200));
+        assert_eq!((state.current_funding, state.reinvestment_pool), (1_960, 100));
+        
+        // A closing vault pays the pooled balance out instead
+        state.vault_status = VaultStatus::Settling;
+        reinvest_entitlements(&mut state, principal(9), 20);
+        let buyer = &state.backers[&principal(1)];
+        assert_eq!((buyer.total_claimed, buyer.reinvestment_balance, state.reinvestment_pool), (200, 0, 0));
+        assert_eq!(base_claimable(buyer), 100);
+    }
+}